@@ -1,5 +1,5 @@
 use crate::objects::BoundingVolume;
-use crate::{Hittable, HittableList, Ray, Vec3, vec3};
+use crate::{Hittable, HittableList, Ray, vec3};
 use crate::traits::HitData;
 
 pub struct BvhNode {
@@ -8,6 +8,12 @@ pub struct BvhNode {
     pub children: Vec<BvhNode>
 }
 
+impl Default for BvhNode {
+    fn default() -> Self {
+        BvhNode::new()
+    }
+}
+
 impl BvhNode {
     pub fn new() -> Self {
         BvhNode {
@@ -98,7 +104,7 @@ impl BvhNode {
         let mut closest_hit = HitData::new();
 
         // If this node's volume was hit and it has children, check the child nodes for hits
-        if did_hit && self.children.len() > 0 {
+        if did_hit && !self.children.is_empty() {
             // Find the direction the ray points in the most
             let mut axis: usize = 0;
             let mut highest_dir: Option<f64> = None;
@@ -117,7 +123,7 @@ impl BvhNode {
             // Hacky way of sorting child BVH nodes, not great. Making self mutable is too much work for now, so this is good enough.
             let mut vols = vec![];
             for child in self.children.iter() {
-                vols.push(child.volume.clone());
+                vols.push(child.volume);
             }
             let mut vol_clone = vols.clone();
             BoundingVolume::sort_by_axis(&mut vol_clone, axis as u8);
@@ -146,7 +152,7 @@ impl BvhNode {
 
 
         // If this a leaf node (no child nodes) then test the objects in this node for intersection.
-        if did_hit && self.children.len() == 0 {
+        if did_hit && self.children.is_empty() {
             closest_hit = self.objects.hit(ray, t_min, t_max);
         }
 
@@ -0,0 +1,158 @@
+use crate::{Vec3, vec3};
+use crate::data_structures::Vector;
+
+// Anything with a position that can be stored in a KdTree.
+pub trait KdPoint {
+    fn get_position(&self) -> Vec3;
+}
+
+// Balanced kd-tree for quickly finding all points near a position.
+// The tree is kept in one flat list. Each part of the list has its median point in the middle, split along the axis
+// its points spread out the most on, with the points below it on that axis before it and the rest after it.
+pub struct KdTree<T> {
+    points: Vec<T>,
+    axes: Vec<usize> // Axis each point splits its part of the list on
+}
+
+impl<T: KdPoint> KdTree<T> {
+    pub fn new(points: Vec<T>) -> Self {
+        let mut tree = KdTree { axes: vec![0; points.len()], points };
+        tree.build(0, tree.points.len());
+        tree
+    }
+
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    // Calls found with every point within radius of the position, and its squared distance from it.
+    pub fn for_each_within(&self, position: Vec3, radius: f64, found: &mut impl FnMut(&T, f64)) {
+        self.search(0, self.points.len(), position, radius * radius, found);
+    }
+
+    fn build(&mut self, start: usize, end: usize) {
+        if end - start <= 1 {
+            return;
+        }
+        let mut min = self.points[start].get_position();
+        let mut max = min;
+        for point in &self.points[start + 1..end] {
+            let p = point.get_position();
+            min = vec3![min.x.min(p.x), min.y.min(p.y), min.z.min(p.z)];
+            max = vec3![max.x.max(p.x), max.y.max(p.y), max.z.max(p.z)];
+        }
+        let extent = max - min;
+        let axis = if extent.x >= extent.y && extent.x >= extent.z { 0 } else if extent.y >= extent.z { 1 } else { 2 };
+
+        let middle = (start + end) / 2;
+        self.points[start..end].select_nth_unstable_by(middle - start, |a, b| {
+            a.get_position()[axis].total_cmp(&b.get_position()[axis])
+        });
+        self.axes[middle] = axis;
+        self.build(start, middle);
+        self.build(middle + 1, end);
+    }
+
+    fn search(&self, start: usize, end: usize, position: Vec3, radius_squared: f64, found: &mut impl FnMut(&T, f64)) {
+        if start >= end {
+            return;
+        }
+        let middle = (start + end) / 2;
+        let point = &self.points[middle];
+        let offset = position - point.get_position();
+        let distance_squared = offset.dot(offset);
+        if distance_squared <= radius_squared {
+            found(point, distance_squared);
+        }
+
+        // Look on the side of the split the position is on first, and only on the other side if the radius reaches over
+        let along_axis = offset[self.axes[middle]];
+        let (near, far) = if along_axis < 0.0 { ((start, middle), (middle + 1, end)) } else { ((middle + 1, end), (start, middle)) };
+        self.search(near.0, near.1, position, radius_squared, found);
+        if along_axis * along_axis <= radius_squared {
+            self.search(far.0, far.1, position, radius_squared, found);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+    use super::*;
+
+    struct Point {
+        id: usize,
+        position: Vec3
+    }
+
+    impl KdPoint for Point {
+        fn get_position(&self) -> Vec3 {
+            self.position
+        }
+    }
+
+    fn tree_of(positions: &[Vec3]) -> KdTree<Point> {
+        KdTree::new(positions.iter().enumerate().map(|(id, position)| Point { id, position: *position }).collect())
+    }
+
+    // Ids of the points the tree finds, checking the distances it gives along the way.
+    fn found_ids(tree: &KdTree<Point>, position: Vec3, radius: f64) -> Vec<usize> {
+        let mut ids = vec![];
+        tree.for_each_within(position, radius, &mut |point, distance_squared| {
+            let offset = point.position - position;
+            assert!((offset.dot(offset) - distance_squared).abs() < 1e-12);
+            ids.push(point.id);
+        });
+        ids.sort_unstable();
+        ids
+    }
+
+    fn brute_force_ids(positions: &[Vec3], position: Vec3, radius: f64) -> Vec<usize> {
+        positions.iter().enumerate()
+            .filter(|(_, p)| (**p - position).dot(**p - position) <= radius * radius)
+            .map(|(id, _)| id)
+            .collect()
+    }
+
+    #[test]
+    fn finds_the_same_points_as_brute_force() {
+        let mut rng = StdRng::seed_from_u64(23);
+        for count in [0, 1, 2, 3, 10, 100, 1000] {
+            // Half the trees are on a coarse grid so lots of points sit right on the splits, or on top of each other
+            for on_grid in [false, true] {
+                let positions: Vec<Vec3> = (0..count).map(|_| {
+                    let mut coordinate = || if on_grid { rng.gen_range(-3..=3) as f64 } else { rng.gen_range(-10.0..10.0) };
+                    vec3![coordinate(), coordinate(), coordinate()]
+                }).collect();
+                let tree = tree_of(&positions);
+                assert_eq!(tree.len(), count);
+                assert_eq!(tree.is_empty(), count == 0);
+
+                for _ in 0..50 {
+                    let position = vec3![rng.gen_range(-12.0..12.0), rng.gen_range(-12.0..12.0), rng.gen_range(-12.0..12.0)];
+                    let radius = rng.gen_range(0.0..8.0);
+                    assert_eq!(found_ids(&tree, position, radius), brute_force_ids(&positions, position, radius),
+                        "{} points, around {:?} with radius {}", count, position, radius);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn empty_and_single_point_trees() {
+        let empty = tree_of(&[]);
+        assert!(empty.is_empty());
+        assert!(found_ids(&empty, vec3![0.0, 0.0, 0.0], f64::INFINITY).is_empty());
+
+        let single = tree_of(&[vec3![1.0, 2.0, 3.0]]);
+        assert_eq!(single.len(), 1);
+        assert_eq!(found_ids(&single, vec3![1.0, 2.0, 3.0], 0.0), vec![0]);
+        assert_eq!(found_ids(&single, vec3![1.0, 2.0, 5.0], 2.0), vec![0]);
+        assert!(found_ids(&single, vec3![1.0, 2.0, 5.0], 1.999).is_empty());
+    }
+}
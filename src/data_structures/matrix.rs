@@ -15,15 +15,21 @@ pub struct Matrix4 {
 macro_rules! matrix4 {
     ( $( $x:expr ),* ) => {
         {
-            let mut new_matrix = Matrix4::new();
+            let mut new_matrix = $crate::data_structures::Matrix4::new();
             $(
-                new_matrix.push(Vec4::from($x.to_vec()));
+                new_matrix.push($crate::data_structures::Vec4::from($x.to_vec()));
             )*
             new_matrix
         }
     };
 }
 
+impl Default for Matrix4 {
+    fn default() -> Self {
+        Matrix4::new()
+    }
+}
+
 impl Matrix4 {
     pub fn new() -> Self {
         Matrix4 { data: vec![] }
@@ -41,6 +47,10 @@ impl Matrix4 {
         self.data.len()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
     pub fn iter(&self) -> Iter<'_, Vec4> {
         self.data.iter()
     }
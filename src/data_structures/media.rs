@@ -0,0 +1,237 @@
+use crate::{Vec3, vec3};
+
+// Most media a ray can be inside at once, enough for ice in water in a glass. Media entered past this are ignored.
+// Rays are copied around a lot, so this is kept small.
+const MAX_MEDIA: usize = 3;
+
+// What the inside of a transparent object is made of.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Medium {
+    pub refractive_index: f64,
+    // Where objects overlap, the medium with the highest priority is the one the ray is in.
+    // Makes it possible to model e.g. water slightly overlapping the glass holding it.
+    pub priority: u32,
+    // How much light is absorbed per unit of distance travelled, per color channel. 0 for clear media.
+    pub absorption: Vec3
+}
+
+impl Medium {
+    // How much light is left per color channel after travelling some distance through the medium (Beer-Lambert law).
+    pub fn get_transmittance(&self, distance: f64) -> Vec3 {
+        let channel = |absorption: f64| (-absorption * distance).exp();
+        vec3![channel(self.absorption.x), channel(self.absorption.y), channel(self.absorption.z)]
+    }
+}
+
+// A ray going through the surface of an object, from the media it was in before to the ones it's in after.
+#[derive(Debug, Copy, Clone)]
+pub struct Crossing {
+    pub before: MediaStack,
+    pub after: MediaStack,
+    // False when the object is overlapped by a medium with a higher priority. The surface isn't really there then,
+    // and the ray should go on unchanged.
+    pub is_interface: bool
+}
+
+// The media a ray is inside, in the order it went into them. Objects with the same medium count as one,
+// so leaving either of them takes the ray out of the medium.
+#[derive(Debug, Copy, Clone)]
+pub struct MediaStack {
+    media: [Option<Medium>; MAX_MEDIA],
+    len: usize
+}
+
+impl MediaStack {
+    pub fn new() -> Self {
+        MediaStack { media: [None; MAX_MEDIA], len: 0 }
+    }
+
+    // The medium the ray is in right now, the one with the highest priority. Ties go to the one entered last.
+    // None when the ray isn't inside anything.
+    pub fn current(&self) -> Option<Medium> {
+        let mut current: Option<Medium> = None;
+        for medium in self.media[..self.len].iter().flatten() {
+            if current.is_none_or(|c| medium.priority >= c.priority) {
+                current = Some(*medium);
+            }
+        }
+        current
+    }
+
+    // Refractive index of the current medium, or of the space around all objects when the ray isn't inside anything.
+    pub fn get_refractive_index(&self, outside: f64) -> f64 {
+        self.current().map_or(outside, |medium| medium.refractive_index)
+    }
+
+    pub fn contains(&self, medium: Medium) -> bool {
+        self.media[..self.len].contains(&Some(medium))
+    }
+
+    // Go into or out of an object made of a medium. A surface is only an interface if the medium is the current one
+    // on the side of it the ray is in.
+    pub fn cross(&self, medium: Medium, entering: bool) -> Crossing {
+        if entering {
+            let after = self.entered(medium);
+            Crossing { before: *self, after, is_interface: after.current() == Some(medium) }
+        } else {
+            // Rays that started inside the object, like from a camera under water, never went in through a surface
+            let before = if self.contains(medium) { *self } else { self.entered(medium) };
+            Crossing { before, after: before.exited(medium), is_interface: before.current() == Some(medium) }
+        }
+    }
+
+    // Stack after going into a medium.
+    pub fn entered(&self, medium: Medium) -> Self {
+        let mut stack = *self;
+        if stack.len < MAX_MEDIA {
+            stack.media[stack.len] = Some(medium);
+            stack.len += 1;
+        }
+        stack
+    }
+
+    // Stack after leaving a medium. Leaving a medium the ray isn't in changes nothing.
+    pub fn exited(&self, medium: Medium) -> Self {
+        let mut stack = *self;
+        if let Some(i) = stack.media[..stack.len].iter().rposition(|m| *m == Some(medium)) {
+            stack.media.copy_within(i + 1..stack.len, i);
+            stack.len -= 1;
+            stack.media[stack.len] = None;
+        }
+        stack
+    }
+}
+
+impl Default for MediaStack {
+    fn default() -> Self {
+        MediaStack::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn medium(refractive_index: f64, priority: u32) -> Medium {
+        Medium { refractive_index, priority, absorption: vec3![0.0, 0.0, 0.0] }
+    }
+
+    fn index_of(stack: &MediaStack) -> f64 {
+        stack.get_refractive_index(1.0)
+    }
+
+    #[test]
+    fn ice_in_water_in_glass() {
+        let (glass, water, ice) = (medium(1.5, 0), medium(1.33, 0), medium(1.31, 0));
+        let mut stack = MediaStack::new();
+        assert_eq!(stack.current(), None);
+
+        // Going in, every surface is an interface from the outer medium to the inner one
+        let mut indices = vec![];
+        for m in [glass, water, ice] {
+            let crossing = stack.cross(m, true);
+            assert!(crossing.is_interface);
+            indices.push((index_of(&crossing.before), index_of(&crossing.after)));
+            stack = crossing.after;
+        }
+        assert_eq!(indices, vec![(1.0, 1.5), (1.5, 1.33), (1.33, 1.31)]);
+        assert_eq!(stack.current(), Some(ice));
+
+        // And coming out it goes back the same way
+        let mut indices = vec![];
+        for m in [ice, water, glass] {
+            let crossing = stack.cross(m, false);
+            assert!(crossing.is_interface);
+            indices.push((index_of(&crossing.before), index_of(&crossing.after)));
+            stack = crossing.after;
+        }
+        assert_eq!(indices, vec![(1.31, 1.33), (1.33, 1.5), (1.5, 1.0)]);
+        assert_eq!(stack.current(), None);
+    }
+
+    #[test]
+    fn overlapping_media_use_priority() {
+        // Water poured into a glass overlaps it a bit. The glass has the higher priority, so the water inside the
+        // glass wall isn't really there.
+        let (glass, water) = (medium(1.5, 2), medium(1.33, 1));
+        let in_glass = MediaStack::new().cross(glass, true).after;
+
+        let into_water = in_glass.cross(water, true);
+        assert!(!into_water.is_interface);
+        assert_eq!(into_water.after.current(), Some(glass));
+
+        // Leaving the glass wall into the water is an interface between the two
+        let out_of_glass = into_water.after.cross(glass, false);
+        assert!(out_of_glass.is_interface);
+        assert_eq!(out_of_glass.after.current(), Some(water));
+        assert!(!out_of_glass.after.contains(glass));
+
+        // Leaving the water while still in the glass isn't
+        let out_of_water = into_water.after.cross(water, false);
+        assert!(!out_of_water.is_interface);
+        assert_eq!(out_of_water.after.current(), Some(glass));
+    }
+
+    #[test]
+    fn equal_priorities_go_to_the_last_entered() {
+        let (a, b) = (medium(1.5, 1), medium(1.2, 1));
+        let stack = MediaStack::new().entered(a).entered(b);
+        assert_eq!(stack.current(), Some(b));
+        assert_eq!(MediaStack::new().entered(b).entered(a).current(), Some(a));
+
+        // Leaving the first one is hidden by the second
+        let crossing = stack.cross(a, false);
+        assert!(!crossing.is_interface);
+        assert_eq!(crossing.after.current(), Some(b));
+
+        // A lower priority entered last doesn't win
+        let low = medium(1.1, 0);
+        let crossing = stack.cross(low, true);
+        assert!(!crossing.is_interface);
+        assert_eq!(crossing.after.current(), Some(b));
+    }
+
+    #[test]
+    fn starting_inside_a_medium() {
+        // A camera under water never went through the surface, but still leaves through it
+        let water = medium(1.33, 0);
+        let crossing = MediaStack::new().cross(water, false);
+        assert!(crossing.is_interface);
+        assert_eq!(crossing.before.current(), Some(water));
+        assert_eq!(crossing.after.current(), None);
+
+        // Leaving a medium the ray isn't in otherwise changes nothing
+        let glass = medium(1.5, 0);
+        let stack = MediaStack::new().entered(glass).exited(water);
+        assert_eq!(stack.current(), Some(glass));
+    }
+
+    #[test]
+    fn media_past_the_limit_are_ignored() {
+        let media: Vec<Medium> = (0..MAX_MEDIA + 2).map(|i| medium(1.1 + 0.1 * i as f64, 0)).collect();
+        let mut stack = MediaStack::new();
+        for m in &media[..MAX_MEDIA] {
+            stack = stack.entered(*m);
+        }
+        assert_eq!(stack.current(), Some(media[MAX_MEDIA - 1]));
+
+        // Surfaces of media that don't fit aren't interfaces going in or out, so the ray stays in the last medium
+        let extra = media[MAX_MEDIA];
+        let crossing = stack.cross(extra, true);
+        assert!(!crossing.is_interface);
+        assert!(!crossing.after.contains(extra));
+        assert_eq!(crossing.after.current(), Some(media[MAX_MEDIA - 1]));
+
+        let crossing = crossing.after.cross(extra, false);
+        assert!(!crossing.is_interface);
+        assert_eq!(crossing.after.current(), Some(media[MAX_MEDIA - 1]));
+
+        // Once there's room again media can be entered
+        let stack = stack.exited(media[0]).entered(media[MAX_MEDIA + 1]);
+        assert_eq!(stack.current(), Some(media[MAX_MEDIA + 1]));
+        assert!(!stack.contains(media[0]));
+        for m in &media[1..MAX_MEDIA] {
+            assert!(stack.contains(*m));
+        }
+    }
+}
@@ -10,8 +10,14 @@ pub use vec4::*;
 mod matrix;
 pub use matrix::*;
 
+mod media;
+pub use media::*;
+
 mod ray;
 pub use ray::*;
 
 mod bvh;
-pub use bvh::*;
\ No newline at end of file
+pub use bvh::*;
+
+mod kd_tree;
+pub use kd_tree::*;
\ No newline at end of file
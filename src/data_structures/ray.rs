@@ -1,18 +1,26 @@
 use crate::data_structures::vec3::Vec3;
-use crate::data_structures::Vector;
+use crate::data_structures::{MediaStack, Vector};
 
 // Simple struct for a ray, represented by where it starts (origin) and where it's going (direction)
+// Rays also keep track of the transparent objects they are inside of, to know what they refract into.
 #[derive(Debug, Copy, Clone)]
 pub struct Ray {
     pub origin: Vec3,
-    pub direction: Vec3
+    pub direction: Vec3,
+    pub media: MediaStack
 }
 
 impl Ray {
+    // A ray that isn't inside any object.
     pub fn new(orig: Vec3, dir: Vec3) -> Self {
+        Ray::new_in_media(orig, dir, MediaStack::new())
+    }
+
+    pub fn new_in_media(orig: Vec3, dir: Vec3, media: MediaStack) -> Self {
         Ray {
             origin: orig,
-            direction: dir
+            direction: dir,
+            media
         }
     }
 
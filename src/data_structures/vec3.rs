@@ -14,7 +14,7 @@ pub struct Vec3 {
 macro_rules! vec3 {
     ($x: expr, $y: expr, $z:expr) => {
         {
-            Vec3 { x: $x, y: $y, z: $z }
+            $crate::data_structures::Vec3 { x: $x, y: $y, z: $z }
         }
     };
 }
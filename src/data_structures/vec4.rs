@@ -15,7 +15,7 @@ pub struct Vec4 {
 macro_rules! vec4 {
     ($x: expr, $y: expr, $z:expr, $w:expr) => {
         {
-            Vec4 { x: $x, y: $y, z: $z, w: $w }
+            $crate::data_structures::Vec4 { x: $x, y: $y, z: $z, w: $w }
         }
     };
 }
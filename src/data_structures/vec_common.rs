@@ -1,5 +1,7 @@
 // Trait for Vec3 and Vec4 to implement
 pub trait Vector {
+    // Zero vector of the same kind. Kept for code that already uses it, even though it takes self.
+    #[allow(clippy::wrong_self_convention)]
     fn new(self) -> Self;
     fn to_vec(self) -> Vec<f64>;
     fn add_v(self, v2: Self) -> Self;
@@ -0,0 +1,17 @@
+// A software raytracer. Scenes can be built up in code or loaded from scene files,
+// then rendered into a list of pixels that can be saved as an image.
+
+pub mod objects;
+pub mod data_structures;
+pub mod traits;
+pub mod materials;
+pub mod utils;
+pub mod loaders;
+
+pub use crate::objects::{SceneObject, WorldLight, Light};
+pub use crate::objects::{Aov, Camera, FovAxis, Integrator, Projection, RenderOutput, Scene};
+pub use crate::data_structures::{Ray, Vec3, Vector};
+pub use crate::materials::Material;
+pub use crate::traits::{Hittable, HittableList};
+pub use crate::loaders::load_scene;
+pub use crate::utils::{save_exr, save_png};
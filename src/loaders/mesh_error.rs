@@ -0,0 +1,114 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::path::{Path, PathBuf};
+use crate::objects::InvalidFaceError;
+
+// What went wrong while loading a mesh file.
+#[derive(Debug)]
+pub enum MeshErrorKind {
+    Io(io::Error),
+    InvalidNumber(String),
+    MissingValues { expected: usize, found: usize },
+    TooFewFaceVertices(usize),
+    // Index as written in the file, and how many elements of that kind there are.
+    IndexOutOfRange { attribute: &'static str, index: i64, count: usize },
+}
+
+// Error produced when a mesh file can't be read or parsed. Names the file and, when known, the line.
+#[derive(Debug)]
+pub struct MeshLoadError {
+    pub path: PathBuf,
+    pub line: Option<usize>,
+    pub kind: MeshErrorKind,
+}
+
+impl MeshLoadError {
+    pub fn new(path: &Path, line: Option<usize>, kind: MeshErrorKind) -> Self {
+        MeshLoadError { path: path.to_path_buf(), line, kind }
+    }
+
+    pub fn io(path: &Path, error: io::Error) -> Self {
+        MeshLoadError::new(path, None, MeshErrorKind::Io(error))
+    }
+
+    // Turn an error from building a mesh into a load error, given the line each face came from.
+    pub fn from_invalid_face(path: &Path, face_lines: &[usize], error: InvalidFaceError) -> Self {
+        MeshLoadError::new(path, face_lines.get(error.face).copied(), MeshErrorKind::IndexOutOfRange {
+            attribute: error.attribute,
+            index: error.index as i64 + 1,
+            count: error.count,
+        })
+    }
+}
+
+impl Display for MeshErrorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MeshErrorKind::Io(e) => write!(f, "{}", e),
+            MeshErrorKind::InvalidNumber(s) => write!(f, "`{}` is not a valid number", s),
+            MeshErrorKind::MissingValues { expected, found } => write!(f, "expected {} values but found {}", expected, found),
+            MeshErrorKind::TooFewFaceVertices(n) => write!(f, "face has {} vertices but needs at least 3", n),
+            MeshErrorKind::IndexOutOfRange { attribute, index, count } => {
+                write!(f, "{} index {} is out of range, only {} defined", attribute, index, count)
+            }
+        }
+    }
+}
+
+impl Display for MeshLoadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.path.display())?;
+        if let Some(line) = self.line {
+            write!(f, ":{}", line)?;
+        }
+        write!(f, ": {}", self.kind)
+    }
+}
+
+impl Error for MeshLoadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match &self.kind {
+            MeshErrorKind::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+// Parse a number from a mesh file.
+pub(crate) fn parse_number<T: std::str::FromStr>(s: &str) -> Result<T, MeshErrorKind> {
+    s.parse().map_err(|_| MeshErrorKind::InvalidNumber(s.to_string()))
+}
+
+// Parse the first count values of a line as numbers.
+pub(crate) fn parse_numbers(args: &[&str], count: usize) -> Result<Vec<f64>, MeshErrorKind> {
+    if args.len() < count {
+        return Err(MeshErrorKind::MissingValues { expected: count, found: args.len() });
+    }
+    args[..count].iter().map(|s| parse_number(s)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_numbers_checks_count_and_values() {
+        assert_eq!(parse_numbers(&["1", "-2.5", "3e2", "ignored"], 3).unwrap(), vec![1.0, -2.5, 300.0]);
+        assert!(matches!(parse_numbers(&["1", "2"], 3), Err(MeshErrorKind::MissingValues { expected: 3, found: 2 })));
+        assert!(matches!(parse_numbers(&["1", "x", "3"], 3), Err(MeshErrorKind::InvalidNumber(s)) if s == "x"));
+        assert!(matches!(parse_number::<i64>("1.5"), Err(MeshErrorKind::InvalidNumber(_))));
+    }
+
+    #[test]
+    fn invalid_faces_point_at_their_line() {
+        let error = InvalidFaceError { face: 2, attribute: "normal", index: 7, count: 4 };
+        let load_error = MeshLoadError::from_invalid_face(Path::new("mesh.obj"), &[3, 5, 5, 9], error);
+        assert_eq!(load_error.line, Some(5));
+        // Indices are shown 1-based like in the file
+        assert_eq!(load_error.to_string(), "mesh.obj:5: normal index 8 is out of range, only 4 defined");
+
+        let no_line = MeshLoadError::new(Path::new("mesh.smf"), None, MeshErrorKind::TooFewFaceVertices(1));
+        assert_eq!(no_line.to_string(), "mesh.smf: face has 1 vertices but needs at least 3");
+    }
+}
@@ -0,0 +1,11 @@
+mod mesh_error;
+pub use mesh_error::*;
+
+mod smf;
+pub use smf::*;
+
+mod obj;
+pub use obj::*;
+
+mod scene_file;
+pub use scene_file::*;
@@ -0,0 +1,432 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use crate::{Material, SceneObject, Vec3, vec3};
+use crate::loaders::{MeshErrorKind, MeshLoadError, parse_number, parse_numbers};
+use crate::materials::{Dielectric, Emissive, Flat, Hall, Phong};
+use crate::objects::{MeshData, MeshFace, TriangleMesh};
+
+// Faces that share the same group and material end up in the same mesh.
+struct ObjGroup {
+    name: String,
+    material: Option<String>,
+    faces: Vec<MeshFace>,
+    face_lines: Vec<usize> // Line each face came from for error messages
+}
+
+// Loads a Wavefront OBJ file. Every group (`o` or `g`) becomes its own mesh, and a group is split up further
+// whenever `usemtl` switches to a different material. Materials from `mtllib` files are mapped onto Flat, Phong,
+// Hall, Dielectric or Emissive materials. Groups without `usemtl`, or whose material isn't in any MTL file, get
+// default_material. Polygons with more than 3 vertices are split into a fan of triangles.
+pub fn load_obj_meshes<P: AsRef<Path>>(filename: P, smooth: bool, default_material: Material) -> Result<Vec<TriangleMesh>, MeshLoadError> {
+    let path = filename.as_ref();
+    let text = fs::read_to_string(path).map_err(|e| MeshLoadError::io(path, e))?;
+    let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+
+    let mut data = MeshData::default();
+    let mut materials = HashMap::new();
+    let mut groups: Vec<ObjGroup> = vec![];
+    let mut current_name = String::new();
+    let mut current_material: Option<String> = None;
+    let mut current_group: Option<usize> = None; // Index into groups that faces are being added to
+
+    for (line_i, line) in text.lines().enumerate() {
+        let line_num = line_i + 1;
+        let error = |kind| MeshLoadError::new(path, Some(line_num), kind);
+
+        let mut tokens = line.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(k) if !k.starts_with('#') => k,
+            _ => continue
+        };
+        let args: Vec<&str> = tokens.collect();
+
+        match keyword {
+            "v" => data.vertices.push(parse_vec3(&args).map_err(error)?),
+            "vn" => data.normals.push(parse_vec3(&args).map_err(error)?),
+            "vt" => {
+                // Texture coordinates only need u and v. w is optional and ignored.
+                let u = parse_numbers(&args, 1).map_err(error)?[0];
+                let v = match args.get(1) {
+                    Some(v) => parse_number(v).map_err(error)?,
+                    None => 0.0
+                };
+                data.uvs.push(vec3![u, v, 0.0]);
+            },
+            "f" => {
+                let corners = args.iter()
+                    .map(|corner| parse_face_corner(corner, &data))
+                    .collect::<Result<Vec<(usize, Option<usize>, Option<usize>)>, MeshErrorKind>>()
+                    .map_err(error)?;
+                if corners.len() < 3 {
+                    return Err(error(MeshErrorKind::TooFewFaceVertices(corners.len())));
+                }
+
+                // Find or start the group for the current group name and material
+                let group_i = *current_group.get_or_insert_with(|| {
+                    groups.iter()
+                        .position(|g| g.name == current_name && g.material == current_material)
+                        .unwrap_or_else(|| {
+                            groups.push(ObjGroup {
+                                name: current_name.clone(),
+                                material: current_material.clone(),
+                                faces: vec![],
+                                face_lines: vec![]
+                            });
+                            groups.len() - 1
+                        })
+                });
+
+                // Fan triangulate the polygon around its first corner
+                for i in 1..corners.len() - 1 {
+                    let tri = [corners[0], corners[i], corners[i + 1]];
+                    groups[group_i].faces.push(MeshFace {
+                        vertices: [tri[0].0, tri[1].0, tri[2].0],
+                        uvs: match (tri[0].1, tri[1].1, tri[2].1) {
+                            (Some(a), Some(b), Some(c)) => Some([a, b, c]),
+                            _ => None
+                        },
+                        normals: match (tri[0].2, tri[1].2, tri[2].2) {
+                            (Some(a), Some(b), Some(c)) => Some([a, b, c]),
+                            _ => None
+                        }
+                    });
+                    groups[group_i].face_lines.push(line_num);
+                }
+            },
+            "o" | "g" => {
+                current_name = args.join(" ");
+                current_group = None;
+            },
+            "usemtl" => {
+                current_material = Some(args.join(" "));
+                current_group = None;
+            },
+            "mtllib" => {
+                for lib in args.iter() {
+                    let lib_path = base_dir.join(lib);
+                    materials.extend(load_mtl_materials(&lib_path)?);
+                }
+            },
+            // Smoothing groups, lines, points etc. aren't supported
+            _ => {}
+        }
+    }
+
+    let mut meshes = vec![];
+    for group in groups {
+        // All groups share the same vertex data, only the faces differ.
+        data.faces = group.faces;
+        let mut mesh = TriangleMesh::from_data(&data, smooth)
+            .map_err(|e| MeshLoadError::from_invalid_face(path, &group.face_lines, e))?;
+        mesh.set_material(group.material.and_then(|name| materials.get(&name).copied()).unwrap_or(default_material));
+        meshes.push(mesh);
+    }
+    Ok(meshes)
+}
+
+// Loads all materials defined in an MTL file, keyed by their name.
+//
+// The illumination model decides which material is used:
+// illum 0 -> Flat, illum 1 and 2 -> Phong, illum 7 -> Dielectric, other reflective (3, 5) or transparent materials -> Hall.
+// Materials with an emission color (Ke) glow instead.
+pub fn load_mtl_materials<P: AsRef<Path>>(filename: P) -> Result<HashMap<String, Material>, MeshLoadError> {
+    let path = filename.as_ref();
+    let text = fs::read_to_string(path).map_err(|e| MeshLoadError::io(path, e))?;
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, MtlProperties)> = None;
+
+    for (line_i, line) in text.lines().enumerate() {
+        let error = |kind| MeshLoadError::new(path, Some(line_i + 1), kind);
+        let mut tokens = line.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(k) if !k.starts_with('#') => k,
+            _ => continue
+        };
+        let args: Vec<&str> = tokens.collect();
+
+        if keyword == "newmtl" {
+            if let Some((name, props)) = current.take() {
+                materials.insert(name, props.to_material());
+            }
+            current = Some((args.join(" "), MtlProperties::default()));
+            continue;
+        }
+
+        if let Some((_, props)) = current.as_mut() {
+            let first = || parse_numbers(&args, 1).map(|v| v[0]);
+            match keyword {
+                "Kd" => props.diffuse = parse_vec3(&args).map_err(error)?,
+                "Ks" => props.specular = parse_vec3(&args).map_err(error)?,
+                "Ke" => props.emission = parse_vec3(&args).map_err(error)?,
+                "Ka" => props.ambient = Some(parse_vec3(&args).map_err(error)?),
+                "Tf" => props.transmission = parse_vec3(&args).map_err(error)?,
+                "Ns" => props.shininess = first().map_err(error)?,
+                "Ni" => props.refractive_index = first().map_err(error)?,
+                "d" => props.dissolve = first().map_err(error)?,
+                "Tr" => props.dissolve = 1.0 - first().map_err(error)?,
+                "illum" => props.illum = first().map_err(error)? as u32,
+                _ => {}
+            }
+        }
+    }
+    if let Some((name, props)) = current {
+        materials.insert(name, props.to_material());
+    }
+    Ok(materials)
+}
+
+// Material properties as written in an MTL file. Colors are in the 0-1 range.
+struct MtlProperties {
+    diffuse: Vec3,
+    specular: Vec3,
+    ambient: Option<Vec3>,
+    emission: Vec3,
+    transmission: Vec3,
+    shininess: f64,
+    refractive_index: f64,
+    dissolve: f64,
+    illum: u32
+}
+
+impl Default for MtlProperties {
+    fn default() -> Self {
+        MtlProperties {
+            diffuse: vec3![0.8, 0.8, 0.8],
+            specular: vec3![0.0, 0.0, 0.0],
+            ambient: None,
+            emission: vec3![0.0, 0.0, 0.0],
+            transmission: vec3![1.0, 1.0, 1.0],
+            shininess: 10.0,
+            refractive_index: 1.0,
+            dissolve: 1.0,
+            illum: 2
+        }
+    }
+}
+
+impl MtlProperties {
+    fn to_material(&self) -> Material {
+        let d_color = self.diffuse * 255.0;
+
+        // Emission can be brighter than 1, which becomes the intensity
+        let emission_strength = self.emission.x.max(self.emission.y).max(self.emission.z);
+        if emission_strength > 0.0 {
+            return Material::Emissive(Emissive::new(self.emission / emission_strength * 255.0, emission_strength));
+        }
+        if self.illum == 0 {
+            return Material::Flat(Flat::new(d_color));
+        }
+        // Illumination model 7 is refraction with Fresnel, which is exactly a dielectric tinted by the transmission filter.
+        if self.illum == 7 {
+            let mut dielectric = Dielectric::new(self.refractive_index);
+            dielectric.set_absorption(self.transmission * 255.0, 1.0);
+            return Material::Dielectric(dielectric);
+        }
+
+        // The strength of the specular color becomes the specular factor, and its hue the specular color.
+        // Factors are kept so that diffuse + specular + transmissive = 1.0.
+        let specular_factor = self.specular.x.max(self.specular.y).max(self.specular.z).min(1.0);
+        let s_color = if specular_factor > 0.0 { self.specular / specular_factor * 255.0 } else { vec3![255.0, 255.0, 255.0] };
+        let transmissive_factor = (1.0 - self.dissolve).clamp(0.0, 1.0);
+        let diffuse_factor = (1.0 - specular_factor - transmissive_factor).max(0.0);
+        let ambient_factor = self.ambient.map_or(0.1, |a| (a.x + a.y + a.z) / 3.0);
+        let reflect_factor = if matches!(self.illum, 3 | 5) { specular_factor } else { 0.0 };
+
+        if reflect_factor > 0.0 || transmissive_factor > 0.0 {
+            Material::Hall(Hall::new(
+                d_color, s_color, self.transmission * 255.0,
+                diffuse_factor, specular_factor,
+                reflect_factor, transmissive_factor,
+                self.refractive_index, self.shininess, ambient_factor
+            ))
+        } else {
+            Material::Phong(Phong::new(d_color, diffuse_factor, specular_factor, self.shininess, 0.0, ambient_factor))
+        }
+    }
+}
+
+fn parse_vec3(args: &[&str]) -> Result<Vec3, MeshErrorKind> {
+    let v = parse_numbers(args, 3)?;
+    Ok(vec3![v[0], v[1], v[2]])
+}
+
+// Parse a face corner in the form v, v/vt, v//vn or v/vt/vn into 0-based indices.
+fn parse_face_corner(corner: &str, data: &MeshData) -> Result<(usize, Option<usize>, Option<usize>), MeshErrorKind> {
+    let mut parts = corner.split('/');
+    let vertex = parse_index(parts.next().unwrap_or(""), "vertex", data.vertices.len())?;
+    let uv = match parts.next().filter(|s| !s.is_empty()) {
+        Some(s) => Some(parse_index(s, "uv", data.uvs.len())?),
+        None => None
+    };
+    let normal = match parts.next().filter(|s| !s.is_empty()) {
+        Some(s) => Some(parse_index(s, "normal", data.normals.len())?),
+        None => None
+    };
+    Ok((vertex, uv, normal))
+}
+
+// OBJ indices start at 1. Negative indices count back from the most recently defined element.
+// Positive indices are checked when the mesh is built since they may refer to elements defined later.
+fn parse_index(index: &str, attribute: &'static str, defined: usize) -> Result<usize, MeshErrorKind> {
+    let i: i64 = parse_number(index)?;
+    if i < 0 && defined as i64 + i >= 0 {
+        Ok((defined as i64 + i) as usize)
+    } else if i > 0 {
+        Ok(i as usize - 1)
+    } else {
+        Err(MeshErrorKind::IndexOutOfRange { attribute, index: i, count: defined })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use crate::{Hittable, Ray, Vector};
+    use crate::traits::HitData;
+    use super::*;
+
+    // Writes the files for a test into its own temporary directory, returning the path of the first one.
+    fn write_files(test: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rust-raytracer-obj-{}-{}", test, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for (name, contents) in files {
+            fs::write(dir.join(name), contents).unwrap();
+        }
+        dir.join(files[0].0)
+    }
+
+    fn default_material() -> Material {
+        Material::Flat(Flat::new(vec3![128.0, 128.0, 128.0]))
+    }
+
+    fn load_path(path: PathBuf, smooth: bool) -> Result<Vec<TriangleMesh>, MeshLoadError> {
+        load_obj_meshes(path, smooth, default_material())
+    }
+
+    fn load(test: &str, obj: &str) -> Vec<TriangleMesh> {
+        load_path(write_files(test, &[("mesh.obj", obj)]), false).unwrap()
+    }
+
+    fn triangle_count(mesh: &TriangleMesh) -> usize {
+        mesh.decompose().len()
+    }
+
+    // Hit where a ray straight down the z axis hits the mesh at (x, y).
+    fn hit_at(mesh: &TriangleMesh, x: f64, y: f64) -> HitData {
+        let hit = mesh.hit(Ray::new(vec3![x, y, 1.0], vec3![0.0, 0.0, -1.0]), 0.0, f64::INFINITY);
+        assert!(hit.did_hit);
+        hit
+    }
+
+    // Materials don't implement PartialEq, so they're compared by their debug output.
+    fn assert_material(mesh: &TriangleMesh, x: f64, y: f64, expected: Material) {
+        assert_eq!(format!("{:?}", hit_at(mesh, x, y).mat), format!("{:?}", expected));
+    }
+
+    fn assert_close(actual: Vec3, expected: Vec3) {
+        assert!((actual - expected).length() < 1e-6, "expected {:?}, got {:?}", expected, actual);
+    }
+
+    const SQUARE: &str = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n";
+
+    #[test]
+    fn negative_indices_count_back_from_the_last_vertex() {
+        let meshes = load("negative", &format!("{}f -4 -3 -2\nv 5 5 5\nf -5 -4 -1\n", SQUARE));
+        let triangles = meshes[0].decompose();
+        let centers: Vec<Vec3> = triangles.iter().map(|tri| tri.get_position()).collect();
+        assert_close(centers[0], vec3![2.0 / 3.0, 1.0 / 3.0, 0.0]);
+        assert_close(centers[1], vec3![2.0, 5.0 / 3.0, 5.0 / 3.0]);
+
+        assert_eq!(parse_index("-1", "vertex", 4).unwrap(), 3);
+        assert_eq!(parse_index("-4", "vertex", 4).unwrap(), 0);
+        assert!(matches!(parse_index("-5", "vertex", 4), Err(MeshErrorKind::IndexOutOfRange { index: -5, count: 4, .. })));
+        assert!(matches!(parse_index("0", "vertex", 4), Err(MeshErrorKind::IndexOutOfRange { index: 0, .. })));
+    }
+
+    #[test]
+    fn face_corners_with_uvs_and_normals() {
+        let data = MeshData {
+            vertices: vec![vec3![0.0, 0.0, 0.0]; 3],
+            normals: vec![vec3![0.0, 0.0, 1.0]; 2],
+            uvs: vec![vec3![0.0, 0.0, 0.0]; 4],
+            faces: vec![]
+        };
+        assert_eq!(parse_face_corner("2", &data).unwrap(), (1, None, None));
+        assert_eq!(parse_face_corner("2/4", &data).unwrap(), (1, Some(3), None));
+        assert_eq!(parse_face_corner("3/1/2", &data).unwrap(), (2, Some(0), Some(1)));
+        assert_eq!(parse_face_corner("1//2", &data).unwrap(), (0, None, Some(1)));
+        assert_eq!(parse_face_corner("-1/-1/-1", &data).unwrap(), (2, Some(3), Some(1)));
+
+        let obj = format!("{}vt 0 0\nvt 1 0\nvt 1 1\nvn 0 0 1\nf 1/1/1 2/2/1 3/3/1\nf 1//1 3//1 4//1\n", SQUARE);
+        let meshes = load("corners", &obj);
+        assert_eq!(meshes.len(), 1);
+        assert_eq!(triangle_count(&meshes[0]), 2);
+    }
+
+    #[test]
+    fn polygons_are_split_into_fans() {
+        let obj = "v 0 0 0\nv 1 0 0\nv 2 1 0\nv 1 2 0\nv 0 1 0\nf 1 2 3\nf 1 2 3 4\nf 1 2 3 4 5\n";
+        let meshes = load("fans", obj);
+        assert_eq!(triangle_count(&meshes[0]), 1 + 2 + 3);
+
+        // Every polygon is fanned around its first corner
+        let triangles = meshes[0].decompose();
+        let expected = [[0, 1, 2], [0, 1, 2], [0, 2, 3], [0, 1, 2], [0, 2, 3], [0, 3, 4]];
+        let vertices = [vec3![0.0, 0.0, 0.0], vec3![1.0, 0.0, 0.0], vec3![2.0, 1.0, 0.0], vec3![1.0, 2.0, 0.0], vec3![0.0, 1.0, 0.0]];
+        for (tri, corners) in triangles.iter().zip(expected) {
+            let center = (vertices[corners[0]] + vertices[corners[1]] + vertices[corners[2]]) / 3.0;
+            assert_close(tri.get_position(), center);
+        }
+
+        let too_few = load_path(write_files("fans-too-few", &[("mesh.obj", "v 0 0 0\nv 1 0 0\nf 1 2\n")]), false);
+        assert!(matches!(too_few, Err(MeshLoadError { line: Some(3), kind: MeshErrorKind::TooFewFaceVertices(2), .. })));
+    }
+
+    #[test]
+    fn objects_and_groups_become_separate_meshes() {
+        let obj = format!("{}o first\nf 1 2 3\ng second\nf 1 3 4\nf 1 2 4\no first\nf 2 3 4\n", SQUARE);
+        let meshes = load("groups", &obj);
+        assert_eq!(meshes.len(), 2);
+        assert_eq!(triangle_count(&meshes[0]), 2);
+        assert_eq!(triangle_count(&meshes[1]), 2);
+
+        // Faces before any o or g still make a mesh
+        assert_eq!(load("no-groups", &format!("{}f 1 2 3\n", SQUARE)).len(), 1);
+    }
+
+    #[test]
+    fn usemtl_picks_materials_from_mtllib() {
+        let mtl = "newmtl red\nKd 1 0 0\nillum 0\n\nnewmtl shiny\nKd 0 0 1\nKs 0.5 0.5 0.5\nNs 50\nillum 2\n";
+        let obj = format!("mtllib materials.mtl\n{}usemtl red\nf 1 2 3\nusemtl shiny\nf 1 3 4\nusemtl red\nf 1 2 4\nusemtl missing\nf 2 3 4\n", SQUARE);
+        let meshes = load_path(write_files("usemtl", &[("mesh.obj", &obj), ("materials.mtl", mtl)]), false).unwrap();
+
+        // A material switch within a group splits it, and switching back joins the earlier mesh again
+        assert_eq!(meshes.len(), 3);
+        assert_eq!(triangle_count(&meshes[0]), 2);
+        assert_material(&meshes[0], 0.6, 0.2, Material::Flat(Flat::new(vec3![255.0, 0.0, 0.0])));
+        assert_material(&meshes[1], 0.2, 0.6, Material::Phong(Phong::new(vec3![0.0, 0.0, 255.0], 0.5, 0.5, 50.0, 0.0, 0.1)));
+        // Unknown materials get the default one
+        assert_material(&meshes[2], 0.8, 0.8, default_material());
+
+        // So do faces without usemtl
+        let meshes = load("no-usemtl", &format!("{}f 1 2 3\n", SQUARE));
+        assert_material(&meshes[0], 0.6, 0.2, default_material());
+    }
+
+    #[test]
+    fn authored_normals_override_averaged_ones() {
+        // Two faces folded along the y axis so averaged normals would lean towards both
+        let obj = "v 0 0 0\nv 1 0 0\nv 0 1 0\nv -1 0 1\nvn 0 0.6 0.8\nf 1//1 2//1 3//1\nf 1 3 4\n";
+        let meshes = load_path(write_files("normals", &[("mesh.obj", obj)]), false).unwrap();
+        assert_close(hit_at(&meshes[0], 0.25, 0.25).normal, vec3![0.0, 0.6, 0.8]);
+
+        let smooth = load_path(write_files("normals-smooth", &[("mesh.obj", obj)]), true).unwrap();
+        assert_close(hit_at(&smooth[0], 0.25, 0.25).normal, vec3![0.0, 0.6, 0.8]);
+
+        // Without authored normals the smooth normal at the shared corner is the average of both faces, weighted by their area
+        let averaged = load_path(write_files("normals-averaged", &[("mesh.obj", "v 0 0 0\nv 1 0 0\nv 0 1 0\nv -1 0 1\nf 1 2 3\nf 1 3 4\n")]), true).unwrap();
+        let expected = (vec3![0.0, 0.0, 1.0] + vec3![1.0, 0.0, 1.0]).unit();
+        assert_close(hit_at(&averaged[0], 1e-9, 1e-9).normal, expected);
+    }
+}
@@ -0,0 +1,1266 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Deserializer};
+use serde::de::{self, IntoDeserializer, MapAccess, SeqAccess, Visitor};
+use serde::de::value::{MapAccessDeserializer, SeqAccessDeserializer};
+use std::marker::PhantomData;
+use toml::Spanned;
+use crate::{Camera, FovAxis, Integrator, Light, Projection, Material, Scene, SceneObject, Vec3, Vector, vec3};
+use crate::materials::{Dielectric, DiffuseModel, Emissive, Flat, Hall, ImageTexture, NormalMap, Param, Pattern, Pbr, Phong, ProceduralTexture, Texture, WrapMode};
+use crate::objects::{AmbientLight, AmbientOcclusion, Aov, Caustics, DirectionalLight, DiskLight, Environment, Plane, PointLight, RectangleLight, Sky, Sphere, SphereLight, SpotLight};
+use crate::loaders::{load_obj_meshes, load_smf_mesh};
+
+// Resolution the sky is baked at. The sky is smooth so it doesn't need much.
+const SKY_WIDTH: usize = 512;
+const SKY_HEIGHT: usize = 256;
+
+// Describes everything needed to build a scene. This is the root of a scene file.
+//
+// Scene files are written in TOML. Objects, lights and materials are tables keyed by their kind, e.g.
+//
+//   [[objects]]
+//   shape.sphere = { radius = 0.75 }
+//   material.flat = { albedo = [255.0, 0.0, 0.0] }
+//   translate = [0.0, 0.0, -5.0]
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SceneDescription {
+    pub render: RenderDescription,
+    #[serde(default)]
+    pub camera: CameraDescription,
+    pub environment: Option<EnvironmentDescription>,
+    pub sky: Option<Spanned<SkyDescription>>,
+    #[serde(default)]
+    pub lights: Vec<LightDescription>,
+    #[serde(default)]
+    pub ambient_lights: Vec<AmbientLightDescription>,
+    #[serde(default)]
+    pub objects: Vec<ObjectDescription>,
+}
+
+// Settings passed on to Scene::new that control how the scene is rendered.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RenderDescription {
+    pub resolution: [u32; 2],
+    #[serde(default = "default_render_distance")]
+    pub render_distance: f64,
+    #[serde(default)]
+    pub background_color: [f64; 3],
+    #[serde(default = "default_acc_obj_num")]
+    pub acc_obj_num: u64,
+    #[serde(default = "default_ray_depth")]
+    pub ray_depth: u32,
+    #[serde(default = "default_refractive_index")]
+    pub refractive_index: f64,
+    #[serde(default = "default_render_shadows")]
+    pub render_shadows: bool,
+    #[serde(default)]
+    pub integrator: IntegratorDescription,
+    #[serde(default = "default_light_samples")]
+    pub emission_samples: u32, // Light samples shared between all objects with an emissive material
+    pub ambient_occlusion: Option<AmbientOcclusionDescription>,
+    pub caustics: Option<CausticsDescription>,
+    #[serde(default)]
+    pub aovs: Vec<AovDescription>,
+}
+
+// Whitted ray tracing by default, or path tracing with a number of samples per pixel:
+//
+//   integrator.path = { samples = 256 }
+//
+// or only an ambient occlusion pass with integrator = "ambient_occlusion".
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum IntegratorDescription {
+    #[default]
+    Whitted,
+    Path {
+        #[serde(default = "default_path_samples")]
+        samples: u32,
+    },
+    AmbientOcclusion,
+}
+
+// Ambient occlusion for the ambient light of Phong and Hall materials, and the settings of the ambient occlusion pass.
+// Objects within radius of a point block ambient light, found with some samples:
+//
+//   ambient_occlusion = { radius = 1.0, samples = 16 }
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AmbientOcclusionDescription {
+    #[serde(default = "default_occlusion_radius")]
+    pub radius: Spanned<f64>,
+    #[serde(default = "default_light_samples")]
+    pub samples: u32,
+}
+
+// Caustics from a photon map, for the Whitted integrator. Photons are shared between all lights, and gathered from
+// radius around each point:
+//
+//   caustics = { photons = 500000, radius = 0.05 }
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CausticsDescription {
+    #[serde(default = "default_photons")]
+    pub photons: u32,
+    #[serde(default = "default_photon_radius")]
+    pub radius: Spanned<f64>,
+}
+
+// Buffers rendered next to the image for compositing, e.g. aovs = ["depth", "normal", "object_id"].
+#[derive(Debug, Copy, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AovDescription {
+    Depth,
+    Normal,
+    Position,
+    Albedo,
+    ObjectId,
+    MaterialId,
+    Diffuse,
+    Specular,
+    Reflection,
+    Refraction,
+}
+
+// The camera sits at position and looks at target. Without a target it looks down -Z.
+// Only one of hfov and vfov can be given, and without either the horizontal field of view is 60 degrees.
+// An aperture larger than 0 gives depth of field. The camera focuses either at focus_distance, on the object
+// visible through focus_pixel (counted from the top left of the image), or by default on the target.
+// The projection defaults to perspective, the other projections are
+//
+//   projection.orthographic = { height = 10.0 }
+//   projection.fisheye = { fov = 180.0 }
+//   projection = "equirectangular"
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CameraDescription {
+    pub hfov: Option<f64>,
+    pub vfov: Option<Spanned<f64>>,
+    #[serde(default)]
+    pub position: [f64; 3],
+    pub target: Option<Spanned<[f64; 3]>>,
+    #[serde(default = "default_up")]
+    pub up: [f64; 3],
+    #[serde(default)]
+    pub roll: f64,
+    #[serde(default)]
+    pub aperture: f64,
+    pub focus_distance: Option<f64>,
+    pub focus_pixel: Option<Spanned<[u32; 2]>>,
+    #[serde(default = "default_lens_samples")]
+    pub lens_samples: u32,
+    #[serde(default)]
+    pub projection: ProjectionDescription,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum ProjectionDescription {
+    #[default]
+    Perspective,
+    Orthographic {
+        height: f64,
+    },
+    Fisheye {
+        #[serde(default = "default_fisheye_fov")]
+        fov: f64,
+    },
+    Equirectangular,
+}
+
+// An equirectangular .hdr or .exr image around the scene, relative to the scene file.
+// It replaces the background color, shows up in reflections and refractions, and lights the scene.
+// Rotation is in degrees around the Y axis, and samples is how many directions are sampled for lighting.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EnvironmentDescription {
+    pub file: Spanned<String>,
+    #[serde(default = "default_intensity")]
+    pub intensity: f64,
+    #[serde(default)]
+    pub rotation: f64,
+    #[serde(default = "default_light_samples")]
+    pub samples: u32,
+}
+
+// A daylight sky instead of a background color or environment image. It lights the scene like an environment,
+// and adds a directional light for the sun. Sun angles are in degrees: elevation above the horizon, and azimuth
+// turning from -Z towards +X. Turbidity is how hazy the air is, from 2 for very clear to 10 for hazy.
+// The ground below the horizon reflects the sky and sun with ground_albedo, a color like material albedos.
+// Setting sun_intensity to 0 leaves out the sun.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SkyDescription {
+    pub sun_elevation: f64,
+    #[serde(default)]
+    pub sun_azimuth: f64,
+    #[serde(default = "default_turbidity")]
+    pub turbidity: f64,
+    #[serde(default = "default_ground_albedo")]
+    pub ground_albedo: [f64; 3],
+    #[serde(default = "default_intensity")]
+    pub intensity: f64,
+    #[serde(default = "default_intensity")]
+    pub sun_intensity: f64,
+    #[serde(default = "default_light_samples")]
+    pub samples: u32,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum LightDescription {
+    Point {
+        color: [f64; 3],
+        intensity: f64,
+        position: [f64; 3],
+    },
+    // Cone angles are in degrees from the direction to the edge of the cone.
+    // The light fades out between the inner and outer angle.
+    Spot {
+        color: [f64; 3],
+        intensity: f64,
+        position: [f64; 3],
+        direction: [f64; 3],
+        inner_angle: f64,
+        outer_angle: f64,
+    },
+    // Light from infinitely far away that travels in direction. It doesn't get weaker with distance.
+    Directional {
+        color: [f64; 3],
+        intensity: f64,
+        direction: [f64; 3],
+    },
+    // Area lights. Lighting and shadows are averaged over a number of samples on the light,
+    // more samples give smoother shadows. Rectangles and disks only shine towards their normal.
+    Rectangle {
+        color: [f64; 3],
+        intensity: f64,
+        position: [f64; 3],
+        normal: [f64; 3],
+        width: f64,
+        height: f64,
+        #[serde(default = "default_light_samples")]
+        samples: u32,
+    },
+    Disk {
+        color: [f64; 3],
+        intensity: f64,
+        position: [f64; 3],
+        normal: [f64; 3],
+        radius: f64,
+        #[serde(default = "default_light_samples")]
+        samples: u32,
+    },
+    Sphere {
+        color: [f64; 3],
+        intensity: f64,
+        position: [f64; 3],
+        radius: f64,
+        #[serde(default = "default_light_samples")]
+        samples: u32,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AmbientLightDescription {
+    pub intensity: [f64; 3],
+}
+
+// An object in the scene. Transformations are applied in the order scale, rotate, translate.
+// Without a material, objects get a flat gray material, and OBJ meshes use the materials from their MTL files.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ObjectDescription {
+    pub shape: ShapeDescription,
+    pub material: Option<MaterialDescription>,
+    pub scale: Option<Spanned<[f64; 3]>>,
+    pub rotate: Option<[f64; 3]>,
+    pub translate: Option<[f64; 3]>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum ShapeDescription {
+    Sphere {
+        radius: f64,
+    },
+    Plane {
+        width: f64,
+        height: f64,
+        normal: [f64; 3],
+    },
+    // Path to the mesh file is relative to the scene file. Files ending in .obj are loaded as Wavefront OBJ,
+    // anything else as SMF. An OBJ file can contain multiple meshes which all get the same transformations.
+    Mesh {
+        file: Spanned<String>,
+        #[serde(default)]
+        smooth: bool,
+    },
+}
+
+// Any color or factor can also come from a texture instead of a fixed value. Textures are tables keyed by their kind, e.g.
+//
+//   material.phong = { albedo.image = { file = "wood.png", scale = [2.0, 2.0] }, ... }
+//
+// Factors from textures are how bright the texture is, from 0 for black to 1 for white.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum MaterialDescription {
+    Flat {
+        albedo: ParamDescription<[f64; 3]>,
+    },
+    Phong {
+        albedo: ParamDescription<[f64; 3]>,
+        diffuse_factor: ParamDescription<f64>,
+        specular_factor: ParamDescription<f64>,
+        shine_factor: f64,
+        reflect_factor: ParamDescription<f64>,
+        ambient_factor: ParamDescription<f64>,
+        normal_map: Option<Spanned<NormalMapDescription>>,
+        bump_map: Option<Spanned<NormalMapDescription>>,
+    },
+    Hall {
+        d_color: ParamDescription<[f64; 3]>,
+        s_color: ParamDescription<[f64; 3]>,
+        t_color: ParamDescription<[f64; 3]>,
+        diffuse_factor: ParamDescription<f64>,
+        specular_factor: ParamDescription<f64>,
+        reflect_factor: ParamDescription<f64>,
+        transmissive_factor: ParamDescription<f64>,
+        refractive_index: f64,
+        shine_factor: f64,
+        ambient_factor: ParamDescription<f64>,
+        #[serde(default)]
+        priority: u32,
+        normal_map: Option<Spanned<NormalMapDescription>>,
+        bump_map: Option<Spanned<NormalMapDescription>>,
+    },
+    // Metallic/roughness material, as exported by most modeling tools. Metallic and roughness go from 0 to 1.
+    Pbr {
+        base_color: ParamDescription<[f64; 3]>,
+        metallic: ParamDescription<f64>,
+        roughness: ParamDescription<f64>,
+        #[serde(default)]
+        diffuse: DiffuseDescription,
+        normal_map: Option<Spanned<NormalMapDescription>>,
+        bump_map: Option<Spanned<NormalMapDescription>>,
+    },
+    // Glass, water and other clear materials. White light turns absorption_color after going absorption_distance through it.
+    // Where transparent objects overlap, rays are inside the one with the highest priority.
+    Dielectric {
+        refractive_index: f64,
+        #[serde(default = "default_absorption_color")]
+        absorption_color: [f64; 3],
+        #[serde(default = "default_absorption_distance")]
+        absorption_distance: Spanned<f64>,
+        #[serde(default)]
+        priority: u32,
+        normal_map: Option<Spanned<NormalMapDescription>>,
+        bump_map: Option<Spanned<NormalMapDescription>>,
+    },
+    // Makes the object glow and light the scene, e.g. a lamp shade mesh or a softbox plane.
+    Emissive {
+        color: [f64; 3],
+        #[serde(default = "default_intensity")]
+        intensity: f64,
+    },
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiffuseDescription {
+    Lambert,
+    #[default]
+    Burley,
+}
+
+// Bends the shading normals of a material, e.g. normal_map = { texture.image = { file = "bricks_normal.png" } }.
+// Normal maps are tangent space normal maps, and bump maps are height maps where brighter is higher.
+// Only one of normal_map and bump_map can be set on a material.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NormalMapDescription {
+    pub texture: TextureDescription,
+    #[serde(default = "default_map_strength")]
+    pub strength: f64,
+}
+
+// A material color or factor, written either as the value itself or as a texture table.
+#[derive(Debug)]
+pub enum ParamDescription<T> {
+    Value(T),
+    Texture(Box<TextureDescription>),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum TextureDescription {
+    // A PNG or JPEG image, relative to the scene file. Texture coordinates are scaled, rotated by some degrees
+    // and then offset. Outside of the image it either wraps around and repeats, or clamps to the edge pixels.
+    Image {
+        file: Spanned<String>,
+        #[serde(default)]
+        wrap: WrapDescription,
+        #[serde(default = "default_uv_scale")]
+        scale: [f64; 2],
+        #[serde(default)]
+        offset: [f64; 2],
+        #[serde(default)]
+        rotation: f64,
+    },
+    // Procedural patterns blend between two colors, black and white by default. Apart from checker_2d, which uses
+    // texture coordinates, they're worked out in object space so they move, turn and scale with the object.
+    // Scale is the size of the pattern's features, like a checker square or the distance between wood rings.
+    #[serde(rename = "checker_2d")]
+    Checker2D {
+        #[serde(default = "default_pattern_colors")]
+        colors: [[f64; 3]; 2],
+        #[serde(default = "default_checker_2d_scale")]
+        scale: f64,
+    },
+    #[serde(rename = "checker_3d")]
+    Checker3D {
+        #[serde(default = "default_pattern_colors")]
+        colors: [[f64; 3]; 2],
+        #[serde(default = "default_pattern_scale")]
+        scale: f64,
+    },
+    Noise {
+        #[serde(default = "default_pattern_colors")]
+        colors: [[f64; 3]; 2],
+        #[serde(default = "default_pattern_scale")]
+        scale: f64,
+        #[serde(default = "default_octaves")]
+        octaves: u32,
+    },
+    Turbulence {
+        #[serde(default = "default_pattern_colors")]
+        colors: [[f64; 3]; 2],
+        #[serde(default = "default_pattern_scale")]
+        scale: f64,
+        #[serde(default = "default_octaves")]
+        octaves: u32,
+    },
+    // Veins running along X. Turbulence is how much the veins get bent.
+    Marble {
+        #[serde(default = "default_pattern_colors")]
+        colors: [[f64; 3]; 2],
+        #[serde(default = "default_pattern_scale")]
+        scale: f64,
+        #[serde(default = "default_octaves")]
+        octaves: u32,
+        #[serde(default = "default_marble_turbulence")]
+        turbulence: f64,
+    },
+    // Rings around the Y axis. Turbulence is how wobbly the rings are.
+    Wood {
+        #[serde(default = "default_pattern_colors")]
+        colors: [[f64; 3]; 2],
+        #[serde(default = "default_pattern_scale")]
+        scale: f64,
+        #[serde(default = "default_wood_turbulence")]
+        turbulence: f64,
+    },
+    // Blends from the first color at one point to the second color at another point, both in object space.
+    Gradient {
+        #[serde(default = "default_pattern_colors")]
+        colors: [[f64; 3]; 2],
+        from: [f64; 3],
+        to: Spanned<[f64; 3]>,
+    },
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WrapDescription {
+    #[default]
+    Wrap,
+    Clamp,
+}
+
+// Values are numbers or arrays and textures are tables, so the kind of TOML value decides which one it is.
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for ParamDescription<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ParamVisitor<T>(PhantomData<T>);
+
+        impl<'de, T: Deserialize<'de>> Visitor<'de> for ParamVisitor<T> {
+            type Value = ParamDescription<T>;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(f, "a value or a texture table")
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<Self::Value, E> {
+                T::deserialize(v.into_deserializer()).map(ParamDescription::Value)
+            }
+
+            fn visit_f64<E: de::Error>(self, v: f64) -> Result<Self::Value, E> {
+                T::deserialize(v.into_deserializer()).map(ParamDescription::Value)
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<Self::Value, A::Error> {
+                T::deserialize(SeqAccessDeserializer::new(seq)).map(ParamDescription::Value)
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+                TextureDescription::deserialize(MapAccessDeserializer::new(map)).map(|t| ParamDescription::Texture(Box::new(t)))
+            }
+        }
+
+        deserializer.deserialize_any(ParamVisitor(PhantomData))
+    }
+}
+
+fn default_render_distance() -> f64 { 100.0 }
+fn default_acc_obj_num() -> u64 { 50 }
+fn default_ray_depth() -> u32 { 5 }
+fn default_refractive_index() -> f64 { 1.0 }
+fn default_render_shadows() -> bool { true }
+fn default_path_samples() -> u32 { 64 }
+fn default_occlusion_radius() -> Spanned<f64> { Spanned::new(0..0, 1.0) }
+fn default_photons() -> u32 { 200000 }
+fn default_photon_radius() -> Spanned<f64> { Spanned::new(0..0, 0.05) }
+fn default_hfov() -> f64 { 60.0 }
+fn default_up() -> [f64; 3] { [0.0, 1.0, 0.0] }
+fn default_lens_samples() -> u32 { 16 }
+fn default_fisheye_fov() -> f64 { 180.0 }
+fn default_light_samples() -> u32 { 16 }
+fn default_intensity() -> f64 { 1.0 }
+fn default_uv_scale() -> [f64; 2] { [1.0, 1.0] }
+fn default_map_strength() -> f64 { 1.0 }
+fn default_pattern_colors() -> [[f64; 3]; 2] { [[0.0, 0.0, 0.0], [255.0, 255.0, 255.0]] }
+fn default_pattern_scale() -> f64 { 1.0 }
+fn default_checker_2d_scale() -> f64 { 0.125 }
+fn default_octaves() -> u32 { 4 }
+fn default_marble_turbulence() -> f64 { 5.0 }
+fn default_wood_turbulence() -> f64 { 0.1 }
+fn default_turbidity() -> f64 { 3.0 }
+fn default_ground_albedo() -> [f64; 3] { [77.0, 77.0, 77.0] }
+fn default_absorption_color() -> [f64; 3] { [255.0, 255.0, 255.0] }
+fn default_absorption_distance() -> Spanned<f64> { Spanned::new(0..0, 1.0) }
+
+// Error produced when a scene file can't be read, parsed or turned into a scene.
+// Points to the location in the scene file that caused the error whenever it is known.
+#[derive(Debug)]
+pub struct SceneFileError {
+    pub path: PathBuf,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub field: Option<String>,
+    pub message: String,
+}
+
+impl SceneFileError {
+    // Create an error at the given byte span of the scene file source.
+    fn at_span(path: &Path, source: &str, span: Range<usize>, message: String) -> Self {
+        let (line, column) = line_and_column(source, span.start);
+        SceneFileError {
+            path: path.to_path_buf(),
+            line: Some(line),
+            column: Some(column),
+            field: field_at_span(source, span),
+            message,
+        }
+    }
+}
+
+impl Display for SceneFileError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.path.display())?;
+        if let (Some(line), Some(column)) = (self.line, self.column) {
+            write!(f, ":{}:{}", line, column)?;
+        }
+        if let Some(field) = &self.field {
+            write!(f, ": field `{}`", field)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+impl Error for SceneFileError {}
+
+// Reads a scene file and builds a ready to render scene from it.
+pub fn load_scene<P: AsRef<Path>>(path: P) -> Result<Scene, SceneFileError> {
+    let path = path.as_ref();
+    let source = fs::read_to_string(path).map_err(|e| SceneFileError {
+        path: path.to_path_buf(),
+        line: None,
+        column: None,
+        field: None,
+        message: e.to_string(),
+    })?;
+    parse_scene(path, &source)
+}
+
+// Builds a scene from the source text of a scene file. The path is used to report errors and to find mesh files.
+pub fn parse_scene(path: &Path, source: &str) -> Result<Scene, SceneFileError> {
+    let description: SceneDescription = toml::from_str(source).map_err(|e| {
+        let span = e.span().unwrap_or(0..0);
+        SceneFileError::at_span(path, source, span, e.message().to_string())
+    })?;
+
+    let render = &description.render;
+    let mut scene = Scene::new(
+        (render.resolution[0], render.resolution[1]),
+        render.render_distance,
+        to_vec3(render.background_color),
+        default_hfov(),
+        render.acc_obj_num,
+        render.ray_depth,
+        render.refractive_index,
+        render.render_shadows
+    );
+
+    scene.set_emission_samples(render.emission_samples);
+    scene.set_integrator(match render.integrator {
+        IntegratorDescription::Whitted => Integrator::Whitted,
+        IntegratorDescription::Path { samples } => Integrator::Path { samples: samples.max(1) },
+        IntegratorDescription::AmbientOcclusion => Integrator::AmbientOcclusion,
+    });
+    if let Some(ambient_occlusion) = &render.ambient_occlusion {
+        if *ambient_occlusion.radius.get_ref() <= 0.0 {
+            return Err(SceneFileError::at_span(path, source, ambient_occlusion.radius.span(), String::from("ambient occlusion radius must be greater than 0")));
+        }
+        scene.set_ambient_occlusion(Some(AmbientOcclusion { radius: *ambient_occlusion.radius.get_ref(), samples: ambient_occlusion.samples.max(1) }));
+    }
+    if let Some(caustics) = &render.caustics {
+        if *caustics.radius.get_ref() <= 0.0 {
+            return Err(SceneFileError::at_span(path, source, caustics.radius.span(), String::from("caustics radius must be greater than 0")));
+        }
+        scene.set_caustics(Some(Caustics { photons: caustics.photons, radius: *caustics.radius.get_ref() }));
+    }
+    scene.set_aovs(render.aovs.iter().map(|aov| match aov {
+        AovDescription::Depth => Aov::Depth,
+        AovDescription::Normal => Aov::Normal,
+        AovDescription::Position => Aov::Position,
+        AovDescription::Albedo => Aov::Albedo,
+        AovDescription::ObjectId => Aov::ObjectId,
+        AovDescription::MaterialId => Aov::MaterialId,
+        AovDescription::Diffuse => Aov::Diffuse,
+        AovDescription::Specular => Aov::Specular,
+        AovDescription::Reflection => Aov::Reflection,
+        AovDescription::Refraction => Aov::Refraction,
+    }).collect());
+    scene.set_camera(build_camera(path, source, &description)?);
+
+    // Files are looked up relative to the directory the scene file is in.
+    let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+    if let (Some(sky), Some(_)) = (&description.sky, &description.environment) {
+        return Err(SceneFileError::at_span(path, source, sky.span(), "a scene can't have both a sky and an environment".to_string()));
+    }
+    if let Some(environment) = &description.environment {
+        let mut built = Environment::load(base_dir.join(environment.file.get_ref()))
+            .map_err(|e| SceneFileError::at_span(path, source, environment.file.span(), e.to_string()))?;
+        built.set_intensity(environment.intensity);
+        built.set_rotation(environment.rotation);
+        built.set_samples(environment.samples);
+        scene.set_environment(Some(built));
+    }
+
+    if let Some(spanned_sky) = &description.sky {
+        let sky = spanned_sky.get_ref();
+        let mut built = Sky::new(sky.sun_elevation, sky.sun_azimuth, sky.turbidity, to_vec3(sky.ground_albedo));
+        built.set_intensity(sky.intensity);
+        let mut environment = built.to_environment(SKY_WIDTH, SKY_HEIGHT, sky.sun_intensity)
+            .map_err(|e| SceneFileError::at_span(path, source, spanned_sky.span(), e.to_string()))?;
+        environment.set_samples(sky.samples);
+        scene.set_environment(Some(environment));
+        if sky.sun_intensity > 0.0 {
+            scene.push_light(Light::DirectionalLight(built.get_sun(sky.sun_intensity)));
+        }
+    }
+
+    for light in description.lights.iter() {
+        scene.push_light(build_light(light));
+    }
+
+    for ambient in description.ambient_lights.iter() {
+        scene.push_ambient_light(AmbientLight {
+            intensity: to_vec3(ambient.intensity)
+        });
+    }
+
+    let mut objects = vec![];
+    for obj in description.objects.iter() {
+        for mut scene_obj in build_objects(path, source, base_dir, &mut scene, obj)? {
+            // Spheres were already scaled when they were built
+            if let (Some(scale), false) = (&obj.scale, matches!(obj.shape, ShapeDescription::Sphere { .. })) {
+                scene_obj.scale(to_vec3(*scale.get_ref()));
+            }
+            if let Some(rotation) = obj.rotate {
+                scene_obj.rotate(to_vec3(rotation));
+            }
+            if let Some(translation) = obj.translate {
+                scene_obj.translate(to_vec3(translation));
+            }
+            objects.push(scene_obj);
+        }
+    }
+    scene.add_objects(objects);
+
+    // Focusing on a pixel needs the objects to be in the scene first.
+    if let Some(focus_pixel) = &description.camera.focus_pixel {
+        let [x, y] = *focus_pixel.get_ref();
+        let error = |message: String| SceneFileError::at_span(path, source, focus_pixel.span(), message);
+        if x >= render.resolution[0] || y >= render.resolution[1] {
+            return Err(error(format!("pixel ({}, {}) is outside the {}x{} image", x, y, render.resolution[0], render.resolution[1])));
+        }
+        if scene.focus_on_pixel(x, y).is_none() {
+            return Err(error(format!("there is no object to focus on at pixel ({}, {})", x, y)));
+        }
+    }
+
+    Ok(scene)
+}
+
+fn build_camera(path: &Path, source: &str, description: &SceneDescription) -> Result<Camera, SceneFileError> {
+    let camera = &description.camera;
+    let resolution = (description.render.resolution[0], description.render.resolution[1]);
+    let (fov, fov_axis) = match (camera.hfov, &camera.vfov) {
+        (Some(_), Some(vfov)) => {
+            return Err(SceneFileError::at_span(path, source, vfov.span(), String::from("only one of hfov and vfov can be set")));
+        },
+        (_, Some(vfov)) => (*vfov.get_ref(), FovAxis::Vertical),
+        (hfov, None) => (hfov.unwrap_or_else(default_hfov), FovAxis::Horizontal),
+    };
+
+    let position = to_vec3(camera.position);
+    let target = match &camera.target {
+        Some(target) if to_vec3(*target.get_ref()).equals(position) => {
+            return Err(SceneFileError::at_span(path, source, target.span(), String::from("target must be away from the camera position")));
+        },
+        Some(target) => to_vec3(*target.get_ref()),
+        None => position + vec3![0.0, 0.0, -1.0],
+    };
+
+    if let (Some(_), Some(focus_pixel)) = (camera.focus_distance, &camera.focus_pixel) {
+        return Err(SceneFileError::at_span(path, source, focus_pixel.span(), String::from("only one of focus_distance and focus_pixel can be set")));
+    }
+
+    let mut built = Camera::look_at(resolution, position, target, to_vec3(camera.up), fov, fov_axis);
+    built.set_roll(camera.roll);
+    built.set_aperture(camera.aperture);
+    built.set_lens_samples(camera.lens_samples);
+    built.set_projection(match camera.projection {
+        ProjectionDescription::Perspective => Projection::Perspective,
+        ProjectionDescription::Orthographic { height } => Projection::Orthographic { height },
+        ProjectionDescription::Fisheye { fov } => Projection::Fisheye { fov },
+        ProjectionDescription::Equirectangular => Projection::Equirectangular,
+    });
+    built.set_focus_distance(camera.focus_distance.unwrap_or_else(|| (target - position).dot(built.get_view_dir())));
+    Ok(built)
+}
+
+fn build_light(light: &LightDescription) -> Light {
+    match light {
+        LightDescription::Point { color, intensity, position } => {
+            let mut point = PointLight::new(to_vec3(*color), *intensity);
+            point.set_position(to_vec3(*position));
+            Light::PointLight(point)
+        },
+        LightDescription::Spot { color, intensity, position, direction, inner_angle, outer_angle } => {
+            let mut spot = SpotLight::new(to_vec3(*color), *intensity, to_vec3(*direction), *inner_angle, *outer_angle);
+            spot.set_position(to_vec3(*position));
+            Light::SpotLight(spot)
+        },
+        LightDescription::Directional { color, intensity, direction } => {
+            Light::DirectionalLight(DirectionalLight::new(to_vec3(*color), *intensity, to_vec3(*direction)))
+        },
+        LightDescription::Rectangle { color, intensity, position, normal, width, height, samples } => {
+            let mut rectangle = RectangleLight::new(to_vec3(*color), *intensity, to_vec3(*normal), *width, *height, *samples);
+            rectangle.set_position(to_vec3(*position));
+            Light::RectangleLight(rectangle)
+        },
+        LightDescription::Disk { color, intensity, position, normal, radius, samples } => {
+            let mut disk = DiskLight::new(to_vec3(*color), *intensity, to_vec3(*normal), *radius, *samples);
+            disk.set_position(to_vec3(*position));
+            Light::DiskLight(disk)
+        },
+        LightDescription::Sphere { color, intensity, position, radius, samples } => {
+            let mut sphere = SphereLight::new(to_vec3(*color), *intensity, *radius, *samples);
+            sphere.set_position(to_vec3(*position));
+            Light::SphereLight(sphere)
+        }
+    }
+}
+
+// Builds the scene objects for an object description. Only OBJ files can produce more than one object.
+// Textures used by the material are added to the scene.
+fn build_objects(
+    path: &Path, source: &str, base_dir: &Path, scene: &mut Scene, obj: &ObjectDescription
+) -> Result<Vec<Box<dyn SceneObject>>, SceneFileError> {
+    let material = match &obj.material {
+        Some(material) => Some(build_material(path, source, base_dir, scene, material)?),
+        None => None,
+    };
+    let default_material = Material::Flat(Flat::new(vec3![128.0, 128.0, 128.0]));
+    let mut scene_objs: Vec<Box<dyn SceneObject>> = vec![];
+    match &obj.shape {
+        ShapeDescription::Sphere { radius } => {
+            // Sphere::scale sets the radius, so the scale from the scene file is turned into one here
+            let mut sphere = Sphere::new(*radius, material.unwrap_or(default_material));
+            if let Some(scale) = &obj.scale {
+                let [x, y, z] = *scale.get_ref();
+                if x != y || y != z {
+                    return Err(SceneFileError::at_span(
+                        path, source, scale.span(),
+                        String::from("spheres can only be scaled by the same amount along every axis")
+                    ));
+                }
+                sphere.scale(vec3![radius * x, radius * x, radius * x]);
+            }
+            scene_objs.push(Box::new(sphere));
+        },
+        ShapeDescription::Plane { width, height, normal } => {
+            scene_objs.push(Box::new(Plane::new(*width, *height, to_vec3(*normal), material.unwrap_or(default_material))));
+        },
+        ShapeDescription::Mesh { file, smooth } => {
+            let mesh_path = base_dir.join(file.get_ref());
+            // OBJ groups without a material from their MTL file get the default one
+            let is_obj = mesh_path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("obj"));
+            let meshes = if is_obj {
+                load_obj_meshes(&mesh_path, *smooth, default_material)
+            } else {
+                load_smf_mesh(&mesh_path, *smooth).map(|mesh| vec![mesh])
+            };
+
+            // Point at the mesh file in the scene file, and include where in the mesh file it went wrong.
+            let meshes = meshes.map_err(|e| SceneFileError::at_span(path, source, file.span(), e.to_string()))?;
+            for mut mesh in meshes {
+                // OBJ meshes keep the materials from their MTL file unless the scene file gives one.
+                if let Some(material) = material {
+                    mesh.set_material(material);
+                } else if !is_obj {
+                    mesh.set_material(default_material);
+                }
+                scene_objs.push(Box::new(mesh));
+            }
+        }
+    }
+    Ok(scene_objs)
+}
+
+fn build_material(
+    path: &Path, source: &str, base_dir: &Path, scene: &mut Scene, material: &MaterialDescription
+) -> Result<Material, SceneFileError> {
+    let material = match material {
+        MaterialDescription::Flat { albedo } => Material::Flat(Flat::new(build_color(path, source, base_dir, scene, albedo)?)),
+        MaterialDescription::Phong {
+            albedo, diffuse_factor, specular_factor, shine_factor, reflect_factor, ambient_factor, normal_map, bump_map
+        } => {
+            let albedo = build_color(path, source, base_dir, scene, albedo)?;
+            let mut factor = |param| build_factor(path, source, base_dir, scene, param);
+            let mut phong = Phong::new(
+                albedo, factor(diffuse_factor)?, factor(specular_factor)?, *shine_factor, factor(reflect_factor)?, factor(ambient_factor)?
+            );
+            phong.set_normal_map(build_normal_map(path, source, base_dir, scene, normal_map, bump_map)?);
+            Material::Phong(phong)
+        },
+        MaterialDescription::Hall {
+            d_color, s_color, t_color,
+            diffuse_factor, specular_factor,
+            reflect_factor, transmissive_factor,
+            refractive_index, shine_factor, ambient_factor,
+            priority, normal_map, bump_map
+        } => {
+            let mut color = |param| build_color(path, source, base_dir, scene, param);
+            let (d_color, s_color, t_color) = (color(d_color)?, color(s_color)?, color(t_color)?);
+            let mut factor = |param| build_factor(path, source, base_dir, scene, param);
+            let mut hall = Hall::new(
+                d_color, s_color, t_color,
+                factor(diffuse_factor)?, factor(specular_factor)?,
+                factor(reflect_factor)?, factor(transmissive_factor)?,
+                *refractive_index, *shine_factor, factor(ambient_factor)?
+            );
+            hall.set_priority(*priority);
+            hall.set_normal_map(build_normal_map(path, source, base_dir, scene, normal_map, bump_map)?);
+            Material::Hall(hall)
+        },
+        MaterialDescription::Pbr { base_color, metallic, roughness, diffuse, normal_map, bump_map } => {
+            let base_color = build_color(path, source, base_dir, scene, base_color)?;
+            let mut factor = |param| build_factor(path, source, base_dir, scene, param);
+            let mut pbr = Pbr::new(base_color, factor(metallic)?, factor(roughness)?);
+            pbr.set_diffuse_model(match diffuse {
+                DiffuseDescription::Lambert => DiffuseModel::Lambert,
+                DiffuseDescription::Burley => DiffuseModel::Burley,
+            });
+            pbr.set_normal_map(build_normal_map(path, source, base_dir, scene, normal_map, bump_map)?);
+            Material::Pbr(pbr)
+        },
+        MaterialDescription::Dielectric {
+            refractive_index, absorption_color, absorption_distance, priority, normal_map, bump_map
+        } => {
+            if *absorption_distance.get_ref() <= 0.0 {
+                return Err(SceneFileError::at_span(path, source, absorption_distance.span(), String::from("absorption_distance must be greater than 0")));
+            }
+            let mut dielectric = Dielectric::new(*refractive_index);
+            dielectric.set_absorption(to_vec3(*absorption_color), *absorption_distance.get_ref());
+            dielectric.set_priority(*priority);
+            dielectric.set_normal_map(build_normal_map(path, source, base_dir, scene, normal_map, bump_map)?);
+            Material::Dielectric(dielectric)
+        },
+        MaterialDescription::Emissive { color, intensity } => Material::Emissive(Emissive::new(to_vec3(*color), *intensity))
+    };
+    Ok(material)
+}
+
+fn build_normal_map(
+    path: &Path, source: &str, base_dir: &Path, scene: &mut Scene,
+    normal_map: &Option<Spanned<NormalMapDescription>>, bump_map: &Option<Spanned<NormalMapDescription>>
+) -> Result<Option<NormalMap>, SceneFileError> {
+    match (normal_map, bump_map) {
+        (Some(_), Some(bump_map)) => {
+            Err(SceneFileError::at_span(path, source, bump_map.span(), String::from("only one of normal_map and bump_map can be set")))
+        },
+        (Some(normal_map), None) => {
+            let map = normal_map.get_ref();
+            let texture = build_texture(path, source, base_dir, scene, &map.texture)?;
+            Ok(Some(NormalMap::Normal { texture, strength: map.strength }))
+        },
+        (None, Some(bump_map)) => {
+            let map = bump_map.get_ref();
+            let texture = build_texture(path, source, base_dir, scene, &map.texture)?;
+            Ok(Some(NormalMap::Bump { texture, strength: map.strength }))
+        },
+        (None, None) => Ok(None)
+    }
+}
+
+fn build_color(
+    path: &Path, source: &str, base_dir: &Path, scene: &mut Scene, param: &ParamDescription<[f64; 3]>
+) -> Result<Param<Vec3>, SceneFileError> {
+    match param {
+        ParamDescription::Value(value) => Ok(Param::Value(to_vec3(*value))),
+        ParamDescription::Texture(texture) => build_texture(path, source, base_dir, scene, texture).map(Param::Texture),
+    }
+}
+
+fn build_factor(
+    path: &Path, source: &str, base_dir: &Path, scene: &mut Scene, param: &ParamDescription<f64>
+) -> Result<Param<f64>, SceneFileError> {
+    match param {
+        ParamDescription::Value(value) => Ok(Param::Value(*value)),
+        ParamDescription::Texture(texture) => build_texture(path, source, base_dir, scene, texture).map(Param::Texture),
+    }
+}
+
+// Loads a texture into the scene and returns its index.
+fn build_texture(
+    path: &Path, source: &str, base_dir: &Path, scene: &mut Scene, texture: &TextureDescription
+) -> Result<usize, SceneFileError> {
+    let built = match texture {
+        TextureDescription::Image { file, wrap, scale, offset, rotation } => {
+            let mut image = ImageTexture::load(base_dir.join(file.get_ref()))
+                .map_err(|e| SceneFileError::at_span(path, source, file.span(), e.to_string()))?;
+            image.set_wrap(match wrap {
+                WrapDescription::Wrap => WrapMode::Wrap,
+                WrapDescription::Clamp => WrapMode::Clamp,
+            });
+            image.set_uv_transform((scale[0], scale[1]), (offset[0], offset[1]), *rotation);
+            Texture::Image(image)
+        },
+        TextureDescription::Checker2D { colors, scale } => procedural(Pattern::Checker2D, colors, *scale),
+        TextureDescription::Checker3D { colors, scale } => procedural(Pattern::Checker3D, colors, *scale),
+        TextureDescription::Noise { colors, scale, octaves } => procedural(Pattern::Noise { octaves: *octaves }, colors, *scale),
+        TextureDescription::Turbulence { colors, scale, octaves } => {
+            procedural(Pattern::Turbulence { octaves: *octaves }, colors, *scale)
+        },
+        TextureDescription::Marble { colors, scale, octaves, turbulence } => {
+            procedural(Pattern::Marble { octaves: *octaves, turbulence: *turbulence }, colors, *scale)
+        },
+        TextureDescription::Wood { colors, scale, turbulence } => {
+            procedural(Pattern::Wood { turbulence: *turbulence }, colors, *scale)
+        },
+        TextureDescription::Gradient { colors, from, to } => {
+            if to.get_ref() == from {
+                return Err(SceneFileError::at_span(path, source, to.span(), String::from("to must be a different point than from")));
+            }
+            procedural(Pattern::Gradient { from: to_vec3(*from), to: to_vec3(*to.get_ref()) }, colors, 1.0)
+        }
+    };
+    Ok(scene.push_texture(built))
+}
+
+fn procedural(pattern: Pattern, colors: &[[f64; 3]; 2], scale: f64) -> Texture {
+    let mut texture = ProceduralTexture::new(pattern, to_vec3(colors[0]), to_vec3(colors[1]));
+    texture.set_scale(scale);
+    Texture::Procedural(texture)
+}
+
+fn to_vec3(v: [f64; 3]) -> Vec3 {
+    vec3![v[0], v[1], v[2]]
+}
+
+// Convert a byte offset into the source to a 1-based line and column.
+fn line_and_column(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset.min(source.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.len() - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+    (line, column)
+}
+
+// Find the name of the field an error span belongs to.
+// The span is either a key itself (unknown keys and variants) or a value, in which case the field
+// is the closest key assigned to on the same line.
+fn field_at_span(source: &str, span: Range<usize>) -> Option<String> {
+    let is_key_char = |c: char| c.is_alphanumeric() || c == '_' || c == '-';
+    let spanned = source.get(span.clone())?;
+    let after = source[span.end..].trim_start_matches([' ', '\t']);
+    if !spanned.is_empty() && spanned.chars().all(is_key_char) && (after.starts_with('=') || after.starts_with('.')) {
+        return Some(spanned.to_string());
+    }
+
+    let line_start = source[..span.start].rfind('\n').map_or(0, |i| i + 1);
+    let before_value = &source[line_start..span.start];
+    let before_equals = before_value[..before_value.rfind('=')?].trim_end();
+    let key_start = before_equals.char_indices().rev()
+        .find(|(_, c)| !is_key_char(*c))
+        .map_or(0, |(i, c)| i + c.len_utf8());
+    let key = &before_equals[key_start..];
+    if key.is_empty() { None } else { Some(key.to_string()) }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Ray;
+    use super::*;
+
+    const RENDER: &str = "[render]\nresolution = [64, 48]\n";
+
+    fn parse_error(source: &str) -> SceneFileError {
+        match parse_scene(Path::new("scene.toml"), source) {
+            Ok(_) => panic!("expected the scene to fail to parse:\n{}", source),
+            Err(e) => e
+        }
+    }
+
+    fn object(source: &str) -> ObjectDescription {
+        toml::from_str(source).unwrap()
+    }
+
+    // Builds an object description that only produces a single scene object.
+    fn build_object(source: &str, base_dir: &Path) -> Result<Box<dyn SceneObject>, SceneFileError> {
+        let mut scene = parse_scene(Path::new("scene.toml"), RENDER).unwrap();
+        let mut built = build_objects(Path::new("scene.toml"), source, base_dir, &mut scene, &object(source))?;
+        assert_eq!(built.len(), 1);
+        Ok(built.remove(0))
+    }
+
+    // Builds the material of an object description.
+    fn material(obj: &ObjectDescription) -> Material {
+        let mut scene = parse_scene(Path::new("scene.toml"), RENDER).unwrap();
+        build_material(Path::new("scene.toml"), "", Path::new(""), &mut scene, obj.material.as_ref().unwrap()).unwrap()
+    }
+
+    // Distance along a ray going down -Z from z = 10 on the X and Y axes until it hits the object.
+    fn hit_distance(obj: &dyn SceneObject) -> Option<f64> {
+        let hit = obj.hit(Ray::new(vec3![0.0, 0.0, 10.0], vec3![0.0, 0.0, -1.0]), 0.0, 100.0);
+        if hit.did_hit { Some(hit.t) } else { None }
+    }
+
+    // Writes files into their own temporary directory, returning its path.
+    fn write_files(test: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rust-raytracer-scene-{}-{}", test, std::process::id()));
+        for (name, contents) in files {
+            let path = dir.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn defaults_fill_in_render_settings() {
+        let scene = parse_scene(Path::new("scene.toml"), RENDER).unwrap();
+        assert_eq!(scene.get_render_resolution(), (64, 48));
+        assert_eq!(scene.get_ray_depth(), default_ray_depth());
+        assert_eq!(scene.get_refractive_index(), default_refractive_index());
+        assert!(scene.get_lights().is_empty());
+    }
+
+    #[test]
+    fn camera_looks_at_its_target() {
+        let source = format!("{}[camera]\nposition = [0.0, 0.0, 5.0]\ntarget = [5.0, 0.0, 5.0]\nvfov = 90.0\n", RENDER);
+        let scene = parse_scene(Path::new("scene.toml"), &source).unwrap();
+        let camera = &scene.main_camera;
+        assert_eq!(camera.get_position(), vec3![0.0, 0.0, 5.0]);
+        assert_eq!(camera.get_view_dir(), vec3![1.0, 0.0, 0.0]);
+        // The vertical fov spans the whole height of the image
+        let top = camera.get_ray(0.5, 1.0).unwrap().direction;
+        assert!((top - vec3![1.0, 1.0, 0.0].unit()).length() < 1e-9, "{:?}", top);
+    }
+
+    #[test]
+    fn lights_and_ambient_lights_are_added() {
+        let source = format!("{}\
+            [[lights]]\npoint = {{ color = [255.0, 0.0, 0.0], intensity = 0.5, position = [1.0, 2.0, 3.0] }}\n\
+            [[ambient_lights]]\nintensity = [0.1, 0.2, 0.3]\n", RENDER);
+        let scene = parse_scene(Path::new("scene.toml"), &source).unwrap();
+        assert_eq!(scene.get_lights().len(), 1);
+        assert_eq!(scene.get_lights()[0].get_position(), Some(vec3![1.0, 2.0, 3.0]));
+        assert_eq!(scene.get_ambient_lights()[0].intensity, vec3![0.1, 0.2, 0.3]);
+    }
+
+    #[test]
+    fn shapes_and_materials_are_built() {
+        let sphere = "shape.sphere = { radius = 2.0 }\nmaterial.flat = { albedo = [255.0, 0.0, 0.0] }\n";
+        let built = build_object(sphere, Path::new("")).unwrap();
+        assert_eq!(hit_distance(built.as_ref()), Some(8.0));
+        assert!(matches!(material(&object(sphere)), Material::Flat(_)));
+
+        let plane = "shape.plane = { width = 4.0, height = 4.0, normal = [0.0, 0.0, 1.0] }\n\
+            material.phong = { albedo = [255.0, 0.0, 0.0], diffuse_factor = 0.5, specular_factor = 0.5, shine_factor = 10.0, reflect_factor = 0.0, ambient_factor = 0.1 }\n";
+        let built = build_object(plane, Path::new("")).unwrap();
+        assert_eq!(hit_distance(built.as_ref()), Some(10.0));
+        assert!(matches!(material(&object(plane)), Material::Phong(_)));
+
+        let hall = object("shape.sphere = { radius = 1.0 }\n\
+            material.hall = { d_color = [255.0, 0.0, 0.0], s_color = [255.0, 255.0, 255.0], t_color = [255.0, 255.0, 255.0], \
+            diffuse_factor = 0.5, specular_factor = 0.5, reflect_factor = 0.2, transmissive_factor = 0.0, refractive_index = 1.5, \
+            shine_factor = 10.0, ambient_factor = 0.1 }\n");
+        assert!(matches!(material(&hall), Material::Hall(_)));
+    }
+
+    #[test]
+    fn spheres_are_scaled_from_their_radius() {
+        let source = "shape.sphere = { radius = 0.5 }\nmaterial.flat = { albedo = [255.0, 0.0, 0.0] }\nscale = [4.0, 4.0, 4.0]\n";
+        let built = build_object(source, Path::new("")).unwrap();
+        assert_eq!(hit_distance(built.as_ref()), Some(8.0));
+
+        let source = "shape.sphere = { radius = 0.5 }\nmaterial.flat = { albedo = [255.0, 0.0, 0.0] }\nscale = [1.0, 2.0, 1.0]\n";
+        let error = match build_object(source, Path::new("")) {
+            Ok(_) => panic!("non-uniform sphere scale should be an error"),
+            Err(e) => e
+        };
+        assert_eq!((error.line, error.column, error.field.as_deref()), (Some(3), Some(9), Some("scale")));
+    }
+
+    #[test]
+    fn bad_values_point_at_their_field() {
+        let error = parse_error("[render]\nresolution = [64, 48]\nray_depth = \"deep\"\n");
+        assert_eq!((error.line, error.column, error.field.as_deref()), (Some(3), Some(13), Some("ray_depth")));
+        assert!(error.to_string().starts_with("scene.toml:3:13: field `ray_depth`: "), "{}", error);
+
+        let error = parse_error(&format!("{}[camera]\nhfov = 60.0\nzoom = 2.0\n", RENDER));
+        assert_eq!((error.line, error.column, error.field.as_deref()), (Some(5), Some(1), Some("zoom")));
+
+        let error = parse_error(&format!("{}[camera]\nhfov = 60.0\nvfov = 40.0\n", RENDER));
+        assert_eq!((error.line, error.column, error.field.as_deref()), (Some(5), Some(8), Some("vfov")));
+        assert_eq!(error.message, "only one of hfov and vfov can be set");
+
+        let error = parse_error(&format!("{}[camera]\nposition = [1.0, 2.0, 3.0]\ntarget = [1.0, 2.0, 3.0]\n", RENDER));
+        assert_eq!((error.line, error.field.as_deref()), (Some(5), Some("target")));
+
+        // Values inside inline tables are found too
+        let error = parse_error(&format!("{}[[objects]]\nshape.sphere = {{ radius = \"big\" }}\nmaterial.flat = {{ albedo = [255.0, 0.0, 0.0] }}\n", RENDER));
+        assert_eq!((error.line, error.field.as_deref()), (Some(4), Some("radius")));
+
+        let error = parse_error("[camera]\nhfov = 60.0\n");
+        assert!(error.message.contains("render"), "{}", error);
+    }
+
+    #[test]
+    fn unknown_materials_and_shapes_are_errors() {
+        let error = parse_error(&format!("{}[[objects]]\nshape.sphere = {{ radius = 1.0 }}\nmaterial.glass = {{ albedo = [255.0, 0.0, 0.0] }}\n", RENDER));
+        assert_eq!((error.line, error.column, error.field.as_deref()), (Some(5), Some(10), Some("glass")));
+        assert!(error.message.contains("unknown variant `glass`"), "{}", error);
+
+        let error = parse_error(&format!("{}[[objects]]\nshape.cube = {{ size = 1.0 }}\nmaterial.flat = {{ albedo = [255.0, 0.0, 0.0] }}\n", RENDER));
+        assert_eq!((error.line, error.field.as_deref()), (Some(4), Some("cube")));
+    }
+
+    #[test]
+    fn mesh_files_are_relative_to_the_scene_file() {
+        let cube = "v -1 -1 -1\nv 1 -1 -1\nv 1 1 -1\nv -1 1 -1\nv -1 -1 1\nv 1 -1 1\nv 1 1 1\nv -1 1 1\n\
+                    f 1 3 2\nf 1 4 3\nf 5 6 7\nf 5 7 8\nf 1 2 6\nf 1 6 5\nf 4 8 7\nf 4 7 3\nf 1 5 8\nf 1 8 4\nf 2 3 7\nf 2 7 6\n";
+        let scene = format!("{}[[objects]]\nshape.mesh = {{ file = \"../models/cube.smf\" }}\nmaterial.flat = {{ albedo = [255.0, 0.0, 0.0] }}\n", RENDER);
+        let dir = write_files("relative", &[("models/cube.smf", cube), ("scenes/scene.toml", &scene)]);
+        assert!(load_scene(dir.join("scenes/scene.toml")).is_ok());
+
+        let source = "shape.mesh = { file = \"../models/cube.smf\" }\nmaterial.flat = { albedo = [255.0, 0.0, 0.0] }\n";
+        let built = build_object(source, &dir.join("scenes")).unwrap();
+        // Off the axis so the ray doesn't go down the diagonal between two of the cube's triangles
+        let hit = built.hit(Ray::new(vec3![0.25, -0.5, 10.0], vec3![0.0, 0.0, -1.0]), 0.0, 100.0);
+        assert!(hit.did_hit);
+        assert!((hit.t - 9.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn objects_without_a_material_are_gray() {
+        let gray = format!("{:?}", Material::Flat(Flat::new(vec3![128.0, 128.0, 128.0])));
+        let material_of = |obj: &dyn SceneObject| {
+            format!("{:?}", obj.hit(Ray::new(vec3![0.25, -0.5, 10.0], vec3![0.0, 0.0, -1.0]), 0.0, 100.0).mat)
+        };
+        assert_eq!(material_of(build_object("shape.sphere = { radius = 1.0 }\n", Path::new("")).unwrap().as_ref()), gray);
+
+        // OBJ groups without a material from an MTL file too, unless the scene file gives one
+        let obj = "v -1 -1 0\nv 1 -1 0\nv 1 1 0\nv -1 1 0\nf 1 2 3 4\n";
+        let dir = write_files("default-material", &[("quad.obj", obj)]);
+        let quad = build_object("shape.mesh = { file = \"quad.obj\" }\n", &dir).unwrap();
+        assert_eq!(material_of(quad.as_ref()), gray);
+
+        let red = "shape.mesh = { file = \"quad.obj\" }\nmaterial.flat = { albedo = [255.0, 0.0, 0.0] }\n";
+        let quad = build_object(red, &dir).unwrap();
+        assert_eq!(material_of(quad.as_ref()), format!("{:?}", Material::Flat(Flat::new(vec3![255.0, 0.0, 0.0]))));
+    }
+
+    #[test]
+    fn missing_mesh_file_points_at_the_file() {
+        let dir = write_files("missing-mesh", &[]);
+        let source = format!("{}[[objects]]\nshape.mesh = {{ file = \"missing.smf\" }}\nmaterial.flat = {{ albedo = [255.0, 0.0, 0.0] }}\n", RENDER);
+        let error = match parse_scene(&dir.join("scene.toml"), &source) {
+            Ok(_) => panic!("missing mesh file should be an error"),
+            Err(e) => e
+        };
+        assert_eq!((error.line, error.column, error.field.as_deref()), (Some(4), Some(23), Some("file")));
+        assert!(error.message.starts_with(&format!("{}: ", dir.join("missing.smf").display())), "{}", error);
+    }
+
+    #[test]
+    fn mesh_errors_point_at_the_file_and_name_the_mesh_line() {
+        let dir = write_files("bad-mesh", &[("bad.smf", "v 0 0 0\nv 1 0 0\nf 1 2 7\n")]);
+        let source = format!("{}[[objects]]\nshape.mesh = {{ file = \"bad.smf\" }}\n", RENDER);
+        let error = match parse_scene(&dir.join("scene.toml"), &source) {
+            Ok(_) => panic!("bad mesh file should be an error"),
+            Err(e) => e
+        };
+        assert_eq!((error.line, error.field.as_deref()), (Some(4), Some("file")));
+        assert!(error.message.starts_with(&format!("{}:3: ", dir.join("bad.smf").display())), "{}", error);
+    }
+
+    #[test]
+    fn missing_scene_file_has_no_location() {
+        let error = load_scene(std::env::temp_dir().join("rust-raytracer-no-such-scene.toml")).err().unwrap();
+        assert_eq!((error.line, error.column, error.field), (None, None, None));
+    }
+
+    #[test]
+    fn line_and_column_are_1_based() {
+        let source = "ab\ncd\n\nef";
+        assert_eq!(line_and_column(source, 0), (1, 1));
+        assert_eq!(line_and_column(source, 1), (1, 2));
+        assert_eq!(line_and_column(source, 3), (2, 1));
+        assert_eq!(line_and_column(source, 6), (3, 1));
+        assert_eq!(line_and_column(source, 8), (4, 2));
+        assert_eq!(line_and_column(source, 100), (4, 3));
+    }
+
+    #[test]
+    fn field_at_span_finds_keys_and_values() {
+        let source = "radius = 1.0\nshape.sphere = { radius = \"x\" }\n";
+        // Span of a key
+        assert_eq!(field_at_span(source, 0..6).as_deref(), Some("radius"));
+        assert_eq!(field_at_span(source, 13..18).as_deref(), Some("shape"));
+        // Span of a value, which belongs to the closest key before it on the line
+        let value = source.find("\"x\"").unwrap();
+        assert_eq!(field_at_span(source, value..value + 3).as_deref(), Some("radius"));
+        // Nothing to point at
+        assert_eq!(field_at_span("[1, 2]", 0..6), None);
+    }
+}
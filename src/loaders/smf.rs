@@ -0,0 +1,118 @@
+use std::fs;
+use std::path::Path;
+use crate::{Vec3, vec3, vec4};
+use crate::data_structures::Matrix4;
+use crate::loaders::{MeshErrorKind, MeshLoadError, parse_number, parse_numbers};
+use crate::objects::TriangleMesh;
+
+// Loads a mesh in SMF format. Only vertices (v) and faces (f) are read, everything else is skipped.
+// Faces with more than 3 vertices are split into a fan of triangles.
+pub fn load_smf_mesh<P: AsRef<Path>>(filename: P, smooth: bool) -> Result<TriangleMesh, MeshLoadError> {
+    let path = filename.as_ref();
+    let text = fs::read_to_string(path).map_err(|e| MeshLoadError::io(path, e))?;
+
+    let mut vertices = Matrix4::new();
+    let mut faces: Vec<Vec3> = vec![];
+    let mut face_lines = vec![]; // Line each face came from for error messages
+    for (line_i, line) in text.lines().enumerate() {
+        let line_num = line_i + 1;
+        let error = |kind| MeshLoadError::new(path, Some(line_num), kind);
+
+        let args: Vec<&str> = line.split_whitespace().collect();
+        match args.first() {
+            Some(&"v") => {
+                let v = parse_numbers(&args[1..], 3).map_err(error)?;
+                vertices.push(vec4![v[0], v[1], v[2], 1.0]);
+            },
+            Some(&"f") => {
+                let indices = args[1..].iter()
+                    .map(|s| parse_number::<f64>(s))
+                    .collect::<Result<Vec<f64>, MeshErrorKind>>()
+                    .map_err(error)?;
+                if indices.len() < 3 {
+                    return Err(error(MeshErrorKind::TooFewFaceVertices(indices.len())));
+                }
+                for i in 1..indices.len() - 1 {
+                    faces.push(vec3![indices[0], indices[i], indices[i + 1]]);
+                    face_lines.push(line_num);
+                }
+            },
+            _ => {}
+        }
+    }
+    TriangleMesh::new(vertices, faces, smooth).map_err(|e| MeshLoadError::from_invalid_face(path, &face_lines, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use crate::SceneObject;
+    use super::*;
+
+    fn write_smf(test: &str, contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("rust-raytracer-smf-{}-{}.smf", test, std::process::id()));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn load_error(test: &str, contents: &str) -> MeshLoadError {
+        match load_smf_mesh(write_smf(test, contents), false) {
+            Ok(_) => panic!("expected {} to fail to load", test),
+            Err(e) => e
+        }
+    }
+
+    const TRIANGLE: &str = "v 0 0 0\nv 1 0 0\nv 0 1 0\n";
+
+    #[test]
+    fn missing_file_is_an_io_error() {
+        let path = std::env::temp_dir().join("rust-raytracer-smf-does-not-exist.smf");
+        let error = load_smf_mesh(&path, false).err().unwrap();
+        assert!(matches!(error.kind, MeshErrorKind::Io(_)));
+        assert_eq!(error.line, None);
+        assert!(error.to_string().starts_with(&path.display().to_string()));
+    }
+
+    #[test]
+    fn crlf_and_tabs_are_whitespace() {
+        let mesh = load_smf_mesh(write_smf("crlf", "# tabs and crlf\r\nv\t0 0 0\r\nv 1\t0\t0\r\nv  0 1 0\r\nv 1 1 0\r\nf 1\t2 3\r\nf 2 4 3\r\n"), false).unwrap();
+        assert_eq!(mesh.decompose().len(), 2);
+    }
+
+    #[test]
+    fn bad_number_names_its_line() {
+        let error = load_error("bad-number", "v 0 0 0\nv 1 0 0\nv 0 1.5.2 0\nf 1 2 3\n");
+        assert_eq!(error.line, Some(3));
+        assert!(matches!(&error.kind, MeshErrorKind::InvalidNumber(s) if s == "1.5.2"));
+        assert!(error.to_string().ends_with(":3: `1.5.2` is not a valid number"), "{}", error);
+
+        let error = load_error("missing-values", "v 0 0\n");
+        assert!(matches!(error.kind, MeshErrorKind::MissingValues { expected: 3, found: 2 }));
+        assert_eq!(error.line, Some(1));
+    }
+
+    #[test]
+    fn faces_need_three_vertices() {
+        let error = load_error("too-few", &format!("{}f 1 2\n", TRIANGLE));
+        assert!(matches!(error.kind, MeshErrorKind::TooFewFaceVertices(2)));
+        assert_eq!(error.line, Some(4));
+        assert!(error.to_string().ends_with(":4: face has 2 vertices but needs at least 3"), "{}", error);
+    }
+
+    #[test]
+    fn out_of_range_indices_are_errors() {
+        let error = load_error("out-of-range", &format!("{}f 1 2 3\n\nf 1 2 4\n", TRIANGLE));
+        assert!(matches!(error.kind, MeshErrorKind::IndexOutOfRange { attribute: "vertex", index: 4, count: 3 }));
+        assert_eq!(error.line, Some(6));
+        assert!(error.to_string().ends_with(":6: vertex index 4 is out of range, only 3 defined"), "{}", error);
+
+        // Faces are fanned, so the line is still found for triangles after the first one
+        let error = load_error("out-of-range-fan", &format!("{}f 1 2 3 9\n", TRIANGLE));
+        assert_eq!(error.line, Some(4));
+
+        // SMF indices start at 1
+        let error = load_error("zero-index", &format!("{}f 0 1 2\n", TRIANGLE));
+        assert!(matches!(error.kind, MeshErrorKind::IndexOutOfRange { attribute: "vertex", index: 0, count: 3 }));
+        assert_eq!(error.line, Some(4));
+    }
+}
@@ -1,433 +1,283 @@
+use std::path::Path;
+use std::process;
+use std::thread;
 use std::time::Instant;
+use clap::{Parser, ValueEnum};
 
-use crate::objects::{SceneObject, WorldLight, Light, Sphere, PointLight, AmbientLight};
-use crate::objects::{Camera, Scene};
-use crate::data_structures::{Ray, Vec3, Vector};
-use crate::materials::{Hall, Material, Phong};
-use crate::traits::{Hittable, HittableList};
-use crate::utils::{load_smf_mesh, save_png};
+use rust_raytracer::{load_scene, save_exr, save_png, Aov, Integrator, Vec3};
+use rust_raytracer::objects::{AmbientOcclusion, Caustics};
 
-mod objects;
-mod data_structures;
-mod traits;
-mod materials;
-mod utils;
+const DEFAULT_PATH_SAMPLES: u32 = 64;
+const DEFAULT_PHOTONS: u32 = 200000;
+const DEFAULT_PHOTON_RADIUS: f64 = 0.05;
+
+/// How pixels are sampled when rendering a frame.
+#[derive(Debug, Copy, Clone, PartialEq, ValueEnum)]
+enum SamplingMode {
+    /// One ray per pixel on a single thread.
+    Single,
+    /// One ray per pixel, rows split across threads.
+    Threaded,
+    /// In-process adaptive supersampling. More rays are shot where neighboring samples differ.
+    Adaptive,
+    /// Render at a higher resolution then average blocks of pixels down to the output resolution.
+    Supersample,
+}
+
+/// How the color of a pixel is worked out.
+#[derive(Debug, Copy, Clone, PartialEq, ValueEnum)]
+enum IntegratorMode {
+    /// Direct lighting with sharp reflections and refractions.
+    Whitted,
+    /// Monte Carlo path tracing with indirect lighting. Noisy at low sample counts.
+    Path,
+    /// Only an ambient occlusion pass, white where nothing blocks ambient light.
+    AmbientOcclusion,
+}
+
+/// A buffer rendered next to the image for compositing.
+#[derive(Debug, Copy, Clone, PartialEq, ValueEnum)]
+enum AovMode {
+    /// Distance along the camera ray.
+    Depth,
+    /// World space shading normal.
+    Normal,
+    /// World space hit point.
+    Position,
+    /// Surface color without lighting.
+    Albedo,
+    /// Objects numbered from 1 in the order of the scene file.
+    #[value(alias = "object_id")]
+    ObjectId,
+    /// Different materials numbered from 1.
+    #[value(alias = "material_id")]
+    MaterialId,
+    /// Diffuse and ambient light of Hall materials.
+    Diffuse,
+    /// Specular highlights of Hall materials.
+    Specular,
+    /// Reflections of Hall materials.
+    Reflection,
+    /// Refractions of Hall materials.
+    Refraction,
+}
+
+/// Render a scene file to an image.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Args {
+    /// Scene file to render.
+    scene: String,
+
+    /// Where to save the rendered image.
+    #[arg(short, long, default_value = "out.png")]
+    output: String,
+
+    /// Output resolution as WIDTHxHEIGHT. Overrides the resolution in the scene file.
+    #[arg(short, long, value_parser = parse_resolution)]
+    resolution: Option<(u32, u32)>,
+
+    /// Number of threads to render with. Defaults to the number of available cores.
+    #[arg(short = 'j', long)]
+    threads: Option<u32>,
+
+    /// How pixels are sampled.
+    #[arg(short, long, value_enum, default_value_t = SamplingMode::Adaptive)]
+    mode: SamplingMode,
+
+    /// Color difference tolerance above which adaptive supersampling subdivides a pixel.
+    #[arg(short, long, default_value_t = 0.05)]
+    tolerance: f64,
+
+    /// Samples per pixel for supersample mode. Must be a square number.
+    #[arg(long, default_value_t = 4)]
+    samples: u32,
+
+    /// Rays shot through the camera lens per sample when the camera has an aperture. Overrides the scene file.
+    #[arg(long)]
+    lens_samples: Option<u32>,
+
+    /// Max depth of reflection and refraction rays. Overrides the scene file.
+    #[arg(short = 'd', long)]
+    ray_depth: Option<u32>,
+
+    /// Render shadows. Overrides the scene file.
+    #[arg(long, conflicts_with = "no_shadows")]
+    shadows: bool,
+
+    /// Don't render shadows. Overrides the scene file.
+    #[arg(long)]
+    no_shadows: bool,
+
+    /// How the color of a pixel is worked out. Overrides the scene file.
+    #[arg(long, value_enum)]
+    integrator: Option<IntegratorMode>,
+
+    /// Paths traced per pixel. Switches to path tracing and overrides the scene file.
+    #[arg(long)]
+    path_samples: Option<u32>,
+
+    /// How far around a point objects block ambient light. Turns ambient occlusion on and overrides the scene file.
+    #[arg(long, value_parser = parse_radius)]
+    ao_radius: Option<f64>,
+
+    /// Rays checking for objects that block ambient light. Turns ambient occlusion on and overrides the scene file.
+    #[arg(long)]
+    ao_samples: Option<u32>,
+
+    /// Photons traced for caustics. Turns caustics on and overrides the scene file.
+    #[arg(long)]
+    photons: Option<u32>,
+
+    /// How far around a point photons are gathered from for caustics. Turns caustics on and overrides the scene file.
+    #[arg(long, value_parser = parse_radius)]
+    photon_radius: Option<f64>,
+
+    /// Buffers to render next to the image, comma separated. Each is saved as an OpenEXR file named after the
+    /// output, e.g. out.depth.exr. Overrides the scene file.
+    #[arg(long, value_enum, value_delimiter = ',')]
+    aov: Vec<AovMode>,
+
+    /// Also save a heatmap of the samples taken per pixel. Only available in adaptive mode.
+    #[arg(long)]
+    heatmap: Option<String>,
+
+    /// Print how many rays were shot and cached samples reused in adaptive mode.
+    #[arg(short, long)]
+    verbose: bool,
+}
+
+fn parse_radius(s: &str) -> Result<f64, String> {
+    let radius: f64 = s.parse().map_err(|_| format!("invalid radius `{}`", s))?;
+    if radius <= 0.0 {
+        return Err(String::from("radius must be larger than 0"));
+    }
+    Ok(radius)
+}
+
+fn parse_resolution(s: &str) -> Result<(u32, u32), String> {
+    let (width, height) = s.split_once('x').ok_or("expected WIDTHxHEIGHT, e.g. 1024x768")?;
+    let width: u32 = width.parse().map_err(|_| format!("invalid width `{}`", width))?;
+    let height: u32 = height.parse().map_err(|_| format!("invalid height `{}`", height))?;
+    if width == 0 || height == 0 {
+        return Err(String::from("resolution must be larger than 0"));
+    }
+    Ok((width, height))
+}
 
 fn main() {
-    let dimension = 1024;
-    let resolution = (dimension, dimension);
-    let mut scene = Scene::new(
-        resolution,
-        100.0,
-        vec3![255.0, 229.0, 204.0],
-        60.0,
-        50,
-        5,
-        1.0,
-        true
-    );
-
-    // Scene 1 ===================
-    //
-    // let mut teapot = load_smf_mesh("models/teapot.smf", true);
-    // teapot.scale(vec3![0.35, 0.35, 0.35]);
-    // teapot.rotate(vec3![0.0, 130.0, 0.0]);
-    // teapot.translate(vec3![-1.2, -2.45, -8.0]);
-    // teapot.set_material(Material::Hall(Hall::new(
-    //     vec3![255.0, 128.0, 0.0], vec3![255.0, 128.0, 0.0], vec3![255.0, 128.0, 0.0],
-    //     0.3, 0.7,
-    //     0.0, 0.0, 1.0,
-    //     200.0, 0.1)
-    // ));
-    // let mut frog = load_smf_mesh("models/frog.smf", true);
-    // frog.scale(vec3![3.9, 3.9, 3.9]);
-    // frog.rotate(vec3![10.0, 110.0, 10.0]);
-    // frog.translate(vec3![0.0, 1.7, -14.0]);
-    // frog.set_material(Material::Hall(Hall::new(
-    //     vec3![35.0, 65.0, 235.0], vec3![35.0, 65.0, 235.0],vec3![35.0, 65.0, 235.0],
-    //     0.4, 0.6,
-    //     0.4, 0.0, 1.0,
-    //     50.0, 0.1)
-    // ));
-    //
-    // let mut bunny = load_smf_mesh("models/bound-bunny_1k.smf", true);
-    // bunny.rotate(vec3![0.0, 0.0, 0.0]);
-    // bunny.translate( vec3![-1.0, -0.75, -3.5]);
-    // bunny.set_material(Material::Hall(Hall::new(
-    //     vec3![204.0, 153.0, 255.0], vec3![204.0, 153.0, 255.0],vec3![255.0, 255.0, 255.0],
-    //     0.4, 0.6,
-    //     0.0, 0.0, 1.055,
-    //     10.0, 0.1)
-    // ));
-    //
-    // let mut sphere1 = Sphere::new(
-    //     0.95,
-    //     Material::Hall(Hall::new(
-    //         vec3![159.0, 252.0, 178.0],vec3![159.0, 252.0, 178.0],vec3![255.0, 255.0, 255.0],
-    //         0.45, 0.55,
-    //         0.4, 0.0, 1.52,
-    //         200.0, 0.0)
-    //     )
-    // );
-    // sphere1.translate(vec3![1.0, -1.8, -6.5]);
-    //
-    // let mut sphere2 = Sphere::new(
-    //     0.75,
-    //     Material::Hall(Hall::new(
-    //         vec3![102.0, 0.0, 0.0], vec3![192.0, 192.0, 192.0],vec3![192.0, 192.0, 192.0],
-    //         0.4, 0.6,
-    //         0.4, 0.0, 1.0,
-    //         250.0, 0.1)
-    //     )
-    // );
-    // sphere2.translate(vec3![2.2, -2.4, -9.0]);
-    //
-    // let mut sphere3 = Sphere::new(
-    //     0.75,
-    //     Material::Hall(Hall::new(
-    //         vec3![102.0, 0.0, 0.0], vec3![192.0, 192.0, 192.0],vec3![192.0, 192.0, 192.0],
-    //         0.4, 0.6,
-    //         0.4, 0.0, 1.0,
-    //         250.0, 0.1)
-    //     )
-    // );
-    // sphere3.translate(vec3![-0.2, -1.9, -9.0]);
-    //
-    // let mut light1 = Light::PointLight(PointLight::new(vec3![255.0, 255.0, 255.0], 0.45)); // White
-    // light1.set_position(vec3![0.0, 2.0, 4.0]);
-    // scene.push_light(light1);
-    //
-    // let mut light2 = Light::PointLight(PointLight::new(vec3![255.0, 255.0, 255.0], 0.5)); // White
-    // light2.set_position(vec3![0.0, 5.0, -10.0]);
-    // scene.push_light(light2);
-    //
-    // scene.push_ambient_light(AmbientLight {
-    //     intensity: vec3![1.0, 1.0, 1.0]
-    // });
-    //
-    // let mut back_cube = load_smf_mesh("models/box.smf", false);
-    // back_cube.scale(vec3![50.0, 50.0, 1.0]);
-    // back_cube.translate(vec3![0.0, 0.0, -20.0]);
-    // back_cube.set_material(Material::Phong(Phong::new(vec3![0.0, 200.0, 200.0], 0.35, 0.4, 20.0 , 0.0, 0.15)));
-    //
-    // let mut front_cube = load_smf_mesh("models/box.smf", false);
-    // front_cube.scale(vec3![50.0, 50.0, 1.0]);
-    // front_cube.translate(vec3![0.0, 0.0, 0.5]);
-    // front_cube.set_material(Material::Phong(Phong::new(vec3![200.0, 200.0, 200.0], 0.35, 0.4, 20.0 , 0.0, 0.15)));
-    //
-    // let mut left_cube = load_smf_mesh("models/box.smf", false);
-    // left_cube.scale(vec3![1.0, 50.0, 70.0]);
-    // left_cube.rotate(vec3![0.0, 20.0, 0.0]);
-    // left_cube.translate(vec3![-5.0, 0.0, -6.0]);
-    // left_cube.set_material(Material::Phong(Phong::new(vec3![200.0, 200.0, 200.0], 0.35, 0.4, 20.0 , 0.0, 0.15)));
-    //
-    // let mut right_cube = load_smf_mesh("models/box.smf", false);
-    // right_cube.scale(vec3![1.0, 50.0, 70.0]);
-    // right_cube.rotate(vec3![0.0, -20.0, 0.0]);
-    // right_cube.translate(vec3![5.0, 0.0, -6.0]);
-    // right_cube.set_material(Material::Phong(Phong::new(vec3![200.0, 200.0, 200.0], 0.35, 0.4, 20.0 , 0.0, 0.15)));
-    //
-    // let mut bottom_cube = load_smf_mesh("models/box.smf", false);
-    // bottom_cube.scale(vec3![50.0, 0.4, 70.0]);
-    // bottom_cube.rotate(vec3![-10.0, 0.0, 0.0]);
-    // bottom_cube.translate(vec3![0.0, -3.0, 0.0]);
-    // bottom_cube.set_material(Material::Phong(Phong::new(vec3![230.0, 230.0, 230.0], 0.35, 0.4, 20.0 , 0.0, 0.15)));
-    //
-    // let mut top_cube = load_smf_mesh("models/box.smf", false);
-    // top_cube.scale(vec3![50.0, 0.4, 50.0]);
-    // top_cube.translate(vec3![0.0, 10.0, 0.0]);
-    // top_cube.set_material(Material::Phong(Phong::new(vec3![200.0, 200.0, 200.0], 0.35, 0.4, 20.0 , 0.0, 0.15)));
-    //
-    // scene.add_objects(vec![
-    //     Box::new(bottom_cube),
-    //     //Box::new(top_cube),
-    //     //Box::new(left_cube),
-    //     //Box::new(right_cube),
-    //     //Box::new(back_cube),
-    //     //Box::new(front_cube),
-    //     Box::new(sphere1),
-    //     Box::new(sphere2),
-    //     Box::new(sphere3),
-    //     Box::new(teapot),
-    //     Box::new(frog),
-    //     Box::new(bunny)
-    // ]);
-
-
-    // Scene 2 ===================
-    // let mut teapot = load_smf_mesh("models/teapot.smf", true);
-    // teapot.scale(vec3![0.85, 0.85, 0.85]);
-    // teapot.rotate(vec3![0.0, 130.0, 0.0]);
-    // teapot.translate(vec3![2.8, -0.95, -14.0]);
-    // teapot.set_material(Material::Hall(Hall::new(
-    //     vec3![255.0, 128.0, 0.0], vec3![255.0, 128.0, 0.0], vec3![255.0, 128.0, 0.0],
-    //     0.3, 0.7,
-    //     0.0, 0.0, 1.0,
-    //     200.0, 0.1)
-    // ));
-    let mut cow = load_smf_mesh("models/cow.smf", true);
-    cow.scale(vec3![4.5, 4.5, 4.5]);
-    cow.rotate(vec3![-10.0, 0.0, 0.0]);
-    cow.translate(vec3![0.1, -1.7, -6.3]);
-    cow.set_material(Material::Hall(Hall::new(
-        vec3![174.0, 226.0, 255.0], vec3![174.0, 226.0, 255.0],vec3![255.0, 255.0, 255.0],
-        0.35, 0.65,
-        0.01, 0.0, 1.25,
-        50.0, 0.1)
-    ));
-    //
-    // let mut bunny = load_smf_mesh("models/bound-bunny_1k.smf", true);
-    // bunny.scale(vec3![4.7, 4.7, 4.7]);
-    // bunny.rotate(vec3![0.0, 0.0, 0.0]);
-    // bunny.translate( vec3![-4.45, -2.8, -18.0]);
-    // bunny.set_material(Material::Hall(Hall::new(
-    //     vec3![255.0, 255.0, 51.0], vec3![255.0, 255.0, 51.0],vec3![255.0, 255.0, 255.0],
-    //     0.25, 0.75,
-    //     0.9, 0.0, 1.0,
-    //     10.0, 0.1)
-    // ));
-    //
-    // let mut sphere1 = Sphere::new(
-    //     0.95,
-    //     Material::Hall(Hall::new(
-    //         vec3![159.0, 252.0, 178.0],vec3![159.0, 252.0, 178.0],vec3![255.0, 255.0, 255.0],
-    //         0.25, 0.75,
-    //         0.1, 0.0, 1.0,
-    //         200.0, 0.0)
-    //     )
-    // );
-    // sphere1.translate(vec3![1.75, -1.9, -7.2]);
-    //
-    // let mut sphere2 = Sphere::new(
-    //     2.55,
-    //     Material::Hall(Hall::new(
-    //         vec3![255.0, 153.0, 255.0], vec3![255.0, 153.0, 255.0],vec3![255.0, 255.0, 255.0],
-    //         0.55, 0.45,
-    //         0.05, 0.0, 1.03,
-    //         250.0, 0.1)
-    //     )
-    // );
-    // sphere2.translate(vec3![0.0, -0.5, -8.8]);
-    //
-    // let mut sphere3 = Sphere::new(
-    //     0.75,
-    //     Material::Hall(Hall::new(
-    //         vec3![102.0, 0.0, 0.0], vec3![192.0, 192.0, 192.0],vec3![192.0, 192.0, 192.0],
-    //         0.4, 0.6,
-    //         0.4, 0.0, 1.0,
-    //         250.0, 0.1)
-    //     )
-    // );
-    // sphere3.translate(vec3![-0.2, -1.9, -9.0]);
-    //
-    // let mut light1 = Light::PointLight(PointLight::new(vec3![255.0, 255.0, 255.0], 0.45)); // White
-    // light1.set_position(vec3![0.0, 2.0, 3.0]);
-    // scene.push_light(light1);
-    //
-    // let mut light2 = Light::PointLight(PointLight::new(vec3![255.0, 255.0, 255.0], 0.5)); // White
-    // light2.set_position(vec3![0.0, 5.0, -7.0]);
-    // scene.push_light(light2);
-    //
-    // scene.push_ambient_light(AmbientLight {
-    //     intensity: vec3![1.0, 1.0, 1.0]
-    // });
-    //
-    // let mut back_cube = load_smf_mesh("models/box.smf", false);
-    // back_cube.scale(vec3![50.0, 50.0, 1.0]);
-    // back_cube.translate(vec3![0.0, 0.0, -20.0]);
-    // back_cube.set_material(Material::Phong(Phong::new(vec3![200.0, 200.0, 200.0], 0.35, 0.4, 20.0 , 0.0, 0.15)));
-    //
-    // let mut front_cube = load_smf_mesh("models/box.smf", false);
-    // front_cube.scale(vec3![50.0, 50.0, 1.0]);
-    // front_cube.translate(vec3![0.0, 0.0, 0.5]);
-    // front_cube.set_material(Material::Phong(Phong::new(vec3![200.0, 200.0, 200.0], 0.35, 0.4, 20.0 , 0.0, 0.15)));
-    //
-    // let mut left_cube = load_smf_mesh("models/box.smf", false);
-    // left_cube.scale(vec3![1.0, 50.0, 70.0]);
-    // left_cube.rotate(vec3![0.0, 20.0, 0.0]);
-    // left_cube.translate(vec3![-5.0, 0.0, -6.0]);
-    // left_cube.set_material(Material::Phong(Phong::new(vec3![200.0, 200.0, 200.0], 0.35, 0.4, 20.0 , 0.0, 0.15)));
-    //
-    // let mut right_cube = load_smf_mesh("models/box.smf", false);
-    // right_cube.scale(vec3![1.0, 50.0, 70.0]);
-    // right_cube.rotate(vec3![0.0, -20.0, 0.0]);
-    // right_cube.translate(vec3![5.0, 0.0, -6.0]);
-    // right_cube.set_material(Material::Phong(Phong::new(vec3![200.0, 200.0, 200.0], 0.35, 0.4, 20.0 , 0.0, 0.15)));
-    //
-    // let mut bottom_cube = load_smf_mesh("models/box.smf", false);
-    // bottom_cube.scale(vec3![50.0, 0.4, 70.0]);
-    // bottom_cube.rotate(vec3![-10.0, 0.0, 0.0]);
-    // bottom_cube.translate(vec3![0.0, -3.0, 0.0]);
-    // bottom_cube.set_material(Material::Phong(Phong::new(vec3![230.0, 230.0, 230.0], 0.35, 0.4, 20.0 , 0.0, 0.15)));
-    //
-    // let mut top_cube = load_smf_mesh("models/box.smf", false);
-    // top_cube.scale(vec3![50.0, 0.4, 50.0]);
-    // top_cube.translate(vec3![0.0, 10.0, 0.0]);
-    // top_cube.set_material(Material::Phong(Phong::new(vec3![200.0, 200.0, 200.0], 0.35, 0.4, 20.0 , 0.0, 0.15)));
-    //
-    // scene.add_objects(vec![
-    //     Box::new(bottom_cube),
-    //     Box::new(top_cube),
-    //     Box::new(left_cube),
-    //     Box::new(right_cube),
-    //     Box::new(back_cube),
-    //     Box::new(front_cube),
-    //     Box::new(sphere1),
-    //     Box::new(sphere2),
-    //     //Box::new(sphere3),
-    //     Box::new(teapot),
-    //     Box::new(cow),
-    //     Box::new(bunny)
-    // ]);
-
-    // Scene 3=========================
-    let mut teapot = load_smf_mesh("models/teapot.smf", true);
-    teapot.scale(vec3![1.0, 1.0, 1.0]);
-    teapot.rotate(vec3![0.0, -20.0, 0.0]);
-    teapot.translate(vec3![0.0, -1.5, -7.0]);
-    teapot.set_material(Material::Hall(Hall::new(
-        vec3![255.0, 218.0, 141.0], vec3![255.0, 218.0, 141.0], vec3![255.0, 255.0, 255.0],
-        0.05, 0.35,
-        0.0, 0.0, 1.05,
-        450.0, 0.1)
-    ));
-    let mut cow = load_smf_mesh("models/cow.smf", true);
-    cow.scale(vec3![4.5, 4.5, 4.5]);
-    cow.rotate(vec3![-10.0, 0.0, 0.0]);
-    cow.translate(vec3![0.1, -1.7, -6.3]);
-    cow.set_material(Material::Hall(Hall::new(
-        vec3![174.0, 226.0, 255.0], vec3![174.0, 226.0, 255.0],vec3![255.0, 255.0, 255.0],
-        0.35, 0.65,
-        0.01, 0.0, 1.25,
-        50.0, 0.1)
-    ));
-
-    let mut bunny = load_smf_mesh("models/bound-bunny_1k.smf", true);
-    bunny.scale(vec3![1.7, 1.7, 1.7]);
-    bunny.rotate(vec3![0.0, 0.0, 0.0]);
-    bunny.translate( vec3![-0.8, 1.8, -6.5]);
-    bunny.set_material(Material::Hall(Hall::new(
-        vec3![0.0, 128.0, 255.0], vec3![0.0, 128.0, 255.0],vec3![255.0, 255.0, 255.0],
-        0.25, 0.75,
-        0.0, 0.0, 1.0,
-        80.0, 0.1)
-    ));
-
-    let mut bunny2 = load_smf_mesh("models/bound-bunny_1k.smf", true);
-    bunny2.scale(vec3![1.7, 1.7, 1.7]);
-    bunny2.rotate(vec3![0.0, 180.0, 0.0]);
-    bunny2.translate( vec3![0.8, 1.8, -6.5]);
-    bunny2.set_material(Material::Hall(Hall::new(
-        vec3![29.0, 132.0, 53.0], vec3![29.0, 132.0, 53.0],vec3![255.0, 255.0, 255.0],
-        0.25, 0.75,
-        0.0, 0.0, 1.0,
-        80.0, 0.1)
-    ));
-
-    let mut sphere1 = Sphere::new(
-        0.75,
-        Material::Hall(Hall::new(
-            vec3![159.0, 252.0, 178.0],vec3![159.0, 252.0, 178.0],vec3![255.0, 255.0, 255.0],
-            0.25, 0.75,
-            0.1, 0.0, 1.0,
-            200.0, 0.0)
-        )
-    );
-    sphere1.translate(vec3![-2.0, 0.0, -9.0]);
-
-    let mut sphere2 = Sphere::new(
-        0.75,
-        Material::Hall(Hall::new(
-            vec3![255.0, 153.0, 255.0], vec3![255.0, 153.0, 255.0],vec3![255.0, 255.0, 255.0],
-            0.55, 0.45,
-            0.05, 0.0, 1.03,
-            250.0, 0.1)
-        )
-    );
-    sphere2.translate(vec3![2.0, 1.0, -9.0]);
-
-    let mut sphere3 = Sphere::new(
-        0.75,
-        Material::Hall(Hall::new(
-            vec3![255.0, 102.0, 102.0], vec3![255.0, 102.0, 102.0],vec3![255.0, 255.0, 255.0],
-            0.05, 0.1,
-            0.0, 0.0, 1.05,
-            250.0, 0.1)
-        )
-    );
-    sphere3.translate(vec3![0.8, -0.8, -4.0]);
-
-    let mut light1 = Light::PointLight(PointLight::new(vec3![255.0, 255.0, 255.0], 0.65)); // White
-    light1.set_position(vec3![-1.0, 3.0, 0.0]);
-    scene.push_light(light1);
-
-    let mut light2 = Light::PointLight(PointLight::new(vec3![255.0, 255.0, 255.0], 0.65)); // White
-    light2.set_position(vec3![1.0, -1.0, 0.0]);
-    scene.push_light(light2);
-
-    scene.push_ambient_light(AmbientLight {
-        intensity: vec3![1.0, 1.0, 1.0]
-    });
+    let args = Args::parse();
 
-    let mut back_cube = load_smf_mesh("models/box.smf", false);
-    back_cube.scale(vec3![50.0, 50.0, 1.0]);
-    back_cube.translate(vec3![0.0, 0.0, -20.0]);
-    back_cube.set_material(Material::Phong(Phong::new(vec3![200.0, 200.0, 200.0], 0.35, 0.4, 20.0 , 0.0, 0.15)));
-
-    let mut front_cube = load_smf_mesh("models/box.smf", false);
-    front_cube.scale(vec3![50.0, 50.0, 1.0]);
-    front_cube.translate(vec3![0.0, 0.0, 0.5]);
-    front_cube.set_material(Material::Phong(Phong::new(vec3![200.0, 200.0, 200.0], 0.35, 0.4, 20.0 , 0.0, 0.15)));
-
-    let mut left_cube = load_smf_mesh("models/box.smf", false);
-    left_cube.scale(vec3![1.0, 50.0, 70.0]);
-    left_cube.rotate(vec3![0.0, 20.0, 0.0]);
-    left_cube.translate(vec3![-5.0, 0.0, -6.0]);
-    left_cube.set_material(Material::Phong(Phong::new(vec3![200.0, 200.0, 200.0], 0.35, 0.4, 20.0 , 0.0, 0.15)));
-
-    let mut right_cube = load_smf_mesh("models/box.smf", false);
-    right_cube.scale(vec3![1.0, 50.0, 70.0]);
-    right_cube.rotate(vec3![0.0, -20.0, 0.0]);
-    right_cube.translate(vec3![5.0, 0.0, -6.0]);
-    right_cube.set_material(Material::Phong(Phong::new(vec3![200.0, 200.0, 200.0], 0.35, 0.4, 20.0 , 0.0, 0.15)));
-
-    let mut bottom_cube = load_smf_mesh("models/box.smf", false);
-    bottom_cube.scale(vec3![50.0, 0.4, 70.0]);
-    bottom_cube.rotate(vec3![-10.0, 0.0, 0.0]);
-    bottom_cube.translate(vec3![0.0, -3.0, 0.0]);
-    bottom_cube.set_material(Material::Phong(Phong::new(vec3![230.0, 230.0, 230.0], 0.35, 0.4, 20.0 , 0.0, 0.15)));
-
-    let mut top_cube = load_smf_mesh("models/box.smf", false);
-    top_cube.scale(vec3![50.0, 0.4, 50.0]);
-    top_cube.translate(vec3![0.0, 10.0, 0.0]);
-    top_cube.set_material(Material::Phong(Phong::new(vec3![200.0, 200.0, 200.0], 0.35, 0.4, 20.0 , 0.0, 0.15)));
-
-    scene.add_objects(vec![
-        Box::new(bottom_cube),
-        Box::new(top_cube),
-        Box::new(left_cube),
-        Box::new(right_cube),
-        Box::new(back_cube),
-        Box::new(front_cube),
-        Box::new(sphere1),
-        Box::new(sphere2),
-        Box::new(sphere3),
-        Box::new(teapot),
-        Box::new(bunny),
-        Box::new(bunny2)
-    ]);
+    let samples_square = (args.samples as f64).sqrt() as u32;
+    if args.mode == SamplingMode::Supersample && (samples_square == 0 || samples_square * samples_square != args.samples) {
+        eprintln!("--samples must be a square number, got {}", args.samples);
+        process::exit(2);
+    }
+    if args.heatmap.is_some() && args.mode != SamplingMode::Adaptive {
+        eprintln!("--heatmap is only available with --mode adaptive");
+        process::exit(2);
+    }
+
+    let mut scene = match load_scene(&args.scene) {
+        Ok(scene) => scene,
+        Err(e) => {
+            eprintln!("Failed to load scene: {}", e);
+            process::exit(1);
+        }
+    };
+
+    // Apply overrides from the command line
+    if let Some(resolution) = args.resolution {
+        scene.set_render_resolution(resolution);
+    }
+    if let Some(ray_depth) = args.ray_depth {
+        scene.set_ray_depth(ray_depth);
+    }
+    if let Some(lens_samples) = args.lens_samples {
+        scene.main_camera.set_lens_samples(lens_samples);
+    }
+    if args.shadows || args.no_shadows {
+        scene.set_render_shadows(args.shadows);
+    }
+    // Giving path samples switches to path tracing, keeping the samples from the scene file if there are none
+    match (args.integrator, args.path_samples) {
+        (Some(IntegratorMode::Whitted), _) => scene.set_integrator(Integrator::Whitted),
+        (Some(IntegratorMode::Path), None) => if !matches!(scene.get_integrator(), Integrator::Path { .. }) {
+            scene.set_integrator(Integrator::Path { samples: DEFAULT_PATH_SAMPLES });
+        },
+        (Some(IntegratorMode::AmbientOcclusion), _) => scene.set_integrator(Integrator::AmbientOcclusion),
+        (_, Some(samples)) => scene.set_integrator(Integrator::Path { samples: samples.max(1) }),
+        (None, None) => {}
+    }
+    // Either ambient occlusion option turns it on, keeping the other one from the scene file if it's there
+    if args.ao_radius.is_some() || args.ao_samples.is_some() {
+        let ambient_occlusion = scene.get_ambient_occlusion().unwrap_or_default();
+        scene.set_ambient_occlusion(Some(AmbientOcclusion {
+            radius: args.ao_radius.unwrap_or(ambient_occlusion.radius),
+            samples: args.ao_samples.unwrap_or(ambient_occlusion.samples).max(1)
+        }));
+    }
+    // Either photon option turns caustics on, keeping the other one from the scene file if it's there
+    if args.photons.is_some() || args.photon_radius.is_some() {
+        let caustics = scene.get_caustics().unwrap_or(Caustics { photons: DEFAULT_PHOTONS, radius: DEFAULT_PHOTON_RADIUS });
+        scene.set_caustics(Some(Caustics {
+            photons: args.photons.unwrap_or(caustics.photons),
+            radius: args.photon_radius.unwrap_or(caustics.radius)
+        }));
+    }
+    if !args.aov.is_empty() {
+        scene.set_aovs(args.aov.iter().map(|aov| match aov {
+            AovMode::Depth => Aov::Depth,
+            AovMode::Normal => Aov::Normal,
+            AovMode::Position => Aov::Position,
+            AovMode::Albedo => Aov::Albedo,
+            AovMode::ObjectId => Aov::ObjectId,
+            AovMode::MaterialId => Aov::MaterialId,
+            AovMode::Diffuse => Aov::Diffuse,
+            AovMode::Specular => Aov::Specular,
+            AovMode::Reflection => Aov::Reflection,
+            AovMode::Refraction => Aov::Refraction,
+        }).collect());
+    }
+    let aovs = scene.get_aovs().clone();
+    let threads = args.threads.unwrap_or_else(|| {
+        thread::available_parallelism().map_or(1, |n| n.get() as u32)
+    });
+    let resolution = scene.get_render_resolution();
 
     let now = Instant::now();
-    let (computed, _heatmap) = scene.render_supersample_frame_threaded(0.05);
-    //let computed = scene.render_frame_threaded();
+    let output = match args.mode {
+        SamplingMode::Single => scene.render_frame(),
+        SamplingMode::Threaded => scene.render_frame_threaded(threads),
+        SamplingMode::Adaptive => {
+            let (output, stats) = scene.render_supersample_frame_threaded(args.tolerance, threads);
+            if args.verbose {
+                println!("Shot a total of {} unique rays.\nCalled ray cache {} times.", stats.rays_shot, stats.cache_calls);
+            }
+            if let (Some(heatmap_path), Some(heatmap)) = (&args.heatmap, output.get("samples")) {
+                save_png(&normalize_heatmap(heatmap), resolution, heatmap_path);
+            }
+            output
+        },
+        SamplingMode::Supersample => {
+            scene.set_render_resolution((resolution.0 * samples_square, resolution.1 * samples_square));
+            scene.render_frame_threaded(threads).supersample(args.samples)
+        }
+    };
     println!("Took {:.2?} to render.", now.elapsed());
-    save_png(&computed, resolution, "out.png");
-    //save_png(&heatmap, resolution, "out_heatmap.png");
-
-    // // Print out super sampled version of image
-    // let samples = 4;
-    // let square = (samples as f32).sqrt() as u32;
-    // let sampled_resolution = (resolution.0 / square, resolution.1 / square);
-    // let antialiased = supersample(&computed, resolution, samples);
-    // save_png(&antialiased, sampled_resolution, "out_supersample.png");
+    save_png(output.get_beauty(), resolution, &args.output);
+
+    // AOVs go next to the image, named after it
+    for aov in aovs {
+        if let Some(pixels) = output.get(aov.get_name()) {
+            let aov_path = Path::new(&args.output).with_extension(format!("{}.exr", aov.get_name()));
+            save_exr(pixels, resolution, &aov_path.to_string_lossy());
+        }
+    }
+}
+
+// Scale the samples taken per pixel so the pixel with the most samples is white.
+fn normalize_heatmap(heatmap: &[Vec3]) -> Vec<Vec3> {
+    let most_samples = heatmap.iter().fold(1.0, |max: f64, p| max.max(p.x));
+    heatmap.iter().map(|p| *p / most_samples * 255.0).collect()
 }
@@ -0,0 +1,269 @@
+use std::f64::consts::PI;
+use rand::Rng;
+use crate::{Vec3, vec3};
+use crate::data_structures::Vector;
+use crate::traits::HitData;
+use crate::utils::orthonormal_basis;
+
+// A direction a Bsdf picked for a path to go on in.
+#[derive(Debug, Copy, Clone)]
+pub struct BsdfSample {
+    pub direction: Vec3,
+    // Light coming back along the direction is multiplied by this. It's the bsdf times the cosine divided by the pdf.
+    pub weight: Vec3,
+    // Probability density of picking the direction per solid angle. 0 for perfectly sharp reflection and refraction,
+    // which can't be picked by light samples.
+    pub pdf: f64,
+    pub transmitted: bool // The direction goes through the surface instead of bouncing off of it
+}
+
+// How a surface scatters light at a hit, used by the path tracer. Made up of lobes that add up: diffuse, glossy
+// highlights from GGX microfacets, and perfectly sharp mirror reflection and refraction.
+//
+// Textures are already looked up, and colors are 0-1 instead of 0-255. The normals face the side the ray came from.
+#[derive(Debug, Copy, Clone)]
+pub struct Bsdf {
+    normal: Vec3,
+    geometric_normal: Vec3,
+    diffuse: Vec3,
+    burley_roughness: Option<f64>, // Burley diffuse instead of Lambert
+    glossy: Vec3,
+    glossy_alpha: f64,
+    glossy_fresnel: bool, // Glossy is the color straight on, going to white at grazing angles. Light reflected there isn't diffused.
+    mirror: Vec3,
+    refraction: Vec3,
+    ratio: f64 // Refractive index on the side the ray came from divided by the one on the other side
+}
+
+impl Bsdf {
+    // A surface that absorbs everything, until lobes are set.
+    pub fn new(hit: &HitData) -> Self {
+        let flip = if hit.ray.direction.dot(hit.geometric_normal) > 0.0 { -1.0 } else { 1.0 };
+        Bsdf {
+            normal: hit.normal * flip,
+            geometric_normal: hit.geometric_normal * flip,
+            diffuse: vec3![0.0, 0.0, 0.0],
+            burley_roughness: None,
+            glossy: vec3![0.0, 0.0, 0.0],
+            glossy_alpha: 1.0,
+            glossy_fresnel: false,
+            mirror: vec3![0.0, 0.0, 0.0],
+            refraction: vec3![0.0, 0.0, 0.0],
+            ratio: 1.0
+        }
+    }
+
+    pub fn set_diffuse(&mut self, color: Vec3) {
+        self.diffuse = color;
+    }
+
+    pub fn set_burley(&mut self, roughness: f64) {
+        self.burley_roughness = Some(roughness);
+    }
+
+    // Alpha is the GGX roughness, 0 for a mirror and 1 for matte.
+    pub fn set_glossy(&mut self, color: Vec3, alpha: f64, fresnel: bool) {
+        self.glossy = color;
+        self.glossy_alpha = alpha.max(1e-4);
+        self.glossy_fresnel = fresnel;
+    }
+
+    pub fn set_mirror(&mut self, color: Vec3) {
+        self.mirror = color;
+    }
+
+    pub fn set_refraction(&mut self, color: Vec3, ratio: f64) {
+        self.refraction = color;
+        self.ratio = ratio;
+    }
+
+    pub fn get_normal(&self) -> Vec3 {
+        self.normal
+    }
+
+    pub fn get_geometric_normal(&self) -> Vec3 {
+        self.geometric_normal
+    }
+
+    // If any light is diffused, which is where caustics show up.
+    pub fn is_diffuse(&self) -> bool {
+        self.diffuse.x.max(self.diffuse.y).max(self.diffuse.z) > 0.0
+    }
+
+    // Color of the surface without any light on it, every lobe together. 0-1 like the lobes.
+    pub fn get_albedo(&self) -> Vec3 {
+        self.diffuse + self.glossy + self.mirror + self.refraction
+    }
+
+    // Light scattered from direction wi towards wo, both pointing away from the surface. Sharp lobes can't be
+    // hit by chance, so they aren't part of it.
+    pub fn eval(&self, wo: Vec3, wi: Vec3) -> Vec3 {
+        let (cos_o, cos_i) = (self.normal.dot(wo), self.normal.dot(wi));
+        if cos_o <= 0.0 || cos_i <= 0.0 {
+            return vec3![0.0, 0.0, 0.0];
+        }
+        let half = (wo + wi).unit();
+        let i_dot_h = wi.dot(half).max(0.0);
+        let fresnel = if self.glossy_fresnel { schlick_fresnel(self.glossy, i_dot_h) } else { self.glossy };
+
+        let alpha = self.glossy_alpha;
+        let glossy = fresnel * (ggx_distribution(self.normal.dot(half).max(0.0), alpha) * smith_masking(cos_i, cos_o, alpha) / (4.0 * cos_i * cos_o));
+
+        let mut diffuse = self.diffuse / PI;
+        if let Some(roughness) = self.burley_roughness {
+            diffuse *= burley_diffuse(cos_i, cos_o, i_dot_h, roughness);
+        }
+        if self.glossy_fresnel {
+            diffuse *= vec3![1.0, 1.0, 1.0] - fresnel;
+        }
+        diffuse + glossy
+    }
+
+    // Probability density of sample picking direction wi per solid angle.
+    pub fn pdf(&self, wo: Vec3, wi: Vec3) -> f64 {
+        let (cos_o, cos_i) = (self.normal.dot(wo), self.normal.dot(wi));
+        if cos_o <= 0.0 || cos_i <= 0.0 {
+            return 0.0;
+        }
+        let [diffuse, glossy, _, _] = self.get_lobe_chances(cos_o);
+        let half = (wo + wi).unit();
+        let glossy_pdf = ggx_distribution(self.normal.dot(half).max(0.0), self.glossy_alpha) * self.normal.dot(half).max(0.0) / (4.0 * wo.dot(half).max(1e-9));
+        diffuse * cos_i / PI + glossy * glossy_pdf
+    }
+
+    // Pick a direction for light to come from towards wo, favoring the lobes that reflect the most.
+    // None when the surface absorbs everything or the direction ended up inside the surface.
+    pub fn sample(&self, wo: Vec3, rng: &mut impl Rng) -> Option<BsdfSample> {
+        let cos_o = self.normal.dot(wo);
+        if cos_o <= 0.0 {
+            return None;
+        }
+        let [diffuse, glossy, mirror, refraction] = self.get_lobe_chances(cos_o);
+        if diffuse + glossy + mirror + refraction <= 0.0 {
+            return None;
+        }
+
+        let pick = rng.gen::<f64>();
+        let (tangent, bitangent) = orthonormal_basis(self.normal);
+        let direction = if pick < diffuse {
+            // More directions close to the normal, matching how much light they bring in
+            let (r, angle) = (rng.gen::<f64>().sqrt(), 2.0 * PI * rng.gen::<f64>());
+            tangent * (r * angle.cos()) + bitangent * (r * angle.sin()) + self.normal * (1.0 - r * r).max(0.0).sqrt()
+        } else if pick < diffuse + glossy {
+            // Pick a microfacet normal from the GGX distribution and reflect off of it
+            let alpha = self.glossy_alpha;
+            let random = rng.gen::<f64>();
+            let cos_h = ((1.0 - random) / (1.0 + (alpha * alpha - 1.0) * random)).sqrt();
+            let sin_h = (1.0 - cos_h * cos_h).max(0.0).sqrt();
+            let angle = 2.0 * PI * rng.gen::<f64>();
+            let half = tangent * (sin_h * angle.cos()) + bitangent * (sin_h * angle.sin()) + self.normal * cos_h;
+            half * (2.0 * wo.dot(half)) - wo
+        } else if pick < diffuse + glossy + mirror {
+            let direction = self.normal * (2.0 * cos_o) - wo;
+            return self.sharp_sample(direction, self.mirror / mirror, false);
+        } else {
+            // Past the critical angle the light is reflected back instead
+            let direction = (-1.0 * wo).refract(self.normal, self.ratio);
+            if direction.length() == 0.0 {
+                return self.sharp_sample(self.normal * (2.0 * cos_o) - wo, self.refraction / refraction, false);
+            }
+            return self.sharp_sample(direction, self.refraction / refraction, true);
+        };
+
+        let pdf = self.pdf(wo, direction);
+        if pdf <= 0.0 || direction.dot(self.geometric_normal) <= 0.0 {
+            return None;
+        }
+        let weight = self.eval(wo, direction) * (self.normal.dot(direction) / pdf);
+        Some(BsdfSample { direction, weight, pdf, transmitted: false })
+    }
+
+    // Mirror reflection and refraction, which only ever go in one direction.
+    fn sharp_sample(&self, direction: Vec3, weight: Vec3, transmitted: bool) -> Option<BsdfSample> {
+        // Bent normals can send reflections into the surface, or refractions out of it
+        if (direction.dot(self.geometric_normal) < 0.0) != transmitted {
+            return None;
+        }
+        Some(BsdfSample { direction, weight, pdf: 0.0, transmitted })
+    }
+
+    // Chance of sampling the diffuse, glossy, mirror and refraction lobes, based on how much light they reflect.
+    fn get_lobe_chances(&self, cos_o: f64) -> [f64; 4] {
+        let glossy = if self.glossy_fresnel { schlick_fresnel(self.glossy, cos_o) } else { self.glossy };
+        let strengths = [self.diffuse, glossy, self.mirror, self.refraction].map(|c| c.x.max(c.y).max(c.z).max(0.0));
+        let total: f64 = strengths.iter().sum();
+        if total <= 0.0 {
+            return [0.0; 4];
+        }
+        strengths.map(|s| s / total)
+    }
+}
+
+// Roughness of a GGX highlight about as wide as a Phong highlight with some shine factor.
+pub fn shine_to_alpha(shine_factor: f64) -> f64 {
+    (2.0 / (shine_factor.max(0.0) + 2.0)).sqrt()
+}
+
+// How much light is reflected instead of entering the surface. Goes up to all of it at grazing angles.
+pub fn schlick_fresnel(f0: Vec3, cos_theta: f64) -> Vec3 {
+    f0 + (vec3![1.0, 1.0, 1.0] - f0) * (1.0 - cos_theta).clamp(0.0, 1.0).powi(5)
+}
+
+// GGX/Trowbridge-Reitz, how many microfacets face along the half vector.
+pub fn ggx_distribution(n_dot_h: f64, alpha: f64) -> f64 {
+    let alpha2 = alpha * alpha;
+    let d = n_dot_h * n_dot_h * (alpha2 - 1.0) + 1.0;
+    alpha2 / (PI * d * d)
+}
+
+// Smith's separable masking-shadowing for GGX, how many microfacets are visible from both the light and the viewer.
+pub fn smith_masking(n_dot_l: f64, n_dot_v: f64, alpha: f64) -> f64 {
+    let alpha2 = alpha * alpha;
+    let g1 = |n_dot_x: f64| 2.0 * n_dot_x / (n_dot_x + (alpha2 + (1.0 - alpha2) * n_dot_x * n_dot_x).sqrt());
+    g1(n_dot_l) * g1(n_dot_v)
+}
+
+// Burley's diffuse retro-reflection, relative to Lambert.
+pub fn burley_diffuse(n_dot_l: f64, n_dot_v: f64, l_dot_h: f64, roughness: f64) -> f64 {
+    let f90 = 0.5 + 2.0 * roughness * l_dot_h * l_dot_h;
+    let light_scatter = 1.0 + (f90 - 1.0) * (1.0 - n_dot_l).powi(5);
+    let view_scatter = 1.0 + (f90 - 1.0) * (1.0 - n_dot_v).powi(5);
+    light_scatter * view_scatter
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ggx_distribution_is_normalized() {
+        // Projected onto the surface, the microfacets cover exactly its area: the integral of D(h) (n.h) over
+        // the hemisphere is 1. Integrate over cos(theta) so the sharp peak of smooth surfaces gets enough steps.
+        let steps = 200_000;
+        for alpha in [0.05, 0.1, 0.3, 0.7, 1.0] {
+            let total: f64 = (0..steps).map(|i| {
+                let cos_theta = (i as f64 + 0.5) / steps as f64;
+                ggx_distribution(cos_theta, alpha) * cos_theta
+            }).sum::<f64>() * 2.0 * PI / steps as f64;
+            assert!((total - 1.0).abs() < 1e-3, "alpha {} integrates to {}", alpha, total);
+        }
+    }
+
+    #[test]
+    fn fresnel_goes_from_f0_to_white() {
+        let f0 = vec3![0.04, 0.5, 1.0];
+        assert_eq!(schlick_fresnel(f0, 1.0), f0);
+        assert_eq!(schlick_fresnel(f0, 0.0), vec3![1.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn smith_masking_is_at_most_one() {
+        for alpha in [0.01, 0.5, 1.0] {
+            for cos in [0.01, 0.3, 1.0] {
+                let g = smith_masking(cos, cos, alpha);
+                assert!(g > 0.0 && g <= 1.0 + 1e-12, "alpha {} cos {} gives {}", alpha, cos, g);
+            }
+        }
+        assert!((smith_masking(1.0, 1.0, 0.5) - 1.0).abs() < 1e-12);
+    }
+}
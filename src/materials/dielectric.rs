@@ -0,0 +1,186 @@
+use crate::{Ray, Scene, Vec3, vec3};
+use crate::data_structures::{Medium, Vector};
+use crate::materials::{Bsdf, Mat, NormalMap};
+use crate::traits::HitData;
+
+// Clear materials like glass or water. How much light is reflected and how much goes through follows the Fresnel
+// equations for the refractive index, so glass reflects a little straight on and a lot at grazing angles. Light that
+// can't leave the object at a steep angle is reflected back inside (total internal reflection).
+//
+// Light is absorbed the further it travels inside the object (Beer-Lambert law). Absorption color is the color of
+// white light after travelling absorption distance through the object, white for clear glass.
+//
+// Objects can be inside each other, like ice in a glass of water. Where they overlap the one with the highest
+// priority is what the ray is in, so water can overlap the glass a little to leave no gap between them.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Dielectric {
+    refractive_index: f64,
+    absorption_color: Vec3,
+    absorption_distance: f64,
+    priority: u32,
+    normal_map: Option<NormalMap>
+}
+
+impl Dielectric {
+    pub fn new(refractive_index: f64) -> Self {
+        Dielectric {
+            refractive_index,
+            absorption_color: vec3![255.0, 255.0, 255.0],
+            absorption_distance: 1.0,
+            priority: 0,
+            normal_map: None
+        }
+    }
+
+    pub fn set_absorption(&mut self, absorption_color: Vec3, absorption_distance: f64) {
+        self.absorption_color = absorption_color;
+        self.absorption_distance = absorption_distance;
+    }
+
+    pub fn set_priority(&mut self, priority: u32) {
+        self.priority = priority;
+    }
+
+    pub fn set_normal_map(&mut self, normal_map: Option<NormalMap>) {
+        self.normal_map = normal_map;
+    }
+
+    fn medium(&self) -> Medium {
+        // Absorption that leaves absorption color after absorption distance
+        let channel = |c: f64| -(c / 255.0).clamp(1e-6, 1.0).ln() / self.absorption_distance;
+        Medium {
+            refractive_index: self.refractive_index,
+            priority: self.priority,
+            absorption: vec3![channel(self.absorption_color.x), channel(self.absorption_color.y), channel(self.absorption_color.z)]
+        }
+    }
+}
+
+impl Mat for Dielectric {
+    fn get_color(&self, scene: &Scene, incoming_ray: Ray, hit: &HitData, ray_depth: u32) -> Vec3 {
+        let direction = incoming_ray.direction.unit();
+        let entering = direction.dot(hit.geometric_normal) < 0.0;
+        let crossing = incoming_ray.media.cross(self.medium(), entering);
+        if !crossing.is_interface {
+            let through_ray = Ray::new_in_media(hit.hit_point + direction * 1e-6, direction, crossing.after);
+            return scene.get_color_from_ray(through_ray, ray_depth);
+        }
+        if ray_depth >= scene.get_ray_depth() {
+            return vec3![0.0, 0.0, 0.0];
+        }
+
+        // Normals point out of the object, so flip them to the side the ray comes from when leaving it
+        let (normal, geometric_normal) = if entering {
+            (hit.normal, hit.geometric_normal)
+        } else {
+            (-1.0 * hit.normal, -1.0 * hit.geometric_normal)
+        };
+
+        // Refract between whatever the ray is in on either side, which isn't always the space around all objects
+        let outside = scene.get_refractive_index();
+        let ratio = crossing.before.get_refractive_index(outside) / crossing.after.get_refractive_index(outside);
+        let cos_i = (-1.0 * direction).dot(normal).clamp(0.0, 1.0);
+        let reflectance = fresnel_reflectance(cos_i, ratio);
+
+        let reflect_ray = Ray::new_in_media(hit.hit_point + geometric_normal * 1e-6, direction.reflect(normal), crossing.before);
+        let mut color = scene.get_color_from_ray(reflect_ray, ray_depth + 1) * reflectance;
+
+        // Nothing goes through on total internal reflection
+        if reflectance < 1.0 {
+            let refract_dir = direction.refract(normal, ratio);
+            let refract_ray = Ray::new_in_media(hit.hit_point - geometric_normal * 1e-6, refract_dir, crossing.after);
+            color += scene.get_color_from_ray(refract_ray, ray_depth + 1) * (1.0 - reflectance);
+        }
+        color
+    }
+
+    // Shadows only see the surfaces where light goes into the object, so this is the light left after going in and
+    // out of it straight on, tinted as if it went through absorption distance of the object.
+    fn get_transmission(&self, scene: &Scene, _: &HitData) -> Vec3 {
+        let reflectance = fresnel_reflectance(1.0, scene.get_refractive_index() / self.refractive_index);
+        self.absorption_color / 255.0 * (1.0 - reflectance).powi(2)
+    }
+
+    fn get_bsdf(&self, _: &Scene, hit: &HitData, ratio: f64) -> Bsdf {
+        let mut bsdf = Bsdf::new(hit);
+        let cos_i = (-1.0 * hit.ray.direction.unit()).dot(bsdf.get_normal()).clamp(0.0, 1.0);
+        let reflectance = fresnel_reflectance(cos_i, ratio);
+        bsdf.set_mirror(vec3![reflectance, reflectance, reflectance]);
+        bsdf.set_refraction(vec3![1.0, 1.0, 1.0] * (1.0 - reflectance), ratio);
+        bsdf
+    }
+
+    fn get_medium(&self, _: &Scene, _: &HitData) -> Option<Medium> {
+        Some(self.medium())
+    }
+
+    fn get_normal_map(&self) -> Option<NormalMap> {
+        self.normal_map
+    }
+    fn casts_caustics(&self) -> bool {
+        true
+    }
+}
+
+// Fraction of unpolarized light reflected at an interface, given the cosine of the angle the light comes in at and
+// the ratio of the refractive index it comes from to the one it goes into. 1 on total internal reflection.
+pub fn fresnel_reflectance(cos_i: f64, ratio: f64) -> f64 {
+    let sin_t2 = ratio * ratio * (1.0 - cos_i * cos_i);
+    if sin_t2 >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin_t2).sqrt();
+    let r_s = (ratio * cos_i - cos_t) / (ratio * cos_i + cos_t);
+    let r_p = (cos_i - ratio * cos_t) / (cos_i + ratio * cos_t);
+    0.5 * (r_s * r_s + r_p * r_p)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Fraction of light let through, from the Fresnel transmission coefficients instead of 1 - R.
+    fn transmittance(cos_i: f64, n1: f64, n2: f64) -> f64 {
+        let cos_t = (1.0 - (n1 / n2).powi(2) * (1.0 - cos_i * cos_i)).sqrt();
+        let t_s = 2.0 * n1 * cos_i / (n1 * cos_i + n2 * cos_t);
+        let t_p = 2.0 * n1 * cos_i / (n2 * cos_i + n1 * cos_t);
+        (n2 * cos_t) / (n1 * cos_i) * 0.5 * (t_s * t_s + t_p * t_p)
+    }
+
+    #[test]
+    fn normal_incidence_matches_schlick_r0() {
+        for (n1, n2) in [(1.0_f64, 1.5), (1.5, 1.0), (1.0, 2.42), (1.33, 1.31), (1.0, 1.0)] {
+            let expected = ((n1 - n2) / (n1 + n2)).powi(2);
+            assert!((fresnel_reflectance(1.0, n1 / n2) - expected).abs() < 1e-12, "{} -> {}", n1, n2);
+        }
+    }
+
+    #[test]
+    fn total_internal_reflection_reflects_everything() {
+        // Critical angle from glass to air is about 41.8 degrees
+        let critical_cos = (1.0 - (1.0_f64 / 1.5).powi(2)).sqrt();
+        assert_eq!(fresnel_reflectance(critical_cos - 1e-6, 1.5), 1.0);
+        assert_eq!(fresnel_reflectance(0.1, 1.5), 1.0);
+        assert_eq!(fresnel_reflectance(0.0, 1.5), 1.0);
+        assert!(fresnel_reflectance(critical_cos + 1e-3, 1.5) < 1.0);
+
+        // Going into a denser material never reflects everything, apart from grazing light
+        assert!(fresnel_reflectance(0.01, 1.0 / 1.5) < 1.0);
+        assert!((fresnel_reflectance(0.0, 1.0 / 1.5) - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn reflected_and_transmitted_add_up_to_one() {
+        for (n1, n2) in [(1.0, 1.5), (1.5, 1.0), (1.0, 2.42), (1.33, 1.5)] {
+            for i in 0..=20 {
+                let cos_i = 0.05 + 0.95 * i as f64 / 20.0;
+                let r = fresnel_reflectance(cos_i, n1 / n2);
+                assert!((0.0..=1.0).contains(&r));
+                if r < 1.0 {
+                    let t = transmittance(cos_i, n1, n2);
+                    assert!((r + t - 1.0).abs() < 1e-9, "{} -> {} at cos {}: R = {}, T = {}", n1, n2, cos_i, r, t);
+                }
+            }
+        }
+    }
+}
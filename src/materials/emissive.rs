@@ -0,0 +1,43 @@
+use crate::{Ray, Scene, Vec3, vec3};
+use crate::data_structures::Vector;
+use crate::materials::{Bsdf, Mat};
+use crate::traits::HitData;
+
+// Makes an object glow, so it's bright even in the dark and lights the rest of the scene like an area light.
+// Color is 0-255 and scaled by intensity. At an intensity of 1 the object is as bright as a white surface fully lit.
+// Only the front of surfaces glows, and spheres glow from the outside.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Emissive {
+    color: Vec3,
+    intensity: f64
+}
+
+impl Emissive {
+    pub fn new(color: Vec3, intensity: f64) -> Self {
+        Emissive { color, intensity }
+    }
+
+    // Light leaving the surface, in the same units as environment pixels.
+    pub fn get_radiance(&self) -> Vec3 {
+        self.color / 255.0 * self.intensity
+    }
+}
+
+impl Mat for Emissive {
+    fn get_color(&self, scene: &Scene, _: Ray, hit: &HitData, _: u32) -> Vec3 {
+        self.get_emission(scene, hit) * 255.0
+    }
+
+    // Glowing surfaces don't reflect anything.
+    fn get_bsdf(&self, _: &Scene, hit: &HitData, _: f64) -> Bsdf {
+        Bsdf::new(hit)
+    }
+
+    fn get_emission(&self, _: &Scene, hit: &HitData) -> Vec3 {
+        if hit.ray.direction.dot(hit.geometric_normal) < 0.0 {
+            self.get_radiance()
+        } else {
+            vec3![0.0, 0.0, 0.0]
+        }
+    }
+}
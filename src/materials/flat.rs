@@ -1,15 +1,15 @@
-use crate::{Ray, Scene, Vec3, vec3, WorldLight};
-use crate::materials::Mat;
+use crate::{Ray, Scene, Vec3, vec3};
+use crate::materials::{Bsdf, Mat, Param};
 use crate::traits::HitData;
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub struct Flat {
-    albedo: Vec3
+    albedo: Param<Vec3>
 }
 
 impl Flat {
-    pub fn new(albedo: Vec3) -> Self {
-        Flat { albedo }
+    pub fn new(albedo: impl Into<Param<Vec3>>) -> Self {
+        Flat { albedo: albedo.into() }
     }
 }
 
@@ -17,9 +17,16 @@ impl Mat for Flat {
     fn get_color(&self, scene: &Scene, _: Ray, hit: &HitData, _: u32) -> Vec3 {
         // Collect the effects all lights have on the surface at the hit point
         let mut all_light = vec3![0.0, 0.0, 0.0];
-        for l in scene.get_lights().iter() {
-            all_light += l.get_light_color(hit.hit_point);
+        for sample in scene.get_light_samples(hit) {
+            all_light += sample.color * scene.get_light_transmission(hit, &sample);
         }
-        all_light + self.albedo
+        all_light += scene.get_caustic_light(hit);
+        all_light + self.albedo.get(scene, hit)
+    }
+
+    fn get_bsdf(&self, scene: &Scene, hit: &HitData, _: f64) -> Bsdf {
+        let mut bsdf = Bsdf::new(hit);
+        bsdf.set_diffuse(self.albedo.get(scene, hit) / 255.0);
+        bsdf
     }
 }
@@ -1,48 +1,100 @@
-use crate::{Ray, Scene, Vec3, vec3, WorldLight};
-use crate::data_structures::Vector;
-use crate::materials::Mat;
+use crate::{Ray, Scene, Vec3, vec3};
+use crate::data_structures::{Medium, Vector};
+use crate::materials::{Bsdf, Mat, NormalMap, Param, shine_to_alpha};
 use crate::traits::HitData;
 
+// The parts the color of a Hall surface is made of, which add up to it. Diffuse includes the ambient light, and
+// refraction includes the light going straight through where the surface isn't there.
 #[derive(Debug, Copy, Clone)]
+pub struct HallComponents {
+    pub diffuse: Vec3,
+    pub specular: Vec3,
+    pub reflection: Vec3,
+    pub refraction: Vec3
+}
+
+impl Default for HallComponents {
+    fn default() -> Self {
+        let black = vec3![0.0, 0.0, 0.0];
+        HallComponents { diffuse: black, specular: black, reflection: black, refraction: black }
+    }
+}
+
+impl HallComponents {
+    pub fn get_total(&self) -> Vec3 {
+        self.diffuse + self.specular + self.reflection + self.refraction
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub struct Hall {
-    d_color: Vec3,
-    s_color: Vec3,
-    t_color: Vec3,
-    diffuse_factor: f64,
-    specular_factor: f64,
-    reflect_factor: f64,
-    transmissive_factor: f64,
+    d_color: Param<Vec3>,
+    s_color: Param<Vec3>,
+    t_color: Param<Vec3>,
+    diffuse_factor: Param<f64>,
+    specular_factor: Param<f64>,
+    reflect_factor: Param<f64>,
+    transmissive_factor: Param<f64>,
     refractive_index: f64,
     shine_factor: f64,
-    ambient_factor: f64
+    ambient_factor: Param<f64>,
+    priority: u32,
+    normal_map: Option<NormalMap>
 }
 
 impl Hall {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
-        d_color: Vec3, s_color: Vec3, t_color: Vec3,
-        diffuse_factor: f64, specular_factor: f64,
-        reflect_factor: f64, transmissive_factor: f64,
-        refractive_index: f64, shine_factor: f64, ambient_factor: f64
+        d_color: impl Into<Param<Vec3>>, s_color: impl Into<Param<Vec3>>, t_color: impl Into<Param<Vec3>>,
+        diffuse_factor: impl Into<Param<f64>>, specular_factor: impl Into<Param<f64>>,
+        reflect_factor: impl Into<Param<f64>>, transmissive_factor: impl Into<Param<f64>>,
+        refractive_index: f64, shine_factor: f64, ambient_factor: impl Into<Param<f64>>
     ) -> Self {
         Hall {
-            d_color, s_color, t_color,
-            diffuse_factor, specular_factor,
-            reflect_factor, transmissive_factor,
-            refractive_index, shine_factor, ambient_factor
+            d_color: d_color.into(), s_color: s_color.into(), t_color: t_color.into(),
+            diffuse_factor: diffuse_factor.into(), specular_factor: specular_factor.into(),
+            reflect_factor: reflect_factor.into(), transmissive_factor: transmissive_factor.into(),
+            refractive_index, shine_factor, ambient_factor: ambient_factor.into(),
+            priority: 0,
+            normal_map: None
         }
     }
-}
 
-impl Mat for Hall {
-    // Gets the color for a point on a surface using the Hall shading model.
+    // Where transmissive objects overlap, rays are inside the one with the highest priority. See Dielectric.
+    pub fn set_priority(&mut self, priority: u32) {
+        self.priority = priority;
+    }
+
+    pub fn set_normal_map(&mut self, normal_map: Option<NormalMap>) {
+        self.normal_map = normal_map;
+    }
+
+    fn medium(&self) -> Medium {
+        Medium { refractive_index: self.refractive_index, priority: self.priority, absorption: vec3![0.0, 0.0, 0.0] }
+    }
+
+    // Gets the color for a point on a surface using the Hall shading model, kept apart in the parts it's made of.
     // diffuse_factor * diffuse + specular_factor * specular
-    fn get_color(&self, scene: &Scene, incoming_ray: Ray, hit: &HitData, ray_depth: u32) -> Vec3 {
+    pub fn get_components(&self, scene: &Scene, incoming_ray: Ray, hit: &HitData, ray_depth: u32) -> HallComponents {
+        // View and normal are opposite, meaning external ray is entering object
+        let entering = hit.ray.direction.dot(hit.normal) < 0.0;
+        let transmissive_factor = self.transmissive_factor.get(scene, hit);
+        let crossing = incoming_ray.media.cross(self.medium(), entering);
+
+        // Inside of an object with a higher priority this surface isn't there, so just keep going
+        if transmissive_factor > 0.0 && !crossing.is_interface {
+            let direction = incoming_ray.direction.unit();
+            let through_ray = Ray::new_in_media(hit.hit_point + direction * 1e-6, direction, crossing.after);
+            return HallComponents { refraction: scene.get_color_from_ray(through_ray, ray_depth), ..HallComponents::default() };
+        }
+
         let mut final_diffuse = vec3![0.0, 0.0, 0.0];
         let mut final_specular = vec3![0.0, 0.0, 0.0];
 
-        for light in scene.get_lights() {
-            let light_color = light.get_light_color(hit.hit_point);
-            let light_dir = (light.get_position() - hit.hit_point).unit();
+        for sample in scene.get_light_samples(hit) {
+            // Lights blocked by other objects contribute nothing, unless the objects in the way let light through
+            let light_color = sample.color * scene.get_light_transmission(hit, &sample);
+            let light_dir = sample.direction;
 
             // Calculate diffuse component. Determines how matte the visible color of the surface is. Lower = less shiny, more matte.
             let light_dot_norm = light_dir.dot(hit.normal).max(0.0);
@@ -56,48 +108,91 @@ impl Mat for Hall {
             let specular = hit.normal.dot(half).max(0.0).powf(self.shine_factor);
             final_specular += light_color * specular;
         }
+        final_diffuse += scene.get_caustic_light(hit);
 
-        // Calculate ambient light
+        // Calculate ambient light, less of it where ambient occlusion finds it blocked
         let mut ambient = vec3![0.0, 0.0, 0.0];
+        let ambient_factor = self.ambient_factor.get(scene, hit);
         for a_light in scene.get_ambient_lights() {
-            ambient += self.ambient_factor * a_light.intensity;
+            ambient += ambient_factor * a_light.intensity;
+        }
+        if ambient.x.max(ambient.y).max(ambient.z) > 0.0 {
+            ambient *= scene.get_ambient_visibility(hit);
         }
 
         let ray_depth_max = scene.get_ray_depth();
 
         // If this material reflects and the max reflect depth hasn't been met, then reflect.
         let mut reflection = vec3![0.0, 0.0, 0.0];
-        if self.reflect_factor > 0.0 && ray_depth < ray_depth_max {
-            let new_ray_origin = hit.hit_point + (hit.normal * 1e-6);
+        let reflect_factor = self.reflect_factor.get(scene, hit);
+        if reflect_factor > 0.0 && ray_depth < ray_depth_max {
+            let new_ray_origin = hit.hit_point + (hit.geometric_normal * 1e-6);
             let reflect_dir = incoming_ray.direction.reflect(hit.normal);
-            let reflect_ray = Ray::new(new_ray_origin, reflect_dir);
+            let reflect_ray = Ray::new_in_media(new_ray_origin, reflect_dir, incoming_ray.media);
             let reflected_color = scene.get_color_from_ray(reflect_ray, ray_depth + 1) / 255.0;
-            reflection = reflected_color * self.reflect_factor;
+            reflection = reflected_color * reflect_factor;
         }
 
         // If this material refracts, then refract
         let mut refraction = vec3![0.0, 0.0, 0.0];
-        if self.transmissive_factor > 0.0 && ray_depth < ray_depth_max {
-            let mut refract_dir = vec3![0.0, 0.0, 0.0];
-            // View and normal are opposite, meaning external ray is entering object
-            if hit.ray.direction.dot(hit.normal) < 0.0 {
-                let ratio = scene.get_refractive_index() / self.refractive_index;
-                refract_dir = hit.ray.direction.refract(hit.normal, ratio);
-            } else {
-                // Otherwise, it is exiting object
-                let ratio = self.refractive_index / scene.get_refractive_index();
-                refract_dir = hit.ray.direction.refract(-1.0 * hit.normal, ratio);
-            }
+        if transmissive_factor > 0.0 && ray_depth < ray_depth_max {
+            // Refract between whatever the ray is in on either side of the surface
+            let outside = scene.get_refractive_index();
+            let ratio = crossing.before.get_refractive_index(outside) / crossing.after.get_refractive_index(outside);
+            let normal = if entering { hit.normal } else { -1.0 * hit.normal };
+            let refract_dir = hit.ray.direction.refract(normal, ratio);
 
-            let refracted_origin = hit.hit_point + refract_dir * 1e-6;
-            let refracted_ray = Ray::new(refracted_origin, refract_dir);
+            // Past the critical angle there is no refraction, the light is reflected back instead
+            let refracted_ray = if refract_dir.length() == 0.0 {
+                let reflect_dir = hit.ray.direction.reflect(normal);
+                Ray::new_in_media(hit.hit_point + reflect_dir * 1e-6, reflect_dir, crossing.before)
+            } else {
+                Ray::new_in_media(hit.hit_point + refract_dir * 1e-6, refract_dir, crossing.after)
+            };
             let refracted_color = scene.get_color_from_ray(refracted_ray, ray_depth + 1);
-            refraction = refracted_color * self.transmissive_factor * (self.t_color / 255.0);
+            refraction = refracted_color * transmissive_factor * (self.t_color.get(scene, hit) / 255.0);
         }
 
         // Diffuse factor + Specular factor + Transmissive factor = 1.0
-        self.d_color * (self.diffuse_factor * final_diffuse + ambient) +
-            self.s_color * (self.specular_factor * final_specular + reflection) +
+        let s_color = self.s_color.get(scene, hit);
+        HallComponents {
+            diffuse: self.d_color.get(scene, hit) * (self.diffuse_factor.get(scene, hit) * final_diffuse + ambient),
+            specular: s_color * (self.specular_factor.get(scene, hit) * final_specular),
+            reflection: s_color * reflection,
             refraction
+        }
+    }
+}
+
+impl Mat for Hall {
+    fn get_color(&self, scene: &Scene, incoming_ray: Ray, hit: &HitData, ray_depth: u32) -> Vec3 {
+        self.get_components(scene, incoming_ray, hit, ray_depth).get_total()
+    }
+
+    // Light passing through is tinted by the transmission color.
+    fn get_transmission(&self, scene: &Scene, hit: &HitData) -> Vec3 {
+        self.t_color.get(scene, hit) / 255.0 * self.transmissive_factor.get(scene, hit)
+    }
+
+    fn get_bsdf(&self, scene: &Scene, hit: &HitData, ratio: f64) -> Bsdf {
+        let s_color = self.s_color.get(scene, hit) / 255.0;
+        let mut bsdf = Bsdf::new(hit);
+        bsdf.set_diffuse(self.d_color.get(scene, hit) / 255.0 * self.diffuse_factor.get(scene, hit));
+        bsdf.set_glossy(s_color * self.specular_factor.get(scene, hit), shine_to_alpha(self.shine_factor), false);
+        bsdf.set_mirror(s_color * self.reflect_factor.get(scene, hit));
+        bsdf.set_refraction(self.t_color.get(scene, hit) / 255.0 * self.transmissive_factor.get(scene, hit), ratio);
+        bsdf
+    }
+
+    // Only objects that let light through have an inside.
+    fn get_medium(&self, scene: &Scene, hit: &HitData) -> Option<Medium> {
+        if self.transmissive_factor.get(scene, hit) > 0.0 { Some(self.medium()) } else { None }
+    }
+
+    fn get_normal_map(&self) -> Option<NormalMap> {
+        self.normal_map
+    }
+    fn casts_caustics(&self) -> bool {
+        self.reflect_factor.can_be_positive() || self.transmissive_factor.can_be_positive()
     }
 }
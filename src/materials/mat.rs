@@ -1,16 +1,44 @@
-use crate::{Ray, Scene, Vec3};
-use crate::materials::{Flat, Hall, Phong};
+use crate::{Ray, Scene, Vec3, vec3};
+use crate::data_structures::Medium;
+use crate::materials::{Bsdf, Dielectric, Emissive, Flat, Hall, NormalMap, Pbr, Phong};
 use crate::traits::HitData;
 
 pub trait Mat {
     fn get_color(&self, scene: &Scene, incoming_ray: Ray, hit: &HitData, reflect_depth: u32) -> Vec3;
+    // How much light passes through the material per color channel, for shadows. Opaque materials let nothing through.
+    fn get_transmission(&self, _: &Scene, _: &HitData) -> Vec3 {
+        vec3![0.0, 0.0, 0.0]
+    }
+    // How the surface scatters light at the hit, for the path tracer. Ratio is the refractive index on the side the
+    // ray comes from divided by the one on the other side.
+    fn get_bsdf(&self, scene: &Scene, hit: &HitData, ratio: f64) -> Bsdf;
+    // What the inside of the material is made of, for materials light can go through.
+    fn get_medium(&self, _: &Scene, _: &HitData) -> Option<Medium> {
+        None
+    }
+    // Light the surface gives off towards the ray, in the same units as environment pixels.
+    fn get_emission(&self, _: &Scene, _: &HitData) -> Vec3 {
+        vec3![0.0, 0.0, 0.0]
+    }
+    // Normal or bump map that bends the shading normal, applied by the scene before get_color.
+    fn get_normal_map(&self) -> Option<NormalMap> {
+        None
+    }
+    // If the material can reflect or refract light sharply anywhere, focusing it into caustics. Photons are aimed at
+    // objects made of these.
+    fn casts_caustics(&self) -> bool {
+        false
+    }
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum Material {
     Flat(Flat),
     Phong(Phong),
-    Hall(Hall)
+    Hall(Hall),
+    Pbr(Pbr),
+    Dielectric(Dielectric),
+    Emissive(Emissive)
 }
 
 impl Mat for Material {
@@ -18,7 +46,76 @@ impl Mat for Material {
         match self {
             Material::Flat(mat) => mat.get_color(scene, incoming_ray, hit, reflect_depth),
             Material::Phong(mat) => mat.get_color(scene, incoming_ray, hit, reflect_depth),
-            Material::Hall(mat) => mat.get_color(scene, incoming_ray, hit, reflect_depth)
+            Material::Hall(mat) => mat.get_color(scene, incoming_ray, hit, reflect_depth),
+            Material::Pbr(mat) => mat.get_color(scene, incoming_ray, hit, reflect_depth),
+            Material::Dielectric(mat) => mat.get_color(scene, incoming_ray, hit, reflect_depth),
+            Material::Emissive(mat) => mat.get_color(scene, incoming_ray, hit, reflect_depth)
+        }
+    }
+
+    fn get_transmission(&self, scene: &Scene, hit: &HitData) -> Vec3 {
+        match self {
+            Material::Flat(mat) => mat.get_transmission(scene, hit),
+            Material::Phong(mat) => mat.get_transmission(scene, hit),
+            Material::Hall(mat) => mat.get_transmission(scene, hit),
+            Material::Pbr(mat) => mat.get_transmission(scene, hit),
+            Material::Dielectric(mat) => mat.get_transmission(scene, hit),
+            Material::Emissive(mat) => mat.get_transmission(scene, hit)
+        }
+    }
+
+    fn get_bsdf(&self, scene: &Scene, hit: &HitData, ratio: f64) -> Bsdf {
+        match self {
+            Material::Flat(mat) => mat.get_bsdf(scene, hit, ratio),
+            Material::Phong(mat) => mat.get_bsdf(scene, hit, ratio),
+            Material::Hall(mat) => mat.get_bsdf(scene, hit, ratio),
+            Material::Pbr(mat) => mat.get_bsdf(scene, hit, ratio),
+            Material::Dielectric(mat) => mat.get_bsdf(scene, hit, ratio),
+            Material::Emissive(mat) => mat.get_bsdf(scene, hit, ratio)
+        }
+    }
+
+    fn get_medium(&self, scene: &Scene, hit: &HitData) -> Option<Medium> {
+        match self {
+            Material::Flat(mat) => mat.get_medium(scene, hit),
+            Material::Phong(mat) => mat.get_medium(scene, hit),
+            Material::Hall(mat) => mat.get_medium(scene, hit),
+            Material::Pbr(mat) => mat.get_medium(scene, hit),
+            Material::Dielectric(mat) => mat.get_medium(scene, hit),
+            Material::Emissive(mat) => mat.get_medium(scene, hit)
+        }
+    }
+
+    fn get_emission(&self, scene: &Scene, hit: &HitData) -> Vec3 {
+        match self {
+            Material::Flat(mat) => mat.get_emission(scene, hit),
+            Material::Phong(mat) => mat.get_emission(scene, hit),
+            Material::Hall(mat) => mat.get_emission(scene, hit),
+            Material::Pbr(mat) => mat.get_emission(scene, hit),
+            Material::Dielectric(mat) => mat.get_emission(scene, hit),
+            Material::Emissive(mat) => mat.get_emission(scene, hit)
+        }
+    }
+
+    fn get_normal_map(&self) -> Option<NormalMap> {
+        match self {
+            Material::Flat(mat) => mat.get_normal_map(),
+            Material::Phong(mat) => mat.get_normal_map(),
+            Material::Hall(mat) => mat.get_normal_map(),
+            Material::Pbr(mat) => mat.get_normal_map(),
+            Material::Dielectric(mat) => mat.get_normal_map(),
+            Material::Emissive(mat) => mat.get_normal_map()
+        }
+    }
+
+    fn casts_caustics(&self) -> bool {
+        match self {
+            Material::Flat(mat) => mat.casts_caustics(),
+            Material::Phong(mat) => mat.casts_caustics(),
+            Material::Hall(mat) => mat.casts_caustics(),
+            Material::Pbr(mat) => mat.casts_caustics(),
+            Material::Dielectric(mat) => mat.casts_caustics(),
+            Material::Emissive(mat) => mat.casts_caustics()
         }
     }
 }
@@ -1,6 +1,18 @@
 mod mat;
 pub use mat::*;
 
+mod texture;
+pub use texture::*;
+
+mod procedural;
+pub use procedural::*;
+
+mod bsdf;
+pub use bsdf::*;
+
+mod normal_map;
+pub use normal_map::*;
+
 mod flat;
 pub use flat::*;
 
@@ -10,3 +22,11 @@ pub use phong::*;
 mod hall;
 pub use hall::*;
 
+mod pbr;
+pub use pbr::*;
+
+mod dielectric;
+pub use dielectric::*;
+
+mod emissive;
+pub use emissive::*;
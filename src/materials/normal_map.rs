@@ -0,0 +1,97 @@
+use crate::{Scene, Vec3};
+use crate::data_structures::Vector;
+use crate::traits::HitData;
+
+// How far texture coordinates and object space points are moved to find the slope of a bump map.
+const BUMP_DELTA: f64 = 1e-3;
+
+// Adds surface detail to a material by bending the normal used for shading, without changing the geometry.
+// Textures are scene textures referred to by index, like material colors.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum NormalMap {
+    // A tangent space normal map. Each color is a direction, with (128, 128, 255) pointing straight out of the
+    // surface, red towards the tangent and green towards the bitangent. Strength scales how far normals lean.
+    Normal { texture: usize, strength: f64 },
+    // A height map where brighter is higher. Strength is how much higher white is than black.
+    Bump { texture: usize, strength: f64 }
+}
+
+impl NormalMap {
+    // Shading normal at the hit after applying the map.
+    pub fn apply(&self, scene: &Scene, hit: &HitData) -> Vec3 {
+        let (normal, tangent, bitangent) = (hit.normal, hit.tangent, hit.bitangent);
+        match *self {
+            NormalMap::Normal { texture, strength } => {
+                let color = scene.get_texture(texture).get_color(hit) / 255.0 * 2.0 - 1.0;
+                let bent = tangent * (color.x * strength) + bitangent * (color.y * strength) + normal * color.z.max(0.0);
+                if bent.length() > 1e-9 { bent.unit() } else { normal }
+            },
+            NormalMap::Bump { texture, strength } => {
+                // Slope of the height map along the tangent and bitangent. Image textures change with the
+                // texture coordinates and procedural ones with the object space point, so move both.
+                let height = |du: f64, dv: f64| {
+                    let mut moved = hit.clone();
+                    moved.uv = (hit.uv.0 + du, hit.uv.1 + dv);
+                    moved.local_point = hit.local_point + tangent * du + bitangent * dv;
+                    let color = scene.get_texture(texture).get_color(&moved);
+                    (color.x + color.y + color.z) / (3.0 * 255.0)
+                };
+                let base = height(0.0, 0.0);
+                let slope_u = (height(BUMP_DELTA, 0.0) - base) / BUMP_DELTA;
+                let slope_v = (height(0.0, BUMP_DELTA) - base) / BUMP_DELTA;
+
+                // Lean the normal away from the uphill directions
+                let bent = normal - tangent * (slope_u * strength) - bitangent * (slope_v * strength);
+                if bent.length() > 1e-9 { bent.unit() } else { normal }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec3;
+    use crate::materials::{ImageTexture, Texture};
+
+    // Scene holding a single texture of one pixel, and a hit whose tangent frame is given.
+    fn setup(color: Vec3, tangent: Vec3, bitangent: Vec3, normal: Vec3) -> (Scene, HitData) {
+        let mut scene = Scene::new((1, 1), 100.0, vec3![0.0, 0.0, 0.0], 90.0, 1, 1, 1.0, false);
+        scene.push_texture(Texture::Image(ImageTexture::new(vec![color], 1, 1)));
+        let mut hit = HitData::new();
+        hit.tangent = tangent;
+        hit.bitangent = bitangent;
+        hit.normal = normal;
+        (scene, hit)
+    }
+
+    fn assert_direction(direction: Vec3, expected: Vec3) {
+        assert!((direction - expected.unit()).length() < 1e-9, "{:?} is not {:?}", direction, expected.unit());
+    }
+
+    #[test]
+    fn flat_color_keeps_the_normal() {
+        let (scene, hit) = setup(vec3![127.5, 127.5, 255.0], vec3![1.0, 0.0, 0.0], vec3![0.0, 1.0, 0.0], vec3![0.0, 0.0, 1.0]);
+        let map = NormalMap::Normal { texture: 0, strength: 1.0 };
+        assert_direction(map.apply(&scene, &hit), vec3![0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn colors_lean_towards_the_tangent_and_bitangent() {
+        let (x, y, z) = (vec3![1.0, 0.0, 0.0], vec3![0.0, 1.0, 0.0], vec3![0.0, 0.0, 1.0]);
+        let (scene, hit) = setup(vec3![255.0, 127.5, 255.0], x, y, z);
+        assert_direction(NormalMap::Normal { texture: 0, strength: 1.0 }.apply(&scene, &hit), vec3![1.0, 0.0, 1.0]);
+        assert_direction(NormalMap::Normal { texture: 0, strength: 0.5 }.apply(&scene, &hit), vec3![0.5, 0.0, 1.0]);
+
+        let (scene, hit) = setup(vec3![127.5, 0.0, 255.0], x, y, z);
+        assert_direction(NormalMap::Normal { texture: 0, strength: 1.0 }.apply(&scene, &hit), vec3![0.0, -1.0, 1.0]);
+    }
+
+    #[test]
+    fn normals_follow_the_surface_frame() {
+        // Same color on a surface facing +X, with the tangent along -Z and bitangent along +Y
+        let (scene, hit) = setup(vec3![255.0, 127.5, 255.0], vec3![0.0, 0.0, -1.0], vec3![0.0, 1.0, 0.0], vec3![1.0, 0.0, 0.0]);
+        let map = NormalMap::Normal { texture: 0, strength: 1.0 };
+        assert_direction(map.apply(&scene, &hit), vec3![1.0, 0.0, -1.0]);
+    }
+}
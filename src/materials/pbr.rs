@@ -0,0 +1,132 @@
+use std::f64::consts::PI;
+use crate::{Ray, Scene, Vec3, vec3};
+use crate::data_structures::Vector;
+use crate::materials::{Bsdf, Mat, NormalMap, Param, burley_diffuse, ggx_distribution, schlick_fresnel, smith_masking};
+use crate::traits::HitData;
+
+// Roughness is kept above this so the highlight from a point light never becomes infinitely small and bright.
+const MIN_ROUGHNESS: f64 = 0.02;
+// How much light dielectrics like plastic or paint reflect when looked at straight on.
+const DIELECTRIC_F0: f64 = 0.04;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DiffuseModel {
+    Lambert, // Equally bright from every angle
+    Burley // Disney's diffuse, rough surfaces get brighter at grazing angles and smooth ones darker
+}
+
+// Physically based material using the metallic/roughness workflow, the same one most modeling tools export.
+// Highlights use the GGX (Trowbridge-Reitz) microfacet distribution with Smith masking and shadowing and
+// Schlick's Fresnel approximation, so they conserve energy without tuning factors by hand.
+//
+// Base color is 0-255. Metals tint their reflections with it and have no diffuse, everything else is diffuse
+// with a white highlight. Roughness goes from 0 for a mirror to 1 for completely matte.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Pbr {
+    base_color: Param<Vec3>,
+    metallic: Param<f64>,
+    roughness: Param<f64>,
+    diffuse_model: DiffuseModel,
+    normal_map: Option<NormalMap>
+}
+
+impl Pbr {
+    pub fn new(base_color: impl Into<Param<Vec3>>, metallic: impl Into<Param<f64>>, roughness: impl Into<Param<f64>>) -> Self {
+        Pbr {
+            base_color: base_color.into(),
+            metallic: metallic.into(),
+            roughness: roughness.into(),
+            diffuse_model: DiffuseModel::Burley,
+            normal_map: None
+        }
+    }
+
+    pub fn set_diffuse_model(&mut self, diffuse_model: DiffuseModel) {
+        self.diffuse_model = diffuse_model;
+    }
+
+    pub fn set_normal_map(&mut self, normal_map: Option<NormalMap>) {
+        self.normal_map = normal_map;
+    }
+}
+
+impl Mat for Pbr {
+    // Lights use the same units as the other materials, where a light of 1 lights a white Lambert surface facing it fully.
+    // That's the BRDF times pi, so the pi in the Lambert and GGX terms cancels out.
+    fn get_color(&self, scene: &Scene, incoming_ray: Ray, hit: &HitData, ray_depth: u32) -> Vec3 {
+        let base_color = self.base_color.get(scene, hit) / 255.0;
+        let metallic = self.metallic.get(scene, hit).clamp(0.0, 1.0);
+        let roughness = self.roughness.get(scene, hit).clamp(MIN_ROUGHNESS, 1.0);
+        let alpha = roughness * roughness;
+
+        // Metals reflect their base color, dielectrics a bit of white
+        let f0 = vec3![DIELECTRIC_F0, DIELECTRIC_F0, DIELECTRIC_F0] * (1.0 - metallic) + base_color * metallic;
+        let diffuse_color = base_color * (1.0 - metallic);
+
+        let normal = hit.normal;
+        let view_dir = (hit.ray.origin - hit.hit_point).unit();
+        let n_dot_v = normal.dot(view_dir).max(1e-4);
+
+        let mut total = vec3![0.0, 0.0, 0.0];
+        for sample in scene.get_light_samples(hit) {
+            let light_dir = sample.direction;
+            let n_dot_l = normal.dot(light_dir);
+            if n_dot_l <= 0.0 {
+                continue;
+            }
+            let light_color = sample.color * scene.get_light_transmission(hit, &sample);
+
+            let half = (light_dir + view_dir).unit();
+            let n_dot_h = normal.dot(half).max(0.0);
+            let l_dot_h = light_dir.dot(half).max(0.0);
+
+            let fresnel = schlick_fresnel(f0, l_dot_h);
+            let specular = fresnel * (ggx_distribution(n_dot_h, alpha) * smith_masking(n_dot_l, n_dot_v, alpha) / (4.0 * n_dot_l * n_dot_v));
+
+            // Light that gets reflected at the surface isn't there to be diffused
+            let diffuse = diffuse_color * (vec3![1.0, 1.0, 1.0] - fresnel) * match self.diffuse_model {
+                DiffuseModel::Lambert => 1.0,
+                DiffuseModel::Burley => burley_diffuse(n_dot_l, n_dot_v, l_dot_h, roughness)
+            };
+
+            total += (diffuse + specular * PI) * light_color * n_dot_l;
+        }
+
+        // Focused light and ambient lights only light the diffuse part
+        total += diffuse_color * scene.get_caustic_light(hit);
+        for a_light in scene.get_ambient_lights() {
+            total += diffuse_color * a_light.intensity;
+        }
+        total *= 255.0;
+
+        // Smooth surfaces also reflect other objects. Rougher surfaces blur their reflections, which a single ray
+        // can't show, so they fade out as roughness goes up.
+        let smoothness = (1.0 - roughness) * (1.0 - roughness);
+        if smoothness > 0.01 && ray_depth < scene.get_ray_depth() {
+            let reflect_origin = hit.hit_point + (hit.geometric_normal * 1e-6);
+            let reflect_ray = Ray::new_in_media(reflect_origin, incoming_ray.direction.reflect(normal), incoming_ray.media);
+            let reflected_color = scene.get_color_from_ray(reflect_ray, ray_depth + 1);
+            total += reflected_color * schlick_fresnel(f0, n_dot_v) * smoothness;
+        }
+        total
+    }
+
+    fn get_bsdf(&self, scene: &Scene, hit: &HitData, _: f64) -> Bsdf {
+        let base_color = self.base_color.get(scene, hit) / 255.0;
+        let metallic = self.metallic.get(scene, hit).clamp(0.0, 1.0);
+        let roughness = self.roughness.get(scene, hit).clamp(MIN_ROUGHNESS, 1.0);
+        let f0 = vec3![DIELECTRIC_F0, DIELECTRIC_F0, DIELECTRIC_F0] * (1.0 - metallic) + base_color * metallic;
+
+        let mut bsdf = Bsdf::new(hit);
+        bsdf.set_diffuse(base_color * (1.0 - metallic));
+        if self.diffuse_model == DiffuseModel::Burley {
+            bsdf.set_burley(roughness);
+        }
+        bsdf.set_glossy(f0, roughness * roughness, true);
+        bsdf
+    }
+
+    fn get_normal_map(&self) -> Option<NormalMap> {
+        self.normal_map
+    }
+}
@@ -1,21 +1,37 @@
-use crate::{Ray, Scene, Vec3, vec3, WorldLight};
+use crate::{Ray, Scene, Vec3, vec3};
 use crate::data_structures::Vector;
-use crate::materials::Mat;
+use crate::materials::{Bsdf, Mat, NormalMap, Param, shine_to_alpha};
 use crate::traits::HitData;
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub struct Phong {
-    albedo: Vec3,
-    diffuse_factor: f64,
-    specular_factor: f64,
+    albedo: Param<Vec3>,
+    diffuse_factor: Param<f64>,
+    specular_factor: Param<f64>,
     shine_factor: f64,
-    reflect_factor: f64,
-    ambient_factor: f64
+    reflect_factor: Param<f64>,
+    ambient_factor: Param<f64>,
+    normal_map: Option<NormalMap>
 }
 
 impl Phong {
-    pub fn new(albedo: Vec3, diffuse_factor: f64, specular_factor: f64, shine_factor: f64, reflect_factor: f64, ambient_factor: f64) -> Self {
-        Phong { albedo, diffuse_factor, specular_factor, shine_factor, reflect_factor, ambient_factor }
+    pub fn new(
+        albedo: impl Into<Param<Vec3>>, diffuse_factor: impl Into<Param<f64>>, specular_factor: impl Into<Param<f64>>,
+        shine_factor: f64, reflect_factor: impl Into<Param<f64>>, ambient_factor: impl Into<Param<f64>>
+    ) -> Self {
+        Phong {
+            albedo: albedo.into(),
+            diffuse_factor: diffuse_factor.into(),
+            specular_factor: specular_factor.into(),
+            shine_factor,
+            reflect_factor: reflect_factor.into(),
+            ambient_factor: ambient_factor.into(),
+            normal_map: None
+        }
+    }
+
+    pub fn set_normal_map(&mut self, normal_map: Option<NormalMap>) {
+        self.normal_map = normal_map;
     }
 }
 
@@ -26,9 +42,10 @@ impl Mat for Phong {
         let mut final_diffuse = vec3![0.0, 0.0, 0.0];
         let mut final_specular = vec3![0.0, 0.0, 0.0];
 
-        for light in scene.get_lights() {
-            let light_color = light.get_light_color(hit.hit_point);
-            let light_dir = (light.get_position() - hit.hit_point).unit();
+        for sample in scene.get_light_samples(hit) {
+            // Lights blocked by other objects contribute nothing, unless the objects in the way let light through
+            let light_color = sample.color * scene.get_light_transmission(hit, &sample);
+            let light_dir = sample.direction;
 
             // Calculate diffuse component. Determines how matte the visible color of the surface is. Lower = less shiny, more matte.
             let light_dot_norm = light_dir.dot(hit.normal).max(0.0);
@@ -41,7 +58,32 @@ impl Mat for Phong {
             let specular = view_dir.dot(reflect).max(0.0).powf(self.shine_factor);
             final_specular += light_color * specular;
         }
+        final_diffuse += scene.get_caustic_light(hit);
+
+        // Less ambient light where ambient occlusion finds it blocked
+        let mut ambient = self.ambient_factor.get(scene, hit);
+        if ambient > 0.0 {
+            ambient *= scene.get_ambient_visibility(hit);
+        }
+
+        self.albedo.get(scene, hit) * (
+            self.diffuse_factor.get(scene, hit) * final_diffuse +
+            self.specular_factor.get(scene, hit) * final_specular +
+            ambient
+        )
+    }
+
+    // The highlight becomes a GGX lobe of about the same width.
+    fn get_bsdf(&self, scene: &Scene, hit: &HitData, _: f64) -> Bsdf {
+        let albedo = self.albedo.get(scene, hit) / 255.0;
+        let mut bsdf = Bsdf::new(hit);
+        bsdf.set_diffuse(albedo * self.diffuse_factor.get(scene, hit));
+        bsdf.set_glossy(albedo * self.specular_factor.get(scene, hit), shine_to_alpha(self.shine_factor), false);
+        bsdf.set_mirror(albedo * self.reflect_factor.get(scene, hit));
+        bsdf
+    }
 
-        self.albedo * (self.diffuse_factor * final_diffuse + self.specular_factor * final_specular + self.ambient_factor)
+    fn get_normal_map(&self) -> Option<NormalMap> {
+        self.normal_map
     }
 }
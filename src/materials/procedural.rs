@@ -0,0 +1,240 @@
+use std::f64::consts::PI;
+use crate::Vec3;
+use crate::data_structures::Vector;
+use crate::traits::HitData;
+
+// Shapes a procedural texture can have. Everything except Checker2D is worked out from where the hit is on the
+// object before it was moved, rotated or scaled, so the pattern sticks to the object.
+#[derive(Debug, Copy, Clone)]
+pub enum Pattern {
+    Checker2D, // Squares over the texture coordinates
+    Checker3D, // Cubes through the object
+    Noise { octaves: u32 }, // Smooth random blobs, with finer detail for every octave
+    Turbulence { octaves: u32 }, // Like noise but with sharp creases
+    Marble { octaves: u32, turbulence: f64 }, // Veins along X that turbulence bends around
+    Wood { turbulence: f64 }, // Rings around the Y axis
+    Gradient { from: Vec3, to: Vec3 } // Goes from the first color at one point to the second color at another
+}
+
+// A texture that blends between two colors using a pattern, instead of looking up an image.
+// Scale is how big the features of the pattern are, like the size of a checker square or a wood ring.
+#[derive(Debug, Copy, Clone)]
+pub struct ProceduralTexture {
+    pattern: Pattern,
+    color_a: Vec3,
+    color_b: Vec3,
+    scale: f64
+}
+
+impl ProceduralTexture {
+    pub fn new(pattern: Pattern, color_a: Vec3, color_b: Vec3) -> Self {
+        ProceduralTexture { pattern, color_a, color_b, scale: 1.0 }
+    }
+
+    pub fn set_scale(&mut self, scale: f64) {
+        self.scale = scale;
+    }
+
+    pub fn get_color(&self, hit: &HitData) -> Vec3 {
+        let t = self.get_value(hit).clamp(0.0, 1.0);
+        self.color_a * (1.0 - t) + self.color_b * t
+    }
+
+    // How much of the second color to use, from 0 to 1.
+    fn get_value(&self, hit: &HitData) -> f64 {
+        let p = hit.local_point / self.scale;
+        match self.pattern {
+            Pattern::Checker2D => {
+                let (u, v) = (hit.uv.0 / self.scale, hit.uv.1 / self.scale);
+                ((u.floor() + v.floor()) as i64).rem_euclid(2) as f64
+            },
+            Pattern::Checker3D => {
+                // Nudge points off the faces of the cubes, otherwise surfaces lying exactly on them flicker
+                let p = p + 1e-6;
+                ((p.x.floor() + p.y.floor() + p.z.floor()) as i64).rem_euclid(2) as f64
+            },
+            Pattern::Noise { octaves } => 0.5 + 0.5 * fbm(p, octaves),
+            Pattern::Turbulence { octaves } => turbulence(p, octaves),
+            Pattern::Marble { octaves, turbulence: amount } => {
+                0.5 + 0.5 * (2.0 * PI * (p.x + amount * turbulence(p, octaves))).sin()
+            },
+            Pattern::Wood { turbulence: amount } => {
+                let rings = (p.x * p.x + p.z * p.z).sqrt() + amount * perlin(p);
+                0.5 + 0.5 * (2.0 * PI * rings).sin()
+            },
+            Pattern::Gradient { from, to } => {
+                let direction = to - from;
+                (hit.local_point - from).dot(direction) / direction.dot(direction)
+            }
+        }
+    }
+}
+
+// Ken Perlin's improved noise. Smoothly changes between about -1 and 1, and is 0 at every whole number point.
+// Instead of a permutation table, the gradient at each lattice corner comes from hashing its coordinates.
+pub fn perlin(p: Vec3) -> f64 {
+    let (x0, y0, z0) = (p.x.floor(), p.y.floor(), p.z.floor());
+    let (x, y, z) = (p.x - x0, p.y - y0, p.z - z0);
+    let (xi, yi, zi) = (x0 as i64, y0 as i64, z0 as i64);
+    let (u, v, w) = (fade(x), fade(y), fade(z));
+
+    let corner = |dx: i64, dy: i64, dz: i64| {
+        gradient(hash(xi + dx, yi + dy, zi + dz), x - dx as f64, y - dy as f64, z - dz as f64)
+    };
+    lerp(w,
+        lerp(v, lerp(u, corner(0, 0, 0), corner(1, 0, 0)), lerp(u, corner(0, 1, 0), corner(1, 1, 0))),
+        lerp(v, lerp(u, corner(0, 0, 1), corner(1, 0, 1)), lerp(u, corner(0, 1, 1), corner(1, 1, 1)))
+    )
+}
+
+// Fractal noise. Each octave adds noise at twice the detail and half the strength. Stays between about -1 and 1.
+pub fn fbm(p: Vec3, octaves: u32) -> f64 {
+    let (mut total, mut amplitude, mut frequency, mut max) = (0.0, 1.0, 1.0, 0.0);
+    for _ in 0..octaves.max(1) {
+        total += amplitude * perlin(p * frequency);
+        max += amplitude;
+        amplitude *= 0.5;
+        frequency *= 2.0;
+    }
+    total / max
+}
+
+// Like fbm but adds up how far each octave is from 0, which gives creases where the noise crosses 0.
+// Stays between 0 and about 1.
+pub fn turbulence(p: Vec3, octaves: u32) -> f64 {
+    let (mut total, mut amplitude, mut frequency, mut max) = (0.0, 1.0, 1.0, 0.0);
+    for _ in 0..octaves.max(1) {
+        total += amplitude * perlin(p * frequency).abs();
+        max += amplitude;
+        amplitude *= 0.5;
+        frequency *= 2.0;
+    }
+    total / max
+}
+
+// Eases in and out of each lattice cell so the noise has no visible grid lines.
+fn fade(t: f64) -> f64 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(t: f64, a: f64, b: f64) -> f64 {
+    a + t * (b - a)
+}
+
+// Scrambles lattice coordinates into a number that looks random, but is always the same for the same point.
+fn hash(x: i64, y: i64, z: i64) -> u64 {
+    let mut h = (x as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+        ^ (y as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F)
+        ^ (z as u64).wrapping_mul(0x1656_67B1_9E37_79F9);
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xFF51_AFD7_ED55_8CCD);
+    h ^= h >> 33;
+    h
+}
+
+// Dot product with one of the 12 directions to the edges of a cube, picked by the hash.
+fn gradient(hash: u64, x: f64, y: f64, z: f64) -> f64 {
+    match hash % 12 {
+        0 => x + y,
+        1 => -x + y,
+        2 => x - y,
+        3 => -x - y,
+        4 => x + z,
+        5 => -x + z,
+        6 => x - z,
+        7 => -x - z,
+        8 => y + z,
+        9 => -y + z,
+        10 => y - z,
+        _ => -y - z
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+    use crate::vec3;
+    use super::*;
+
+    fn random_points(count: usize) -> Vec<Vec3> {
+        let mut rng = StdRng::seed_from_u64(7);
+        (0..count).map(|_| vec3![rng.gen_range(-50.0..50.0), rng.gen_range(-50.0..50.0), rng.gen_range(-50.0..50.0)]).collect()
+    }
+
+    fn value_at(pattern: Pattern, local_point: Vec3, uv: (f64, f64)) -> f64 {
+        let mut hit = HitData::new();
+        hit.local_point = local_point;
+        hit.uv = uv;
+        ProceduralTexture::new(pattern, vec3![0.0, 0.0, 0.0], vec3![255.0, 255.0, 255.0]).get_value(&hit)
+    }
+
+    #[test]
+    fn noise_is_deterministic_and_in_range() {
+        for p in random_points(2000) {
+            let n = perlin(p);
+            assert_eq!(n, perlin(p));
+            assert!((-1.0..=1.0).contains(&n), "perlin({:?}) = {}", p, n);
+            assert!((-1.0..=1.0).contains(&fbm(p, 5)));
+            assert!((0.0..=1.0).contains(&turbulence(p, 5)));
+
+            for pattern in [Pattern::Noise { octaves: 4 }, Pattern::Turbulence { octaves: 4 }, Pattern::Marble { octaves: 4, turbulence: 5.0 }, Pattern::Wood { turbulence: 0.3 }] {
+                let value = value_at(pattern, p, (0.0, 0.0));
+                assert_eq!(value, value_at(pattern, p, (0.0, 0.0)));
+                assert!((0.0..=1.0).contains(&value), "{:?} at {:?} = {}", pattern, p, value);
+            }
+        }
+    }
+
+    #[test]
+    fn noise_is_zero_on_the_lattice_and_varies_between() {
+        for (x, y, z) in [(0.0, 0.0, 0.0), (3.0, -2.0, 7.0), (-11.0, -4.0, -1.0)] {
+            assert_eq!(perlin(vec3![x, y, z]), 0.0);
+        }
+        let values: Vec<f64> = random_points(100).into_iter().map(perlin).collect();
+        assert!(values.iter().any(|v| *v > 0.1) && values.iter().any(|v| *v < -0.1));
+    }
+
+    #[test]
+    fn checker_2d_alternates_over_uvs() {
+        let checker = |u, v| value_at(Pattern::Checker2D, vec3![0.0, 0.0, 0.0], (u, v));
+        assert_eq!(checker(0.5, 0.5), 0.0);
+        assert_eq!(checker(1.5, 0.5), 1.0);
+        assert_eq!(checker(0.5, 1.5), 1.0);
+        assert_eq!(checker(1.5, 1.5), 0.0);
+        // Negative coordinates keep alternating instead of mirroring around 0
+        assert_eq!(checker(-0.5, 0.5), 1.0);
+        assert_eq!(checker(-1.5, 0.5), 0.0);
+        assert_eq!(checker(-0.5, -0.5), 0.0);
+        assert_eq!(checker(-1.5, -0.5), 1.0);
+        assert_eq!(checker(-2.5, -0.5), 0.0);
+    }
+
+    #[test]
+    fn checker_3d_alternates_along_every_axis() {
+        let checker = |x, y, z| value_at(Pattern::Checker3D, vec3![x, y, z], (0.0, 0.0));
+        assert_eq!(checker(0.5, 0.5, 0.5), 0.0);
+        for step in [vec3![1.0, 0.0, 0.0], vec3![0.0, 1.0, 0.0], vec3![0.0, 0.0, 1.0]] {
+            for i in -4..4 {
+                let p = vec3![0.5, 0.5, 0.5] + step * i as f64;
+                assert_eq!(checker(p.x, p.y, p.z), (i as f64).rem_euclid(2.0), "at {:?}", p);
+            }
+        }
+        assert_eq!(checker(-0.5, -0.5, 0.5), 0.0);
+        assert_eq!(checker(-0.5, -0.5, -0.5), 1.0);
+
+        // Points right on a face count as the cube above it
+        assert_eq!(checker(1.0, 0.5, 0.5), 1.0);
+        assert_eq!(checker(0.0, 0.5, 0.5), 0.0);
+    }
+
+    #[test]
+    fn scale_sets_the_checker_size() {
+        let mut hit = HitData::new();
+        hit.local_point = vec3![1.5, 0.5, 0.5];
+        let mut texture = ProceduralTexture::new(Pattern::Checker3D, vec3![0.0, 0.0, 0.0], vec3![255.0, 255.0, 255.0]);
+        assert_eq!(texture.get_color(&hit), vec3![255.0, 255.0, 255.0]);
+        texture.set_scale(2.0);
+        assert_eq!(texture.get_color(&hit), vec3![0.0, 0.0, 0.0]);
+    }
+}
@@ -0,0 +1,223 @@
+use std::path::Path;
+use image::ImageResult;
+use crate::{Scene, Vec3, vec3};
+use crate::materials::ProceduralTexture;
+use crate::traits::HitData;
+use crate::utils::deg_to_rad;
+
+// How texture coordinates outside of 0 to 1 are looked up.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum WrapMode {
+    Wrap, // The image repeats
+    Clamp // The edge pixels stretch out forever
+}
+
+// A PNG or JPEG image put on a surface using the texture coordinates of the hit.
+// (0, 0) is the bottom left of the image and (1, 1) the top right. Colors are 0-255 like material colors.
+pub struct ImageTexture {
+    pixels: Vec<Vec3>,
+    width: usize,
+    height: usize,
+    wrap: WrapMode,
+    scale: (f64, f64),
+    offset: (f64, f64),
+    rotation: f64, // In radians
+}
+
+impl ImageTexture {
+    // Builds a texture from pixels, row by row starting at the top of the image.
+    pub fn new(pixels: Vec<Vec3>, width: usize, height: usize) -> Self {
+        ImageTexture {
+            pixels,
+            width,
+            height,
+            wrap: WrapMode::Wrap,
+            scale: (1.0, 1.0),
+            offset: (0.0, 0.0),
+            rotation: 0.0,
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> ImageResult<Self> {
+        let image = image::open(path)?.into_rgb8();
+        let pixels = image.pixels()
+            .map(|p| vec3![p[0] as f64, p[1] as f64, p[2] as f64])
+            .collect();
+        Ok(ImageTexture::new(pixels, image.width() as usize, image.height() as usize))
+    }
+
+    pub fn set_wrap(&mut self, wrap: WrapMode) {
+        self.wrap = wrap;
+    }
+
+    // Texture coordinates are scaled first, then rotated by some degrees around (0, 0), then offset.
+    // A scale of 2 repeats the image twice across the surface.
+    pub fn set_uv_transform(&mut self, scale: (f64, f64), offset: (f64, f64), rotation: f64) {
+        self.scale = scale;
+        self.offset = offset;
+        self.rotation = deg_to_rad(rotation);
+    }
+
+    fn transform_uv(&self, (u, v): (f64, f64)) -> (f64, f64) {
+        let (u, v) = (u * self.scale.0, v * self.scale.1);
+        let (sin, cos) = self.rotation.sin_cos();
+        (u * cos - v * sin + self.offset.0, u * sin + v * cos + self.offset.1)
+    }
+
+    // Pixel at integer coordinates, which may be outside of the image.
+    fn get_pixel(&self, x: i64, y: i64) -> Vec3 {
+        let (x, y) = match self.wrap {
+            WrapMode::Wrap => (x.rem_euclid(self.width as i64), y.rem_euclid(self.height as i64)),
+            WrapMode::Clamp => (x.clamp(0, self.width as i64 - 1), y.clamp(0, self.height as i64 - 1)),
+        };
+        self.pixels[y as usize * self.width + x as usize]
+    }
+
+    // Bilinear filtering, blends the four pixels closest to the texture coordinates.
+    pub fn get_color(&self, uv: (f64, f64)) -> Vec3 {
+        let (u, v) = self.transform_uv(uv);
+
+        // Pixel centers are at half pixel offsets, and rows go from the top down
+        let x = u * self.width as f64 - 0.5;
+        let y = (1.0 - v) * self.height as f64 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+
+        let top = self.get_pixel(x0, y0) * (1.0 - fx) + self.get_pixel(x0 + 1, y0) * fx;
+        let bottom = self.get_pixel(x0, y0 + 1) * (1.0 - fx) + self.get_pixel(x0 + 1, y0 + 1) * fx;
+        top * (1.0 - fy) + bottom * fy
+    }
+}
+
+// Anything that gives a color for a point on a surface. Textures are stored in the scene and
+// materials refer to them by index, so materials stay small and copyable.
+pub enum Texture {
+    Image(ImageTexture),
+    Procedural(ProceduralTexture)
+}
+
+impl Texture {
+    // Color at the hit, in the 0-255 range.
+    pub fn get_color(&self, hit: &HitData) -> Vec3 {
+        match self {
+            Texture::Image(texture) => texture.get_color(hit.uv),
+            Texture::Procedural(texture) => texture.get_color(hit)
+        }
+    }
+}
+
+// A material color or factor. Either a fixed value or looked up in one of the scene's textures.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Param<T> {
+    Value(T),
+    Texture(usize) // Index of the texture in the scene
+}
+
+impl Param<Vec3> {
+    pub fn get(&self, scene: &Scene, hit: &HitData) -> Vec3 {
+        match self {
+            Param::Value(value) => *value,
+            Param::Texture(index) => scene.get_texture(*index).get_color(hit)
+        }
+    }
+}
+
+impl Param<f64> {
+    // Factors from textures are how bright the texture is, from 0 for black to 1 for white.
+    pub fn get(&self, scene: &Scene, hit: &HitData) -> f64 {
+        match self {
+            Param::Value(value) => *value,
+            Param::Texture(index) => {
+                let color = scene.get_texture(*index).get_color(hit);
+                (color.x + color.y + color.z) / (3.0 * 255.0)
+            }
+        }
+    }
+
+    // If the factor is above 0 anywhere. Textures might be, so they count.
+    pub fn can_be_positive(&self) -> bool {
+        match self {
+            Param::Value(value) => *value > 0.0,
+            Param::Texture(_) => true
+        }
+    }
+}
+
+impl From<Vec3> for Param<Vec3> {
+    fn from(value: Vec3) -> Self {
+        Param::Value(value)
+    }
+}
+
+impl From<f64> for Param<f64> {
+    fn from(value: f64) -> Self {
+        Param::Value(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2x2 image, black and white along the top row and red and blue along the bottom.
+    fn two_by_two() -> ImageTexture {
+        ImageTexture::new(vec![
+            vec3![0.0, 0.0, 0.0], vec3![255.0, 255.0, 255.0],
+            vec3![255.0, 0.0, 0.0], vec3![0.0, 0.0, 255.0],
+        ], 2, 2)
+    }
+
+    fn assert_color(color: Vec3, expected: Vec3) {
+        assert!(
+            (color.x - expected.x).abs() < 1e-9 && (color.y - expected.y).abs() < 1e-9 && (color.z - expected.z).abs() < 1e-9,
+            "{:?} is not {:?}", color, expected
+        );
+    }
+
+    #[test]
+    fn pixel_centers_give_the_pixel() {
+        let texture = two_by_two();
+        assert_color(texture.get_color((0.25, 0.75)), vec3![0.0, 0.0, 0.0]);
+        assert_color(texture.get_color((0.75, 0.75)), vec3![255.0, 255.0, 255.0]);
+        assert_color(texture.get_color((0.25, 0.25)), vec3![255.0, 0.0, 0.0]);
+        assert_color(texture.get_color((0.75, 0.25)), vec3![0.0, 0.0, 255.0]);
+    }
+
+    #[test]
+    fn colors_between_pixels_are_blended() {
+        let texture = two_by_two();
+        // Halfway along the top row
+        assert_color(texture.get_color((0.5, 0.75)), vec3![127.5, 127.5, 127.5]);
+        // A quarter of the way from black down to red
+        assert_color(texture.get_color((0.25, 0.625)), vec3![63.75, 0.0, 0.0]);
+        // Middle of the image is the average of all four
+        assert_color(texture.get_color((0.5, 0.5)), vec3![127.5, 63.75, 127.5]);
+    }
+
+    #[test]
+    fn wrapping_repeats_the_image() {
+        let texture = two_by_two();
+        assert_color(texture.get_color((1.25, 0.75)), texture.get_color((0.25, 0.75)));
+        assert_color(texture.get_color((-0.25, -1.75)), texture.get_color((0.75, 0.25)));
+        // The left edge blends with the right edge of the image
+        assert_color(texture.get_color((0.0, 0.75)), vec3![127.5, 127.5, 127.5]);
+    }
+
+    #[test]
+    fn clamping_stretches_the_edges() {
+        let mut texture = two_by_two();
+        texture.set_wrap(WrapMode::Clamp);
+        assert_color(texture.get_color((0.0, 0.75)), vec3![0.0, 0.0, 0.0]);
+        assert_color(texture.get_color((5.0, 0.75)), vec3![255.0, 255.0, 255.0]);
+        assert_color(texture.get_color((-3.0, -3.0)), vec3![255.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn uv_transform_scales_and_offsets() {
+        let mut texture = two_by_two();
+        texture.set_uv_transform((2.0, 2.0), (0.5, 0.0), 0.0);
+        // 0.125 * 2 + 0.5 = 0.75
+        assert_color(texture.get_color((0.125, 0.375)), vec3![255.0, 255.0, 255.0]);
+    }
+}
@@ -0,0 +1,120 @@
+use std::f64::consts::PI;
+use rand::Rng;
+use crate::{Ray, Scene, Vec3, vec3, Vector};
+use crate::traits::HitData;
+use crate::utils::orthonormal_basis;
+
+// Ambient occlusion darkens ambient light in creases and where objects touch, which ambient light alone can't see.
+// Rays are sent out over the hemisphere above a point, more of them close to the normal, and the ones that hit
+// something within radius block ambient light from that direction.
+
+// How far around a point objects block ambient light, and how many rays check for them. More samples give less noise.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AmbientOcclusion {
+    pub radius: f64,
+    pub samples: u32
+}
+
+impl Default for AmbientOcclusion {
+    fn default() -> Self {
+        AmbientOcclusion { radius: 1.0, samples: 16 }
+    }
+}
+
+impl AmbientOcclusion {
+    // How much ambient light reaches the hit, from 0 when it's fully blocked to 1 when nothing is near.
+    pub fn get_visibility(&self, scene: &Scene, hit: &HitData) -> f64 {
+        // Look out from the side of the surface the ray came from
+        let flip = if hit.ray.direction.dot(hit.geometric_normal) > 0.0 { -1.0 } else { 1.0 };
+        let (normal, geometric_normal) = (hit.normal * flip, hit.geometric_normal * flip);
+        let (tangent, bitangent) = orthonormal_basis(normal);
+        let origin = hit.hit_point + geometric_normal * 1e-6;
+
+        // Jitter the rays inside a grid over the hemisphere so they spread out evenly, like rectangle light samples
+        let mut rng = rand::thread_rng();
+        let samples = self.samples.max(1);
+        let grid = (samples as f64).sqrt() as u32;
+        let mut open = 0;
+        for i in 0..samples {
+            let (u, v) = if i < grid * grid {
+                (((i % grid) as f64 + rng.gen::<f64>()) / grid as f64, ((i / grid) as f64 + rng.gen::<f64>()) / grid as f64)
+            } else {
+                (rng.gen::<f64>(), rng.gen::<f64>())
+            };
+            let (r, angle) = (u.sqrt(), 2.0 * PI * v);
+            let direction = tangent * (r * angle.cos()) + bitangent * (r * angle.sin()) + normal * (1.0 - u).max(0.0).sqrt();
+
+            // Bent normals can point rays into the surface, which would always be blocked
+            if direction.dot(geometric_normal) <= 0.0 || !scene.get_hit(Ray::new(origin, direction), self.radius).did_hit {
+                open += 1;
+            }
+        }
+        open as f64 / samples as f64
+    }
+}
+
+impl Scene {
+    // Ambient occlusion at the hit, 1 when it's turned off.
+    pub fn get_ambient_visibility(&self, hit: &HitData) -> f64 {
+        match self.get_ambient_occlusion() {
+            Some(ambient_occlusion) => ambient_occlusion.get_visibility(self, hit),
+            None => 1.0
+        }
+    }
+
+    // Color seen along a camera ray in an ambient occlusion pass. White where nothing is in the way of ambient light,
+    // black where it's fully blocked. Uses the default settings when ambient occlusion isn't turned on.
+    pub fn get_occlusion_color(&self, ray: Ray) -> Vec3 {
+        let hit = self.get_hit(ray, self.get_render_distance());
+        if !hit.did_hit {
+            return vec3![255.0, 255.0, 255.0];
+        }
+        let visibility = self.get_ambient_occlusion().unwrap_or_default().get_visibility(self, &hit);
+        vec3![255.0, 255.0, 255.0] * visibility
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::materials::{Flat, Material};
+    use crate::objects::Plane;
+
+    fn gray() -> Material {
+        Material::Flat(Flat::new(vec3![128.0, 128.0, 128.0]))
+    }
+
+    // Scene with a floor at y = 0, and the hit of a ray going straight down onto it at some x.
+    fn floor_hit(walls: Vec<Plane>, x: f64) -> (Scene, HitData) {
+        let mut scene = Scene::new((1, 1), 100.0, vec3![0.0, 0.0, 0.0], 90.0, 0, 1, 1.0, true);
+        scene.push_object(Box::new(Plane::new(10.0, 10.0, vec3![0.0, 1.0, 0.0], gray())));
+        for wall in walls {
+            scene.push_object(Box::new(wall));
+        }
+        let hit = scene.get_hit(Ray::new(vec3![x, 2.0, 0.0], vec3![0.0, -1.0, 0.0]), 100.0);
+        assert!(hit.did_hit && hit.hit_point.y.abs() < 1e-9);
+        (scene, hit)
+    }
+
+    #[test]
+    fn nothing_around_lets_all_ambient_light_in() {
+        let (scene, hit) = floor_hit(vec![], 0.0);
+        let ambient_occlusion = AmbientOcclusion { radius: 1.0, samples: 64 };
+        assert_eq!(ambient_occlusion.get_visibility(&scene, &hit), 1.0);
+        assert_eq!(scene.get_ambient_visibility(&hit), 1.0);
+    }
+
+    #[test]
+    fn corners_block_some_ambient_light() {
+        // A wall standing on the floor at x = 0, facing +X
+        let wall = Plane::new(10.0, 10.0, vec3![1.0, 0.0, 0.0], gray());
+        let (scene, hit) = floor_hit(vec![wall], 0.1);
+        let ambient_occlusion = AmbientOcclusion { radius: 1.0, samples: 256 };
+        let visibility = ambient_occlusion.get_visibility(&scene, &hit);
+        assert!(visibility > 0.2 && visibility < 0.9, "{}", visibility);
+
+        // Further from the wall than the radius nothing is blocked
+        let (scene, hit) = floor_hit(vec![wall], 3.0);
+        assert_eq!(ambient_occlusion.get_visibility(&scene, &hit), 1.0);
+    }
+}
@@ -0,0 +1,204 @@
+use crate::{Ray, Scene, Vec3, vec3, Vector};
+use crate::materials::{HallComponents, Mat, Material};
+use crate::utils::supersample;
+
+// Arbitrary output variables, images of what the camera sees besides its color. Compositors use them to rebuild the
+// image and tweak parts of it, like making reflections brighter or tinting a single object.
+//
+// Every AOV is taken from the first surface seen through the middle of the camera lens, so they stay sharp when the
+// camera has an aperture. Where nothing is hit they're all 0.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Aov {
+    Depth, // Distance along the camera ray, in all three channels
+    Normal, // Shading normal in world space, after normal maps
+    Position, // Hit point in world space
+    Albedo, // Color of the surface without any light on it, 0-255
+    ObjectId, // Objects numbered from 1 in the order they were added to the scene, in all three channels
+    MaterialId, // Different materials numbered from 1, in all three channels
+    // The parts Hall materials add up their color from, see HallComponents. Black on other materials.
+    Diffuse,
+    Specular,
+    Reflection,
+    Refraction
+}
+
+impl Aov {
+    // Name of the buffer the AOV is saved in.
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            Aov::Depth => "depth",
+            Aov::Normal => "normal",
+            Aov::Position => "position",
+            Aov::Albedo => "albedo",
+            Aov::ObjectId => "object_id",
+            Aov::MaterialId => "material_id",
+            Aov::Diffuse => "diffuse",
+            Aov::Specular => "specular",
+            Aov::Reflection => "reflection",
+            Aov::Refraction => "refraction"
+        }
+    }
+
+    fn is_hall_component(&self) -> bool {
+        matches!(self, Aov::Diffuse | Aov::Specular | Aov::Reflection | Aov::Refraction)
+    }
+}
+
+// Everything a render makes, as named float images of the same size. The rendered image comes first as "beauty", then
+// the scene's AOVs by name in the order they were asked for. Pixels go row by row from the top left of the image.
+pub struct RenderOutput {
+    resolution: (u32, u32),
+    buffers: Vec<(String, Vec<Vec3>)>
+}
+
+impl RenderOutput {
+    pub fn new(resolution: (u32, u32)) -> Self {
+        RenderOutput { resolution, buffers: vec![] }
+    }
+
+    pub fn get_resolution(&self) -> (u32, u32) {
+        self.resolution
+    }
+
+    // Adds a buffer at the end, or replaces the one with the same name.
+    pub fn insert(&mut self, name: &str, pixels: Vec<Vec3>) {
+        match self.buffers.iter_mut().find(|(buffer_name, _)| buffer_name == name) {
+            Some((_, buffer)) => *buffer = pixels,
+            None => self.buffers.push((String::from(name), pixels))
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&[Vec3]> {
+        self.buffers.iter().find(|(buffer_name, _)| buffer_name == name).map(|(_, buffer)| buffer.as_slice())
+    }
+
+    // The rendered image.
+    pub fn get_beauty(&self) -> &[Vec3] {
+        self.get("beauty").unwrap_or(&[])
+    }
+
+    pub fn get_names(&self) -> Vec<&str> {
+        self.buffers.iter().map(|(name, _)| name.as_str()).collect()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &[Vec3])> {
+        self.buffers.iter().map(|(name, buffer)| (name.as_str(), buffer.as_slice()))
+    }
+
+    // Averages blocks of pixels in every buffer down to a smaller resolution, see utils::supersample.
+    // Averaged ids would be in between at the edges of objects and match neither, so they take a single sample instead.
+    pub fn supersample(&self, samples: u32) -> Self {
+        let square = (samples as f64).sqrt() as u32;
+        let mut output = RenderOutput::new((self.resolution.0 / square.max(1), self.resolution.1 / square.max(1)));
+        let ids = [Aov::ObjectId.get_name(), Aov::MaterialId.get_name()];
+        for (name, buffer) in self.iter() {
+            if ids.contains(&name) {
+                output.insert(name, first_samples(buffer, self.resolution, samples));
+            } else {
+                output.insert(name, supersample(buffer, self.resolution, samples));
+            }
+        }
+        output
+    }
+}
+
+// The top left pixel of every block supersample would average. Empty when it would be too.
+fn first_samples(pixels: &[Vec3], resolution: (u32, u32), samples: u32) -> Vec<Vec3> {
+    let square = (samples as f64).sqrt() as u32;
+    if square == 0 || square * square != samples || !resolution.0.is_multiple_of(square) || !resolution.1.is_multiple_of(square) {
+        return vec![];
+    }
+    let mut new_image = vec![];
+    for i in 0..resolution.1 / square {
+        for j in 0..resolution.0 / square {
+            new_image.push(pixels[(square * i * resolution.0 + j * square) as usize]);
+        }
+    }
+    new_image
+}
+
+impl Scene {
+    // Values of the scene's AOVs at normalized pixel location, in the order they were asked for.
+    pub fn get_aovs_at_pixel(&self, x: f64, y: f64) -> Vec<Vec3> {
+        if self.get_aovs().is_empty() {
+            return vec![];
+        }
+        match self.main_camera.get_pinhole_ray(x, y) {
+            Some(ray) => self.get_aovs_from_ray(ray),
+            None => vec![vec3![0.0, 0.0, 0.0]; self.get_aovs().len()]
+        }
+    }
+
+    // Values of the scene's AOVs for the first surface the ray hits.
+    pub fn get_aovs_from_ray(&self, ray: Ray) -> Vec<Vec3> {
+        let mut hit = self.get_hit(ray, self.get_render_distance());
+        if !hit.did_hit {
+            return vec![vec3![0.0, 0.0, 0.0]; self.get_aovs().len()];
+        }
+        if let Some(normal_map) = hit.mat.get_normal_map() {
+            hit.normal = normal_map.apply(self, &hit);
+        }
+
+        // Hall components trace reflections and refractions, so only work them out when they're asked for
+        let components = match hit.mat {
+            Material::Hall(hall) if self.get_aovs().iter().any(|aov| aov.is_hall_component()) => hall.get_components(self, ray, &hit, 0),
+            _ => HallComponents::default()
+        };
+
+        self.get_aovs().iter().map(|aov| match aov {
+            Aov::Depth => {
+                let depth = hit.t * ray.direction.length();
+                vec3![depth, depth, depth]
+            },
+            Aov::Normal => hit.normal,
+            Aov::Position => hit.hit_point,
+            // Lobes can add up to more than white, like glass that reflects and lets light through
+            Aov::Albedo => {
+                let albedo = hit.mat.get_bsdf(self, &hit, 1.0).get_albedo();
+                vec3![albedo.x.min(1.0), albedo.y.min(1.0), albedo.z.min(1.0)] * 255.0
+            },
+            Aov::ObjectId => vec3![hit.object_id as f64, hit.object_id as f64, hit.object_id as f64],
+            Aov::MaterialId => vec3![hit.material_id as f64, hit.material_id as f64, hit.material_id as f64],
+            Aov::Diffuse => components.diffuse,
+            Aov::Specular => components.specular,
+            Aov::Reflection => components.reflection,
+            Aov::Refraction => components.refraction
+        }).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn supersample_averages_images_but_not_ids() {
+        // 4x2 image where the left half is object 1 and the right half object 2, with an edge in the middle block
+        let ids: Vec<Vec3> = [1.0, 2.0, 2.0, 2.0, 1.0, 1.0, 2.0, 2.0].iter().map(|id| vec3![*id, *id, *id]).collect();
+        let image: Vec<Vec3> = (0..8).map(|i| vec3![i as f64, 0.0, 0.0]).collect();
+        let mut output = RenderOutput::new((4, 2));
+        output.insert("beauty", image);
+        output.insert(Aov::ObjectId.get_name(), ids.clone());
+        output.insert(Aov::MaterialId.get_name(), ids);
+
+        let sampled = output.supersample(4);
+        assert_eq!(sampled.get_resolution(), (2, 1));
+        assert_eq!(sampled.get_names(), vec!["beauty", "object_id", "material_id"]);
+        assert_eq!(sampled.get_beauty(), &[vec3![2.5, 0.0, 0.0], vec3![4.5, 0.0, 0.0]]);
+        for name in ["object_id", "material_id"] {
+            assert_eq!(sampled.get(name).unwrap(), &[vec3![1.0, 1.0, 1.0], vec3![2.0, 2.0, 2.0]]);
+        }
+    }
+
+    #[test]
+    fn supersample_needs_whole_blocks() {
+        let mut output = RenderOutput::new((3, 2));
+        output.insert("beauty", vec![vec3![1.0, 1.0, 1.0]; 6]);
+        output.insert("object_id", vec![vec3![1.0, 1.0, 1.0]; 6]);
+        for samples in [0, 3, 4] {
+            let sampled = output.supersample(samples);
+            assert!(sampled.get_beauty().is_empty() && sampled.get("object_id").unwrap().is_empty(), "{} samples", samples);
+        }
+        assert_eq!(output.supersample(1).get("object_id").unwrap().len(), 6);
+    }
+}
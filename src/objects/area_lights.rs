@@ -0,0 +1,658 @@
+use std::f64::consts::PI;
+use rand::Rng;
+use crate::data_structures::{Vector, Vec3};
+use crate::{vec3};
+use crate::objects::{LightHit, LightSample, WorldLight};
+use crate::utils::orthonormal_basis;
+
+// Area lights are sampled at random points on their surface. Each sample carries 1/samples of the light,
+// weighted by how much the light surface faces the point being lit. The more samples the smoother the shadows.
+// At a distance an area light with some intensity looks like a point light with the same intensity.
+
+// Light arriving at surface_point from light_point on a light surface with the given normal.
+// Weight scales the sample to make up for only sampling part of the surface.
+// Density is how many samples are taken per unit of area on the light.
+fn area_sample(surface_point: Vec3, light_point: Vec3, light_normal: Vec3, light_color: Vec3, weight: f64, density: f64) -> LightSample {
+    let to_light = light_point - surface_point;
+    let distance = to_light.length();
+    let direction = to_light / distance;
+
+    // Light leaves the surface at an angle, so less of it reaches the point
+    let cos_light = (direction * -1.0).dot(light_normal).max(0.0);
+    let color = light_color * (weight * cos_light / (distance * distance));
+    LightSample { direction, distance, color, pdf: solid_angle_pdf(density, distance, cos_light) }
+}
+
+// Turns a density of samples over the area of a light into a density over the directions seen from a point.
+// Far away or tilted parts of the light cover less of the view, so directions towards them are more crowded.
+fn solid_angle_pdf(density: f64, distance: f64, cos_light: f64) -> f64 {
+    if cos_light > 0.0 { density * distance * distance / cos_light } else { 0.0 }
+}
+
+// Where a ray hits the plane of a one-sided light from the front. Returns the distance and the hit point.
+fn hit_plane(surface_point: Vec3, direction: Vec3, position: Vec3, normal: Vec3) -> Option<(f64, Vec3)> {
+    let facing = direction.dot(normal);
+    if facing >= 0.0 {
+        return None;
+    }
+    let distance = (position - surface_point).dot(normal) / facing;
+    if distance <= 0.0 {
+        return None;
+    }
+    Some((distance, surface_point + direction * distance))
+}
+
+// Uniformly random direction.
+fn random_unit_vector(rng: &mut impl Rng) -> Vec3 {
+    let z = 2.0 * rng.gen::<f64>() - 1.0;
+    let ring = (1.0 - z * z).sqrt();
+    let angle = 2.0 * PI * rng.gen::<f64>();
+    vec3![ring * angle.cos(), ring * angle.sin(), z]
+}
+
+// Adds up the light from all samples.
+fn total_color(samples: &[LightSample]) -> Vec3 {
+    samples.iter().fold(vec3![0.0, 0.0, 0.0], |total, sample| total + sample.color)
+}
+
+// A one-sided rectangle that emits light in the direction of its normal.
+// Width is along a horizontal axis of the rectangle, and height is perpendicular to it.
+#[derive(Debug, Copy, Clone)]
+pub struct RectangleLight {
+    pub color: Vec3,
+    pub intensity: f64,
+    pub position: Vec3, // Center of the rectangle
+    pub samples: u32,
+    normal: Vec3,
+    width_axis: Vec3,
+    height_axis: Vec3,
+    width: f64,
+    height: f64,
+}
+
+impl RectangleLight {
+    pub fn new(color: Vec3, intensity: f64, normal: Vec3, width: f64, height: f64, samples: u32) -> Self {
+        let mut light = RectangleLight {
+            color,
+            intensity,
+            position: vec3![0.0, 0.0, 0.0],
+            samples: samples.max(1),
+            normal: vec3![0.0, -1.0, 0.0],
+            width_axis: vec3![1.0, 0.0, 0.0],
+            height_axis: vec3![0.0, 0.0, 1.0],
+            width,
+            height,
+        };
+        light.set_normal(normal);
+        light
+    }
+
+    pub fn get_position(&self) -> Vec3 {
+        self.position
+    }
+
+    pub fn set_position(&mut self, position: Vec3) {
+        self.position = position;
+    }
+
+    // Keep the width horizontal unless the rectangle faces straight up or down.
+    pub fn set_normal(&mut self, normal: Vec3) {
+        self.normal = normal.unit();
+        let horizontal = self.normal.cross(vec3![0.0, 1.0, 0.0]);
+        if horizontal.length() > 1e-9 {
+            self.width_axis = horizontal.unit();
+            self.height_axis = self.width_axis.cross(self.normal);
+        } else {
+            (self.width_axis, self.height_axis) = orthonormal_basis(self.normal);
+        }
+    }
+}
+
+impl WorldLight for RectangleLight {
+    fn get_light_color(self, surface_point: Vec3) -> Vec3 {
+        total_color(&self.get_samples(surface_point))
+    }
+
+    // Only points in front of the rectangle are lit.
+    fn illuminates(self, surface_point: Vec3) -> bool {
+        (surface_point - self.position).dot(self.normal) > 0.0
+    }
+
+    fn get_direction_from(self, surface_point: Vec3) -> (Vec3, f64) {
+        let to_light = self.position - surface_point;
+        (to_light.unit(), to_light.length())
+    }
+
+    // Jitter samples inside a grid over the rectangle so they spread out evenly.
+    // Samples that don't fit in the square grid are placed anywhere on the rectangle.
+    fn get_samples(self, surface_point: Vec3) -> Vec<LightSample> {
+        let mut rng = rand::thread_rng();
+        let grid = (self.samples as f64).sqrt() as u32;
+        let weight = self.intensity / self.samples as f64;
+        let density = self.samples as f64 / (self.width * self.height);
+
+        (0..self.samples).map(|i| {
+            let (u, v) = if i < grid * grid {
+                (((i % grid) as f64 + rng.gen::<f64>()) / grid as f64, ((i / grid) as f64 + rng.gen::<f64>()) / grid as f64)
+            } else {
+                (rng.gen::<f64>(), rng.gen::<f64>())
+            };
+            let (u, v) = (u - 0.5, v - 0.5);
+            let light_point = self.position + self.width_axis * (u * self.width) + self.height_axis * (v * self.height);
+            area_sample(surface_point, light_point, self.normal, self.color, weight, density)
+        }).collect()
+    }
+
+    fn set_color(&mut self, color: Vec3) {
+        self.color = color;
+    }
+
+    // The light spreads evenly over the rectangle, and the pi makes it match the light from its samples.
+    fn hit(self, surface_point: Vec3, direction: Vec3) -> Option<LightHit> {
+        let (distance, point) = hit_plane(surface_point, direction, self.position, self.normal)?;
+        let offset = point - self.position;
+        if offset.dot(self.width_axis).abs() > self.width / 2.0 || offset.dot(self.height_axis).abs() > self.height / 2.0 {
+            return None;
+        }
+        let area = self.width * self.height;
+        let cos_light = (direction * -1.0).dot(self.normal);
+        Some(LightHit {
+            distance,
+            radiance: self.color * (PI * self.intensity / area),
+            pdf: solid_angle_pdf(self.samples as f64 / area, distance, cos_light)
+        })
+    }
+
+    fn get_photon_origin(self) -> Option<Vec3> {
+        let mut rng = rand::thread_rng();
+        let (u, v) = (rng.gen::<f64>() - 0.5, rng.gen::<f64>() - 0.5);
+        Some(self.position + self.width_axis * (u * self.width) + self.height_axis * (v * self.height))
+    }
+
+    // The radiance from hit times the area, since every point is as likely to be picked.
+    fn get_photon_intensity(self, _: Vec3, direction: Vec3) -> Vec3 {
+        self.color * (PI * self.intensity * direction.dot(self.normal).max(0.0))
+    }
+}
+
+// A one-sided disk that emits light in the direction of its normal.
+#[derive(Debug, Copy, Clone)]
+pub struct DiskLight {
+    pub color: Vec3,
+    pub intensity: f64,
+    pub position: Vec3, // Center of the disk
+    pub radius: f64,
+    pub samples: u32,
+    normal: Vec3,
+}
+
+impl DiskLight {
+    pub fn new(color: Vec3, intensity: f64, normal: Vec3, radius: f64, samples: u32) -> Self {
+        DiskLight {
+            color,
+            intensity,
+            position: vec3![0.0, 0.0, 0.0],
+            radius,
+            samples: samples.max(1),
+            normal: normal.unit(),
+        }
+    }
+
+    pub fn get_position(&self) -> Vec3 {
+        self.position
+    }
+
+    pub fn set_position(&mut self, position: Vec3) {
+        self.position = position;
+    }
+
+    pub fn set_normal(&mut self, normal: Vec3) {
+        self.normal = normal.unit();
+    }
+}
+
+impl WorldLight for DiskLight {
+    fn get_light_color(self, surface_point: Vec3) -> Vec3 {
+        total_color(&self.get_samples(surface_point))
+    }
+
+    // Only points in front of the disk are lit.
+    fn illuminates(self, surface_point: Vec3) -> bool {
+        (surface_point - self.position).dot(self.normal) > 0.0
+    }
+
+    fn get_direction_from(self, surface_point: Vec3) -> (Vec3, f64) {
+        let to_light = self.position - surface_point;
+        (to_light.unit(), to_light.length())
+    }
+
+    fn get_samples(self, surface_point: Vec3) -> Vec<LightSample> {
+        let mut rng = rand::thread_rng();
+        let (tangent, bitangent) = orthonormal_basis(self.normal);
+        let weight = self.intensity / self.samples as f64;
+        let density = self.samples as f64 / (PI * self.radius * self.radius);
+
+        (0..self.samples).map(|_| {
+            // Uniformly sample the disk. Without the sqrt samples would bunch up in the center.
+            let r = self.radius * rng.gen::<f64>().sqrt();
+            let angle = 2.0 * PI * rng.gen::<f64>();
+            let light_point = self.position + tangent * (r * angle.cos()) + bitangent * (r * angle.sin());
+            area_sample(surface_point, light_point, self.normal, self.color, weight, density)
+        }).collect()
+    }
+
+    fn set_color(&mut self, color: Vec3) {
+        self.color = color;
+    }
+
+    fn hit(self, surface_point: Vec3, direction: Vec3) -> Option<LightHit> {
+        let (distance, point) = hit_plane(surface_point, direction, self.position, self.normal)?;
+        if (point - self.position).length() > self.radius {
+            return None;
+        }
+        let area = PI * self.radius * self.radius;
+        let cos_light = (direction * -1.0).dot(self.normal);
+        Some(LightHit {
+            distance,
+            radiance: self.color * (PI * self.intensity / area),
+            pdf: solid_angle_pdf(self.samples as f64 / area, distance, cos_light)
+        })
+    }
+
+    fn get_photon_origin(self) -> Option<Vec3> {
+        let mut rng = rand::thread_rng();
+        let (tangent, bitangent) = orthonormal_basis(self.normal);
+        let r = self.radius * rng.gen::<f64>().sqrt();
+        let angle = 2.0 * PI * rng.gen::<f64>();
+        Some(self.position + tangent * (r * angle.cos()) + bitangent * (r * angle.sin()))
+    }
+
+    fn get_photon_intensity(self, _: Vec3, direction: Vec3) -> Vec3 {
+        self.color * (PI * self.intensity * direction.dot(self.normal).max(0.0))
+    }
+}
+
+// A glowing ball that emits light in all directions.
+#[derive(Debug, Copy, Clone)]
+pub struct SphereLight {
+    pub color: Vec3,
+    pub intensity: f64,
+    pub position: Vec3, // Center of the sphere
+    pub radius: f64,
+    pub samples: u32,
+}
+
+impl SphereLight {
+    pub fn new(color: Vec3, intensity: f64, radius: f64, samples: u32) -> Self {
+        SphereLight {
+            color,
+            intensity,
+            position: vec3![0.0, 0.0, 0.0],
+            radius,
+            samples: samples.max(1),
+        }
+    }
+
+    pub fn get_position(&self) -> Vec3 {
+        self.position
+    }
+
+    pub fn set_position(&mut self, position: Vec3) {
+        self.position = position;
+    }
+}
+
+impl WorldLight for SphereLight {
+    fn get_light_color(self, surface_point: Vec3) -> Vec3 {
+        total_color(&self.get_samples(surface_point))
+    }
+
+    fn illuminates(self, _: Vec3) -> bool {
+        true
+    }
+
+    fn get_direction_from(self, surface_point: Vec3) -> (Vec3, f64) {
+        let to_light = self.position - surface_point;
+        (to_light.unit(), to_light.length())
+    }
+
+    // Only the half of the sphere facing the surface point can light it, so only that half is sampled.
+    fn get_samples(self, surface_point: Vec3) -> Vec<LightSample> {
+        let mut rng = rand::thread_rng();
+        let facing = (surface_point - self.position).unit();
+        let (tangent, bitangent) = orthonormal_basis(facing);
+
+        // Facing samples are lit at cos 1 and edge samples at cos 0, which averages out to 1/2 over the hemisphere.
+        // Double the weight so a far away sphere is as bright as a point light.
+        let weight = 2.0 * self.intensity / self.samples as f64;
+        let density = self.samples as f64 / (2.0 * PI * self.radius * self.radius);
+
+        (0..self.samples).map(|_| {
+            // Uniformly sample the hemisphere
+            let z = rng.gen::<f64>();
+            let ring = (1.0 - z * z).sqrt();
+            let angle = 2.0 * PI * rng.gen::<f64>();
+            let normal = facing * z + tangent * (ring * angle.cos()) + bitangent * (ring * angle.sin());
+            area_sample(surface_point, self.position + normal * self.radius, normal, self.color, weight, density)
+        }).collect()
+    }
+
+    fn set_color(&mut self, color: Vec3) {
+        self.color = color;
+    }
+
+    // Points inside the sphere aren't lit by it, so they can't hit it either.
+    fn hit(self, surface_point: Vec3, direction: Vec3) -> Option<LightHit> {
+        let to_center = self.position - surface_point;
+        let along = to_center.dot(direction);
+        let closest_squared = to_center.dot(to_center) - along * along;
+        let radius_squared = self.radius * self.radius;
+        if to_center.dot(to_center) <= radius_squared || along <= 0.0 || closest_squared > radius_squared {
+            return None;
+        }
+        let distance = along - (radius_squared - closest_squared).sqrt();
+        let normal = (surface_point + direction * distance - self.position) / self.radius;
+        let cos_light = (direction * -1.0).dot(normal);
+
+        // Only the facing half of the sphere is sampled, which is twice as bright to make up for it
+        let area = 2.0 * PI * radius_squared;
+        Some(LightHit {
+            distance,
+            radiance: self.color * (PI * 2.0 * self.intensity / area),
+            pdf: solid_angle_pdf(self.samples as f64 / area, distance, cos_light)
+        })
+    }
+
+    // Photons leave from anywhere on the sphere, not just the half facing something.
+    fn get_photon_origin(self) -> Option<Vec3> {
+        Some(self.position + random_unit_vector(&mut rand::thread_rng()) * self.radius)
+    }
+
+    // The radiance from hit is color * intensity / radius^2, times the area of the whole sphere.
+    fn get_photon_intensity(self, origin: Vec3, direction: Vec3) -> Vec3 {
+        let normal = (origin - self.position).unit();
+        self.color * (4.0 * PI * self.intensity * direction.dot(normal).max(0.0))
+    }
+}
+
+// Part of a glowing object, given off by SceneObject::get_emitters.
+#[derive(Debug, Copy, Clone)]
+pub enum EmitterShape {
+    Triangle([Vec3; 3]), // Glows towards the side the corners go around counterclockwise
+    Sphere { center: Vec3, radius: f64 }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Emitter {
+    pub shape: EmitterShape,
+    pub radiance: Vec3 // Same units as LightHit::radiance
+}
+
+impl Emitter {
+    fn get_area(&self) -> f64 {
+        match self.shape {
+            EmitterShape::Triangle([a, b, c]) => (b - a).cross(c - a).length() / 2.0,
+            EmitterShape::Sphere { radius, .. } => 4.0 * PI * radius * radius
+        }
+    }
+
+    // How much light the emitter gives off in total, up to a constant.
+    fn get_power(&self) -> f64 {
+        self.radiance.x.max(self.radiance.y).max(self.radiance.z) * self.get_area()
+    }
+
+    // Uniformly random point on the surface and the normal there.
+    fn sample_point(&self, rng: &mut impl Rng) -> (Vec3, Vec3) {
+        match self.shape {
+            EmitterShape::Triangle([a, b, c]) => {
+                let (r, v) = (rng.gen::<f64>().sqrt(), rng.gen::<f64>());
+                (a * (1.0 - r) + b * (r * (1.0 - v)) + c * (r * v), (b - a).cross(c - a).unit())
+            },
+            EmitterShape::Sphere { center, radius } => {
+                let normal = random_unit_vector(rng);
+                (center + normal * radius, normal)
+            }
+        }
+    }
+}
+
+// All glowing objects in the scene, sampled together like one big area light. Emitters are picked in proportion to how
+// much light they give off, so big and bright ones get more of the samples and small dim ones aren't wasted on.
+#[derive(Debug, Clone)]
+pub struct MeshLight {
+    pub samples: u32,
+    emitters: Vec<Emitter>,
+    cumulative_power: Vec<f64>, // Running total of the power of the emitters, for picking them
+}
+
+impl MeshLight {
+    pub fn new(samples: u32) -> Self {
+        MeshLight { samples: samples.max(1), emitters: vec![], cumulative_power: vec![] }
+    }
+
+    pub fn set_samples(&mut self, samples: u32) {
+        self.samples = samples.max(1);
+    }
+
+    // Emitters that don't give off any light are left out.
+    pub fn push(&mut self, emitter: Emitter) {
+        let power = emitter.get_power();
+        if power > 0.0 {
+            self.cumulative_power.push(self.get_total_power() + power);
+            self.emitters.push(emitter);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.emitters.is_empty()
+    }
+
+    fn get_total_power(&self) -> f64 {
+        self.cumulative_power.last().copied().unwrap_or(0.0)
+    }
+
+    // Random emitter, more likely the more light it gives off.
+    fn pick_emitter(&self, rng: &mut impl Rng) -> Emitter {
+        let pick = rng.gen::<f64>() * self.get_total_power();
+        let index = self.cumulative_power.partition_point(|&power| power <= pick).min(self.emitters.len() - 1);
+        self.emitters[index]
+    }
+
+    // Samples per unit of area on a surface with some radiance.
+    fn get_density(&self, radiance: Vec3) -> f64 {
+        self.samples as f64 * radiance.x.max(radiance.y).max(radiance.z) / self.get_total_power()
+    }
+
+    pub fn get_samples(&self, surface_point: Vec3) -> Vec<LightSample> {
+        if self.is_empty() {
+            return vec![];
+        }
+        let mut rng = rand::thread_rng();
+        (0..self.samples).map(|_| {
+            let emitter = self.pick_emitter(&mut rng);
+            let (light_point, light_normal) = emitter.sample_point(&mut rng);
+
+            // The pi turns radiance into the units light samples use, see RectangleLight::hit
+            let density = self.get_density(emitter.radiance);
+            area_sample(surface_point, light_point, light_normal, emitter.radiance / PI, 1.0 / density, density)
+        }).collect()
+    }
+
+    // Random point on a glowing surface for a photon to leave from, picked like the light samples are. Returns the point,
+    // the normal there, and the radiance divided by the chance of picking the point.
+    pub fn get_photon_origin(&self) -> Option<(Vec3, Vec3, Vec3)> {
+        if self.is_empty() {
+            return None;
+        }
+        let mut rng = rand::thread_rng();
+        let emitter = self.pick_emitter(&mut rng);
+        let (point, normal) = emitter.sample_point(&mut rng);
+        Some((point, normal, emitter.radiance / self.get_density(emitter.radiance) * self.samples as f64))
+    }
+
+    // Same as LightSample::pdf for a ray that hit a glowing surface with some radiance.
+    pub fn get_pdf(&self, radiance: Vec3, distance: f64, cos_light: f64) -> f64 {
+        if self.is_empty() {
+            return 0.0;
+        }
+        solid_angle_pdf(self.get_density(radiance), distance, cos_light)
+    }
+}
+
+impl Default for MeshLight {
+    fn default() -> Self {
+        MeshLight::new(16)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SURFACE_POINT: Vec3 = Vec3 { x: 0.5, y: -3.0, z: 1.0 };
+
+    // Where on the light each sample came from.
+    fn sample_points(samples: &[LightSample]) -> Vec<Vec3> {
+        samples.iter().map(|sample| {
+            assert!((sample.direction.length() - 1.0).abs() < 1e-9);
+            SURFACE_POINT + sample.direction * sample.distance
+        }).collect()
+    }
+
+    fn rectangle() -> RectangleLight {
+        let mut light = RectangleLight::new(vec3![255.0, 255.0, 255.0], 1.0, vec3![0.0, -1.0, -1.0], 2.0, 1.0, 7);
+        light.set_position(vec3![1.0, 2.0, 3.0]);
+        light
+    }
+
+    #[test]
+    fn rectangle_samples_land_on_the_rectangle() {
+        let light = rectangle();
+        let samples = light.get_samples(SURFACE_POINT);
+        // 7 doesn't fit a square grid, the rest are placed anywhere
+        assert_eq!(samples.len(), 7);
+        for point in sample_points(&samples) {
+            let offset = point - light.position;
+            assert!(offset.dot(light.normal).abs() < 1e-9);
+            assert!(offset.dot(light.width_axis).abs() <= 1.0 + 1e-9);
+            assert!(offset.dot(light.height_axis).abs() <= 0.5 + 1e-9);
+        }
+        assert!(light.illuminates(SURFACE_POINT));
+        assert!(!light.illuminates(vec3![1.0, 5.0, 3.0]));
+    }
+
+    #[test]
+    fn disk_samples_land_on_the_disk() {
+        let mut light = DiskLight::new(vec3![255.0, 255.0, 255.0], 1.0, vec3![0.0, -1.0, 0.0], 1.5, 12);
+        light.set_position(vec3![0.0, 4.0, 0.0]);
+        let samples = light.get_samples(SURFACE_POINT);
+        assert_eq!(samples.len(), 12);
+        for point in sample_points(&samples) {
+            let offset = point - light.position;
+            assert!(offset.dot(light.normal).abs() < 1e-9);
+            assert!(offset.length() <= 1.5 + 1e-9);
+        }
+    }
+
+    #[test]
+    fn sphere_samples_land_on_the_half_facing_the_point() {
+        let mut light = SphereLight::new(vec3![255.0, 255.0, 255.0], 1.0, 0.5, 9);
+        light.set_position(vec3![2.0, 2.0, -1.0]);
+        let samples = light.get_samples(SURFACE_POINT);
+        assert_eq!(samples.len(), 9);
+        let facing = (SURFACE_POINT - light.position).unit();
+        for point in sample_points(&samples) {
+            let offset = point - light.position;
+            assert!((offset.length() - 0.5).abs() < 1e-9);
+            assert!(offset.dot(facing) >= -1e-9);
+        }
+    }
+
+    #[test]
+    fn sample_count_is_at_least_one() {
+        assert_eq!(RectangleLight::new(vec3![1.0, 1.0, 1.0], 1.0, vec3![0.0, -1.0, 0.0], 1.0, 1.0, 0).get_samples(SURFACE_POINT).len(), 1);
+        assert_eq!(DiskLight::new(vec3![1.0, 1.0, 1.0], 1.0, vec3![0.0, -1.0, 0.0], 1.0, 0).get_samples(SURFACE_POINT).len(), 1);
+        assert_eq!(SphereLight::new(vec3![1.0, 1.0, 1.0], 1.0, 1.0, 0).get_samples(SURFACE_POINT).len(), 1);
+    }
+
+    #[test]
+    fn far_away_area_lights_look_like_point_lights() {
+        let mut point = crate::objects::PointLight::new(vec3![255.0, 255.0, 255.0], 1.0);
+        point.set_position(vec3![0.0, 1000.0, 0.0]);
+        let expected = point.get_light_color(vec3![0.0, 0.0, 0.0]).x;
+
+        let mut disk = DiskLight::new(vec3![255.0, 255.0, 255.0], 1.0, vec3![0.0, -1.0, 0.0], 0.5, 16);
+        disk.set_position(vec3![0.0, 1000.0, 0.0]);
+        let mut sphere = SphereLight::new(vec3![255.0, 255.0, 255.0], 1.0, 0.5, 4096);
+        sphere.set_position(vec3![0.0, 1000.0, 0.0]);
+
+        assert!((disk.get_light_color(vec3![0.0, 0.0, 0.0]).x / expected - 1.0).abs() < 1e-3);
+        // The sphere's hemisphere is sampled randomly, so it's only close on average
+        assert!((sphere.get_light_color(vec3![0.0, 0.0, 0.0]).x / expected - 1.0).abs() < 0.05);
+    }
+
+    fn triangle(x: f64, size: f64, radiance: f64) -> Emitter {
+        // Faces down towards the origin
+        Emitter {
+            shape: EmitterShape::Triangle([vec3![x, 4.0, 0.0], vec3![x + size, 4.0, 0.0], vec3![x, 4.0, size]]),
+            radiance: vec3![radiance, radiance * 0.5, 0.0]
+        }
+    }
+
+    #[test]
+    fn mesh_light_picks_emitters_by_power() {
+        let mut light = MeshLight::new(20_000);
+        light.push(triangle(-10.0, 1.0, 1.0)); // Power 0.5
+        light.push(triangle(0.0, 2.0, 1.0)); // Power 2
+        light.push(triangle(10.0, 1.0, 3.0)); // Power 1.5
+        light.push(triangle(20.0, 1.0, 0.0)); // Dark, left out
+        assert_eq!(light.emitters.len(), 3);
+
+        let mut previous = 0.0;
+        let probabilities: Vec<f64> = light.cumulative_power.iter().map(|&power| {
+            let probability = (power - previous) / light.get_total_power();
+            previous = power;
+            probability
+        }).collect();
+        assert!((probabilities.iter().sum::<f64>() - 1.0).abs() < 1e-12);
+        for (probability, expected) in probabilities.iter().zip([0.125, 0.5, 0.375]) {
+            assert!((probability - expected).abs() < 1e-12, "{:?}", probabilities);
+        }
+
+        // Samples land on each emitter about as often
+        let samples = light.get_samples(vec3![0.0, 0.0, 0.0]);
+        let mut counts = [0.0; 3];
+        for sample in samples.iter() {
+            let x = (sample.direction * sample.distance).x;
+            counts[((x + 10.0) / 10.0).floor() as usize] += 1.0 / samples.len() as f64;
+        }
+        for (count, expected) in counts.iter().zip(probabilities) {
+            assert!((count - expected).abs() < 0.02, "{:?}", counts);
+        }
+    }
+
+    #[test]
+    fn mesh_light_pdf_matches_how_a_triangle_is_sampled() {
+        let samples = 50_000;
+        let mut light = MeshLight::new(samples);
+        let emitter = triangle(-0.5, 1.0, 2.0);
+        light.push(emitter);
+        let EmitterShape::Triangle([a, b, c]) = emitter.shape else { unreachable!() };
+
+        // Solid angle of the triangle seen from the origin (Van Oosterom and Strackee)
+        let (la, lb, lc) = (a.length(), b.length(), c.length());
+        let solid_angle = 2.0 * (a.dot(b.cross(c)).abs()
+            / (la * lb * lc + a.dot(b) * lc + a.dot(c) * lb + b.dot(c) * la)).atan();
+
+        // Averaging 1/pdf over the samples of one pick estimates the solid angle the samples are spread over
+        let mut total = 0.0;
+        for sample in light.get_samples(vec3![0.0, 0.0, 0.0]) {
+            let cos_light = sample.direction.y;
+            let pdf = light.get_pdf(emitter.radiance, sample.distance, cos_light);
+            assert!((pdf - sample.pdf).abs() < sample.pdf * 1e-9);
+            total += samples as f64 / pdf;
+        }
+        let estimate = total / samples as f64;
+        assert!((estimate / solid_angle - 1.0).abs() < 0.02, "{} is not {}", estimate, solid_angle);
+    }
+}
@@ -29,14 +29,14 @@ impl BoundingVolume {
             }
         }
         // If the ray intersects with all axes of the box, then it does intersect.
-        return true;
+        true
     }
 
     pub fn get_position(&self) -> Vec3 {
         self.min + (self.max - self.min) / 2.0
     }
 
-    pub fn sort_by_axis(vols: &mut Vec<BoundingVolume>, axis: u8) {
+    pub fn sort_by_axis(vols: &mut [BoundingVolume], axis: u8) {
         vols.sort_by(|a, b| {
             let a_pos = a.get_position();
             let b_pos = b.get_position();
@@ -1,41 +1,470 @@
+use std::f64::consts::PI;
+use rand::Rng;
 use crate::data_structures::{Ray, Vec3, Vector};
 use crate::{vec3};
 use crate::utils::deg_to_rad;
 
+// Which direction of the image the field of view spans.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum FovAxis {
+    Horizontal,
+    Vertical,
+}
+
+// How rays are shot from the camera into the scene.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Projection {
+    // Pinhole or thin lens camera using the field of view.
+    Perspective,
+    // Parallel rays in the view direction. Height is how much of the scene fits vertically, in world units.
+    Orthographic { height: f64 },
+    // Equidistant fisheye where the circle fits the height of the image. Pixels outside the circle have no ray.
+    Fisheye { fov: f64 },
+    // Full 360 by 180 degree lat-long panorama centered on the view direction.
+    Equirectangular,
+}
+
+// A camera at some position looking towards a target.
+// The up vector decides which way is up in the image, and roll turns the camera around its view direction.
+// With an aperture larger than 0 the camera acts as a thin lens, and only objects at the focus distance are sharp.
 pub struct Camera {
+    // Changing the position directly keeps the view direction, use set_position to keep looking at the target
     pub position: Vec3,
+    target: Vec3,
+    up: Vec3,
+    fov: f64, // In degrees
+    fov_axis: FovAxis,
+    roll: f64, // In degrees
+    aspect_ratio: f64,
+    // Framing of cameras made with Camera::new. The view plane is 1 unit high, and the horizontal fov is
+    // stretched by the aspect ratio instead of spanning the image. Setting a new fov turns it off.
+    fixed_height: bool,
+    aperture: f64, // Radius of the lens
+    focus_distance: f64, // Distance along the view direction where objects are in focus
+    lens_samples: u32, // Rays shot through the lens for each pixel sample
+    projection: Projection,
+
+    // Orthonormal basis of the camera, recomputed whenever one of the settings above changes
     view_dir: Vec3,
+    right: Vec3,
+    true_up: Vec3,
+
+    // Full width and height of the view plane one unit in front of the camera
     horizontal: Vec3,
     vertical: Vec3,
 }
 
-// TODO make positionable camera
 impl Camera {
+    // Camera at the origin looking down -Z, framed the way cameras were before look_at.
+    // Use look_at for a camera where hfov is the actual field of view across the image.
     pub fn new(resolution: (u32, u32), hfov: f64) -> Self {
-        let aspect_ratio = resolution.0 as f64 / resolution.1 as f64;
-        let view_dir = vec3![0.0, 0.0, -1.0];
-        let vertical = vec3![0.0, 1.0, 0.0];
-
-        // Find the horizontal width of the view plane.
-        // Have horizontal scale with aspect ratio
-        let horiz= -2.0 * view_dir.z * (deg_to_rad(hfov / 2.0)).tan() * aspect_ratio;
-        let horizontal = vec3![horiz, 0.0, 0.0];
-
-        Camera {
-            position : vec3![0.0, 0.0, 0.0],
-            view_dir,
-            horizontal,
-            vertical,
+        let mut camera = Camera::look_at(resolution, vec3![0.0, 0.0, 0.0], vec3![0.0, 0.0, -1.0], vec3![0.0, 1.0, 0.0], hfov, FovAxis::Horizontal);
+        camera.fixed_height = true;
+        camera.update_basis();
+        camera
+    }
+
+    pub fn look_at(resolution: (u32, u32), position: Vec3, target: Vec3, up: Vec3, fov: f64, fov_axis: FovAxis) -> Self {
+        let mut camera = Camera {
+            position,
+            target,
+            up,
+            fov,
+            fov_axis,
+            roll: 0.0,
+            aperture: 0.0,
+            focus_distance: 1.0,
+            lens_samples: 1,
+            projection: Projection::Perspective,
+            aspect_ratio: resolution.0 as f64 / resolution.1 as f64,
+            fixed_height: false,
+            view_dir: vec3![0.0, 0.0, -1.0],
+            right: vec3![1.0, 0.0, 0.0],
+            true_up: vec3![0.0, 1.0, 0.0],
+            horizontal: vec3![0.0, 0.0, 0.0],
+            vertical: vec3![0.0, 0.0, 0.0],
+        };
+        camera.update_basis();
+        camera
+    }
+
+    // Recompute the view plane for a new resolution, since its width and height depend on the aspect ratio.
+    pub fn set_resolution(&mut self, resolution: (u32, u32)) {
+        self.aspect_ratio = resolution.0 as f64 / resolution.1 as f64;
+        self.update_basis();
+    }
+
+    // Moves the camera while it keeps looking at the same target.
+    pub fn set_position(&mut self, position: Vec3) {
+        self.position = position;
+        self.update_basis();
+    }
+
+    pub fn set_target(&mut self, target: Vec3) {
+        self.target = target;
+        self.update_basis();
+    }
+
+    pub fn set_up(&mut self, up: Vec3) {
+        self.up = up;
+        self.update_basis();
+    }
+
+    pub fn set_fov(&mut self, fov: f64, fov_axis: FovAxis) {
+        self.fov = fov;
+        self.fov_axis = fov_axis;
+        self.fixed_height = false;
+        self.update_basis();
+    }
+
+    // Roll in degrees. Positive values turn the camera counter-clockwise around its view direction.
+    pub fn set_roll(&mut self, roll: f64) {
+        self.roll = roll;
+        self.update_basis();
+    }
+
+    // An aperture of 0 is a pinhole camera where everything is in focus.
+    pub fn set_aperture(&mut self, aperture: f64) {
+        self.aperture = aperture.max(0.0);
+    }
+
+    pub fn set_focus_distance(&mut self, focus_distance: f64) {
+        self.focus_distance = focus_distance;
+    }
+
+    // How many rays are averaged for each sample. The more samples the smoother the blur.
+    pub fn set_lens_samples(&mut self, lens_samples: u32) {
+        self.lens_samples = lens_samples.max(1);
+    }
+
+    pub fn set_projection(&mut self, projection: Projection) {
+        self.projection = projection;
+    }
+
+    pub fn get_projection(&self) -> Projection {
+        self.projection
+    }
+
+    pub fn get_aperture(&self) -> f64 {
+        self.aperture
+    }
+
+    pub fn get_focus_distance(&self) -> f64 {
+        self.focus_distance
+    }
+
+    // A pinhole camera always gets the same ray, so one sample is enough. Only perspective cameras have a lens.
+    pub fn get_lens_samples(&self) -> u32 {
+        if self.aperture > 0.0 && self.projection == Projection::Perspective { self.lens_samples } else { 1 }
+    }
+
+    pub fn get_position(&self) -> Vec3 {
+        self.position
+    }
+
+    pub fn get_target(&self) -> Vec3 {
+        self.target
+    }
+
+    pub fn get_view_dir(&self) -> Vec3 {
+        self.view_dir
+    }
+
+    // Builds the right, up and view direction vectors of the camera, then scales the view plane to the field of view.
+    fn update_basis(&mut self) {
+        // Keep the last view direction if the target is on top of the camera, since there's no direction to look in.
+        let to_target = self.target - self.position;
+        if to_target.length() > 0.0 {
+            self.view_dir = to_target.unit();
+        }
+
+        // If up is parallel to the view direction pick any other axis, otherwise the cross product is zero.
+        let mut right = self.view_dir.cross(self.up);
+        if right.length() < 1e-9 {
+            let fallback = if self.view_dir.x.abs() < 0.9 { vec3![1.0, 0.0, 0.0] } else { vec3![0.0, 0.0, 1.0] };
+            right = self.view_dir.cross(fallback);
+        }
+        let right = right.unit();
+        let true_up = right.cross(self.view_dir);
+
+        // Rotate right and up around the view direction
+        let (sin, cos) = deg_to_rad(self.roll).sin_cos();
+        self.right = right * cos + true_up * sin;
+        self.true_up = true_up * cos - right * sin;
+
+        // Size of the view plane one unit in front of the camera
+        let half_size = deg_to_rad(self.fov / 2.0).tan();
+        let (width, height) = match self.fov_axis {
+            _ if self.fixed_height => (2.0 * half_size * self.aspect_ratio, 1.0),
+            FovAxis::Horizontal => (2.0 * half_size, 2.0 * half_size / self.aspect_ratio),
+            FovAxis::Vertical => (2.0 * half_size * self.aspect_ratio, 2.0 * half_size),
+        };
+        self.horizontal = self.right * width;
+        self.vertical = self.true_up * height;
+    }
+
+    // Gets a ray from the camera to some normalized pixel location in the scene, or None if the projection
+    // doesn't cover that pixel. (0, 0) is the bottom left of the image, and (1, 1) the top right.
+    // With an aperture the ray starts from a random point on the lens, so call it multiple times to get the blur.
+    pub fn get_ray(&self, x: f64, y: f64) -> Option<Ray> {
+        let pinhole_ray = self.get_pinhole_ray(x, y);
+        if self.aperture <= 0.0 || self.projection != Projection::Perspective {
+            return pinhole_ray;
         }
+
+        // Every ray through the same pixel meets at the focal plane, so things at the focus distance stay sharp.
+        // The direction has a length of 1 along the view direction before normalizing, so scaling it reaches the plane.
+        let to_plane = self.view_dir + self.horizontal * (x - 0.5) + self.vertical * (y - 0.5);
+        let focus_point = self.position + to_plane * self.focus_distance;
+
+        // Uniformly sample a point on the lens disk
+        let mut rng = rand::thread_rng();
+        let radius = self.aperture * rng.gen::<f64>().sqrt();
+        let angle = 2.0 * PI * rng.gen::<f64>();
+        let origin = self.position + self.right * (radius * angle.cos()) + self.true_up * (radius * angle.sin());
+        Some(Ray::new(origin, (focus_point - origin).unit()))
+    }
+
+    // Ray through the center of the lens, which is the ray a pinhole camera would shoot.
+    pub fn get_pinhole_ray(&self, x: f64, y: f64) -> Option<Ray> {
+        match self.projection {
+            Projection::Perspective => {
+                // Origin of the ray should be coming from the camera, so it's the camera origin.
+                // The direction is from the camera origin to some pixel location in the canvas.
+                Some(Ray::new(
+                    self.position,
+                    (self.view_dir + self.horizontal * (x - 0.5) + self.vertical * (y - 0.5)).unit()
+                ))
+            },
+            Projection::Orthographic { height } => {
+                // Every ray goes in the view direction, starting from a point on the view plane.
+                let width = height * self.aspect_ratio;
+                let origin = self.position + self.right * (width * (x - 0.5)) + self.true_up * (height * (y - 0.5));
+                Some(Ray::new(origin, self.view_dir))
+            },
+            Projection::Fisheye { fov } => {
+                // Distance from the center of the image where the edge of the circle is 1.
+                // The angle away from the view direction grows linearly with this distance.
+                let px = (x - 0.5) * 2.0 * self.aspect_ratio;
+                let py = (y - 0.5) * 2.0;
+                let r = (px * px + py * py).sqrt();
+                if r > 1.0 {
+                    return None;
+                }
+                let theta = r * deg_to_rad(fov / 2.0);
+                let sideways = if r > 0.0 { (self.right * px + self.true_up * py) / r } else { vec3![0.0, 0.0, 0.0] };
+                Some(Ray::new(self.position, (self.view_dir * theta.cos() + sideways * theta.sin()).unit()))
+            },
+            Projection::Equirectangular => {
+                // x goes all the way around, with the view direction in the middle of the image.
+                // y goes from straight down to straight up.
+                let longitude = (x - 0.5) * 2.0 * PI;
+                let latitude = (y - 0.5) * PI;
+                let dir = self.view_dir * (latitude.cos() * longitude.cos())
+                    + self.right * (latitude.cos() * longitude.sin())
+                    + self.true_up * latitude.sin();
+                Some(Ray::new(self.position, dir.unit()))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: Vec3, expected: Vec3) {
+        assert!((actual - expected).length() < 1e-9, "expected {:?}, got {:?}", expected, actual);
+    }
+
+    fn direction_at(camera: &Camera, x: f64, y: f64) -> Vec3 {
+        camera.get_ray(x, y).unwrap().direction
+    }
+
+    #[test]
+    fn look_at_builds_an_orthonormal_basis() {
+        let camera = Camera::look_at((100, 100), vec3![1.0, 2.0, 3.0], vec3![1.0, 2.0, 13.0], vec3![0.0, 1.0, 0.0], 90.0, FovAxis::Horizontal);
+        assert_close(camera.get_view_dir(), vec3![0.0, 0.0, 1.0]);
+        assert_close(camera.right, vec3![-1.0, 0.0, 0.0]);
+        assert_close(camera.true_up, vec3![0.0, 1.0, 0.0]);
+
+        // Up doesn't have to be perpendicular to the view direction
+        let tilted = Camera::look_at((100, 100), vec3![0.0, 0.0, 0.0], vec3![1.0, 0.0, -1.0], vec3![0.3, 1.0, 0.2], 60.0, FovAxis::Horizontal);
+        for (a, b) in [(tilted.view_dir, tilted.right), (tilted.right, tilted.true_up), (tilted.true_up, tilted.view_dir)] {
+            assert!(a.dot(b).abs() < 1e-9);
+        }
+        assert!((tilted.true_up.length() - 1.0).abs() < 1e-9);
+        assert!(tilted.true_up.y > 0.0);
+
+        // Looking straight along up still gives a usable basis
+        let down = Camera::look_at((100, 100), vec3![0.0, 5.0, 0.0], vec3![0.0, 0.0, 0.0], vec3![0.0, 1.0, 0.0], 60.0, FovAxis::Horizontal);
+        assert_close(down.get_view_dir(), vec3![0.0, -1.0, 0.0]);
+        assert!((down.right.length() - 1.0).abs() < 1e-9 && down.right.dot(down.view_dir).abs() < 1e-9);
+
+        // The center of the image is the view direction, and the top right is up and to the right
+        assert_close(direction_at(&camera, 0.5, 0.5), vec3![0.0, 0.0, 1.0]);
+        assert_close(direction_at(&camera, 1.0, 1.0), vec3![-1.0, 1.0, 1.0].unit());
     }
 
-    // Gets a ray from the camera position to some normalized pixel location in the scene
-    pub fn get_ray(&self, x: f64, y: f64) -> Ray {
-        // Origin of the ray should be coming from the camera, so it's the camera origin.
-        // The direction is from the camera origin to some pixel location in the canvas.
-        Ray::new(
-            self.position,
-            (self.view_dir + self.horizontal * (x - 0.5) + self.vertical * (y - 0.5)).unit()
-        )
+    #[test]
+    fn new_keeps_the_original_framing() {
+        // The view plane is 2 * tan(hfov / 2) * aspect ratio wide and 1 high
+        let hfov: f64 = 60.0;
+        let camera = Camera::new((200, 100), hfov);
+        let width = 2.0 * (hfov.to_radians() / 2.0).tan() * 2.0;
+        assert_close(camera.horizontal, vec3![width, 0.0, 0.0]);
+        assert_close(camera.vertical, vec3![0.0, 1.0, 0.0]);
+        assert_close(direction_at(&camera, 1.0, 1.0), vec3![width / 2.0, 0.5, -1.0].unit());
+
+        // Still true after a new resolution, but a new fov frames it like look_at
+        let mut camera = Camera::new((100, 100), hfov);
+        camera.set_resolution((200, 100));
+        assert_close(camera.horizontal, vec3![width, 0.0, 0.0]);
+        assert_close(camera.vertical, vec3![0.0, 1.0, 0.0]);
+        camera.set_fov(90.0, FovAxis::Horizontal);
+        assert_close(camera.horizontal, vec3![2.0, 0.0, 0.0]);
+        assert_close(camera.vertical, vec3![0.0, 1.0, 0.0]);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn thin_lens_rays_start_on_the_aperture_and_meet_at_the_focus_distance() {
+        let mut camera = Camera::look_at((100, 100), vec3![1.0, 2.0, 3.0], vec3![1.0, 2.0, -7.0], vec3![0.0, 1.0, 0.0], 60.0, FovAxis::Horizontal);
+        camera.set_aperture(0.5);
+        camera.set_focus_distance(4.0);
+
+        // Where the pinhole ray through the pixel crosses the focal plane
+        let pinhole = camera.get_pinhole_ray(0.8, 0.3).unwrap();
+        let focus_point = pinhole.origin + pinhole.direction * (4.0 / pinhole.direction.dot(camera.get_view_dir()));
+
+        let mut widest: f64 = 0.0;
+        for _ in 0..200 {
+            let ray = camera.get_ray(0.8, 0.3).unwrap();
+            // Origins lie on the lens disk, which faces along the view direction
+            let offset = ray.origin - camera.get_position();
+            assert!(offset.dot(camera.get_view_dir()).abs() < 1e-9);
+            assert!(offset.length() <= 0.5 + 1e-9);
+            widest = widest.max(offset.length());
+
+            let t = 4.0 / ray.direction.dot(camera.get_view_dir());
+            assert_close(ray.origin + ray.direction * t, focus_point);
+        }
+        assert!(widest > 0.25);
+
+        // Without an aperture every ray is the pinhole ray
+        camera.set_aperture(0.0);
+        assert_eq!(camera.get_lens_samples(), 1);
+        assert_close(camera.get_ray(0.8, 0.3).unwrap().origin, camera.get_position());
+        assert_close(camera.get_ray(0.8, 0.3).unwrap().direction, pinhole.direction);
+    }
+
+    #[test]
+    fn set_position_keeps_looking_at_the_target() {
+        let mut camera = Camera::new((100, 100), 90.0);
+        camera.set_target(vec3![0.0, 0.0, -10.0]);
+        camera.set_position(vec3![10.0, 0.0, -10.0]);
+        assert_close(camera.get_view_dir(), vec3![-1.0, 0.0, 0.0]);
+
+        camera.position = vec3![10.0, 5.0, -10.0];
+        assert_close(camera.get_view_dir(), vec3![-1.0, 0.0, 0.0]);
+        assert_close(camera.get_ray(0.5, 0.5).unwrap().origin, vec3![10.0, 5.0, -10.0]);
+    }
+
+    #[test]
+    fn roll_turns_the_image_counter_clockwise() {
+        let mut camera = Camera::look_at((100, 100), vec3![0.0, 0.0, 0.0], vec3![0.0, 0.0, -1.0], vec3![0.0, 1.0, 0.0], 90.0, FovAxis::Horizontal);
+        camera.set_roll(90.0);
+        assert_close(camera.get_view_dir(), vec3![0.0, 0.0, -1.0]);
+        assert_close(camera.right, vec3![0.0, 1.0, 0.0]);
+        assert_close(camera.true_up, vec3![-1.0, 0.0, 0.0]);
+        assert_close(direction_at(&camera, 1.0, 0.5), vec3![0.0, 1.0, -1.0].unit());
+
+        camera.set_roll(-360.0);
+        assert_close(camera.right, vec3![1.0, 0.0, 0.0]);
+        assert_close(camera.true_up, vec3![0.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn fov_spans_the_chosen_axis() {
+        // 2:1 image, so a 90 degree horizontal fov is about 53 degrees vertically
+        let horizontal = Camera::look_at((200, 100), vec3![0.0, 0.0, 0.0], vec3![0.0, 0.0, -1.0], vec3![0.0, 1.0, 0.0], 90.0, FovAxis::Horizontal);
+        assert!((horizontal.horizontal.length() - 2.0).abs() < 1e-9);
+        assert!((horizontal.vertical.length() - 1.0).abs() < 1e-9);
+        assert_close(direction_at(&horizontal, 1.0, 0.5), vec3![1.0, 0.0, -1.0].unit());
+
+        let vertical = Camera::look_at((200, 100), vec3![0.0, 0.0, 0.0], vec3![0.0, 0.0, -1.0], vec3![0.0, 1.0, 0.0], 90.0, FovAxis::Vertical);
+        assert!((vertical.horizontal.length() - 4.0).abs() < 1e-9);
+        assert!((vertical.vertical.length() - 2.0).abs() < 1e-9);
+        assert_close(direction_at(&vertical, 0.5, 1.0), vec3![0.0, 1.0, -1.0].unit());
+
+        // A vertical fov matching the horizontal one gives the same camera
+        let vfov = 2.0 * (0.5_f64).atan().to_degrees();
+        let mut converted = Camera::look_at((200, 100), vec3![0.0, 0.0, 0.0], vec3![0.0, 0.0, -1.0], vec3![0.0, 1.0, 0.0], vfov, FovAxis::Vertical);
+        assert_close(converted.horizontal, horizontal.horizontal);
+        assert_close(converted.vertical, horizontal.vertical);
+
+        // Changing the resolution keeps the chosen axis and stretches the other one
+        converted.set_resolution((100, 100));
+        assert!((converted.vertical.length() - 1.0).abs() < 1e-9);
+        assert!((converted.horizontal.length() - 1.0).abs() < 1e-9);
+    }
+
+    fn projected(projection: Projection) -> Camera {
+        let mut camera = Camera::look_at((200, 100), vec3![1.0, 2.0, 3.0], vec3![1.0, 2.0, -7.0], vec3![0.0, 1.0, 0.0], 90.0, FovAxis::Horizontal);
+        camera.set_projection(projection);
+        camera
+    }
+
+    #[test]
+    fn orthographic_rays_are_parallel() {
+        let camera = projected(Projection::Orthographic { height: 4.0 });
+        let center = camera.get_ray(0.5, 0.5).unwrap();
+        assert_close(center.origin, vec3![1.0, 2.0, 3.0]);
+        assert_close(center.direction, vec3![0.0, 0.0, -1.0]);
+
+        // The view plane is 8 by 4 units for a 2:1 image
+        let corner = camera.get_ray(1.0, 1.0).unwrap();
+        assert_close(corner.origin, vec3![5.0, 4.0, 3.0]);
+        assert_close(corner.direction, vec3![0.0, 0.0, -1.0]);
+    }
+
+    #[test]
+    fn fisheye_angle_grows_with_distance_from_the_center() {
+        let camera = projected(Projection::Fisheye { fov: 180.0 });
+        assert_close(direction_at(&camera, 0.5, 0.5), vec3![0.0, 0.0, -1.0]);
+        assert_close(camera.get_ray(0.5, 0.5).unwrap().origin, vec3![1.0, 2.0, 3.0]);
+
+        // The circle fits the height, so its edge is 90 degrees away at the top and halfway to the right side
+        assert_close(direction_at(&camera, 0.5, 1.0), vec3![0.0, 1.0, 0.0]);
+        assert_close(direction_at(&camera, 0.75, 0.5), vec3![1.0, 0.0, 0.0]);
+        assert_close(direction_at(&camera, 0.5, 0.75), vec3![0.0, 1.0, -1.0].unit());
+
+        // Outside the circle there's no ray
+        assert!(camera.get_ray(0.76, 0.5).is_none());
+        assert!(camera.get_ray(1.0, 0.5).is_none());
+        assert!(camera.get_ray(0.7, 0.9).is_none());
+        assert!(camera.get_pinhole_ray(0.0, 0.0).is_none());
+    }
+
+    #[test]
+    fn equirectangular_covers_every_direction() {
+        let camera = projected(Projection::Equirectangular);
+        assert_close(direction_at(&camera, 0.5, 0.5), vec3![0.0, 0.0, -1.0]);
+        assert_close(direction_at(&camera, 0.75, 0.5), vec3![1.0, 0.0, 0.0]);
+        assert_close(direction_at(&camera, 0.25, 0.5), vec3![-1.0, 0.0, 0.0]);
+        assert_close(direction_at(&camera, 0.0, 0.5), vec3![0.0, 0.0, 1.0]);
+        assert_close(direction_at(&camera, 0.5, 1.0), vec3![0.0, 1.0, 0.0]);
+        assert_close(direction_at(&camera, 0.3, 0.0), vec3![0.0, -1.0, 0.0]);
+    }
+
+    #[test]
+    fn only_perspective_cameras_have_a_lens() {
+        let mut camera = projected(Projection::Orthographic { height: 4.0 });
+        camera.set_aperture(0.5);
+        camera.set_lens_samples(16);
+        assert_eq!(camera.get_lens_samples(), 1);
+        assert_close(camera.get_ray(0.5, 0.5).unwrap().origin, vec3![1.0, 2.0, 3.0]);
+
+        camera.set_projection(Projection::Perspective);
+        assert_eq!(camera.get_lens_samples(), 16);
+    }
+}
@@ -0,0 +1,335 @@
+use std::error::Error;
+use std::f64::consts::PI;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use image::codecs::hdr::HdrDecoder;
+use image::ImageError;
+use rand::Rng;
+use crate::data_structures::{Vector, Vec3};
+use crate::{vec3};
+use crate::objects::LightSample;
+use crate::utils::deg_to_rad;
+
+// An equirectangular (lat-long) HDR image surrounding the whole scene.
+// Rays that don't hit anything see the environment, and it lights the scene like a huge area light.
+//
+// Pixel values are linear. A value of 1 shows up as 255 in the background, and a uniform environment with
+// a value of 1 lights a surface as much as an ambient light with an intensity of 1.
+pub struct Environment {
+    pixels: Vec<Vec3>,
+    width: usize,
+    height: usize,
+    rotation: f64, // Around the Y axis, in radians
+    intensity: f64,
+    samples: u32, // Samples taken for lighting each surface point
+
+    // Distributions for importance sampling bright parts of the image.
+    // The marginal picks a row, then that row's conditional picks a pixel. Both are cumulative and end at 1.
+    marginal_cdf: Vec<f64>,
+    conditional_cdfs: Vec<Vec<f64>>,
+    pixel_pdfs: Vec<f64>, // Probability of picking each pixel
+}
+
+// Why an environment couldn't be made from an image.
+#[derive(Debug)]
+pub enum EnvironmentError {
+    Image(ImageError),
+    // The image has no pixels, or the number of pixels doesn't match its size.
+    InvalidSize { width: usize, height: usize, pixels: usize },
+}
+
+impl Display for EnvironmentError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EnvironmentError::Image(e) => write!(f, "{}", e),
+            EnvironmentError::InvalidSize { width, height, .. } if width * height == 0 => {
+                write!(f, "image is {}x{} but needs at least one pixel", width, height)
+            }
+            EnvironmentError::InvalidSize { width, height, pixels } => {
+                write!(f, "image is {}x{} but has {} pixels", width, height, pixels)
+            }
+        }
+    }
+}
+
+impl Error for EnvironmentError {}
+
+impl From<ImageError> for EnvironmentError {
+    fn from(error: ImageError) -> Self {
+        EnvironmentError::Image(error)
+    }
+}
+
+impl Environment {
+    // Builds an environment from linear RGB pixels, row by row starting at the top of the image.
+    pub fn new(pixels: Vec<Vec3>, width: usize, height: usize) -> Result<Self, EnvironmentError> {
+        if width * height == 0 || pixels.len() != width * height {
+            return Err(EnvironmentError::InvalidSize { width, height, pixels: pixels.len() });
+        }
+
+        let mut environment = Environment {
+            pixels,
+            width,
+            height,
+            rotation: 0.0,
+            intensity: 1.0,
+            samples: 16,
+            marginal_cdf: vec![],
+            conditional_cdfs: vec![],
+            pixel_pdfs: vec![],
+        };
+        environment.build_distribution();
+        Ok(environment)
+    }
+
+    // Loads a Radiance .hdr or OpenEXR .exr image. Other formats supported by the image crate also work
+    // but are treated as linear.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, EnvironmentError> {
+        let path = path.as_ref();
+        let is_hdr = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("hdr"));
+
+        // Going through image::open would turn .hdr files into 8 bit images, so decode them directly.
+        if is_hdr {
+            let decoder = HdrDecoder::new(BufReader::new(File::open(path).map_err(ImageError::IoError)?))?;
+            let metadata = decoder.metadata();
+            let pixels = decoder.read_image_hdr()?.iter()
+                .map(|p| vec3![p[0] as f64, p[1] as f64, p[2] as f64])
+                .collect();
+            return Environment::new(pixels, metadata.width as usize, metadata.height as usize);
+        }
+
+        let image = image::open(path)?.into_rgb32f();
+        let pixels = image.pixels()
+            .map(|p| vec3![p[0] as f64, p[1] as f64, p[2] as f64])
+            .collect();
+        Environment::new(pixels, image.width() as usize, image.height() as usize)
+    }
+
+    // Rotation in degrees around the Y axis.
+    pub fn set_rotation(&mut self, rotation: f64) {
+        self.rotation = deg_to_rad(rotation);
+    }
+
+    pub fn set_intensity(&mut self, intensity: f64) {
+        self.intensity = intensity;
+    }
+
+    pub fn set_samples(&mut self, samples: u32) {
+        self.samples = samples;
+    }
+
+    // Color seen when looking in a direction, in the 0-255 range like the background color.
+    pub fn get_color(&self, direction: Vec3) -> Vec3 {
+        self.get_radiance(direction) * 255.0
+    }
+
+    // Linear light coming from a direction.
+    pub fn get_radiance(&self, direction: Vec3) -> Vec3 {
+        self.pixels[self.direction_to_pixel(direction)] * self.intensity
+    }
+
+    // Index of the pixel seen in a direction.
+    fn direction_to_pixel(&self, direction: Vec3) -> usize {
+        let (u, v) = self.direction_to_uv(direction);
+        let x = ((u * self.width as f64) as usize).min(self.width - 1);
+        let y = ((v * self.height as f64) as usize).min(self.height - 1);
+        y * self.width + x
+    }
+
+    // Probability density per solid angle of get_samples picking a direction, for all the samples together.
+    pub fn get_pdf(&self, direction: Vec3) -> f64 {
+        let (_, v) = self.direction_to_uv(direction);
+        self.pixel_pdfs[self.direction_to_pixel(direction)] / self.get_pixel_solid_angle(v) * self.samples as f64
+    }
+
+    // How much of the sphere of directions a pixel at some height in the image covers.
+    fn get_pixel_solid_angle(&self, v: f64) -> f64 {
+        let sin_theta = (v * PI).sin().max(1e-6);
+        (2.0 * PI / self.width as f64) * (PI / self.height as f64) * sin_theta
+    }
+
+    // The center of the image is -Z, and the top of the image is +Y. Rotation turns the image around Y.
+    fn direction_to_uv(&self, direction: Vec3) -> (f64, f64) {
+        let d = direction.unit();
+        let phi = d.x.atan2(-d.z) - self.rotation;
+        let u = (phi / (2.0 * PI) + 0.5).rem_euclid(1.0);
+        let v = d.y.clamp(-1.0, 1.0).acos() / PI;
+        (u, v)
+    }
+
+    fn uv_to_direction(&self, u: f64, v: f64) -> Vec3 {
+        let phi = (u - 0.5) * 2.0 * PI + self.rotation;
+        let theta = v * PI;
+        vec3![theta.sin() * phi.sin(), theta.cos(), -theta.sin() * phi.cos()]
+    }
+
+    // Brighter pixels are more likely to be picked. Rows near the poles cover less of the sphere,
+    // so they're weighted down by sin(theta).
+    fn build_distribution(&mut self) {
+        let mut weights = Vec::with_capacity(self.pixels.len());
+        for y in 0..self.height {
+            let sin_theta = (PI * (y as f64 + 0.5) / self.height as f64).sin();
+            for x in 0..self.width {
+                let p = self.pixels[y * self.width + x];
+                let luminance = 0.2126 * p.x + 0.7152 * p.y + 0.0722 * p.z;
+                weights.push(luminance.max(0.0) * sin_theta);
+            }
+        }
+
+        // A completely black image is sampled uniformly, it doesn't matter where since nothing gets lit.
+        let total: f64 = weights.iter().sum();
+        if total <= 0.0 {
+            weights.iter_mut().for_each(|w| *w = 1.0);
+        }
+        let total: f64 = weights.iter().sum();
+
+        self.pixel_pdfs = weights.iter().map(|w| w / total).collect();
+        self.conditional_cdfs = weights.chunks(self.width).map(cumulative).collect();
+        let row_weights: Vec<f64> = weights.chunks(self.width).map(|row| row.iter().sum()).collect();
+        self.marginal_cdf = cumulative(&row_weights);
+    }
+
+    // Pick a direction towards the environment, with brighter directions being more likely.
+    // Returns the direction and the probability density of picking it per solid angle.
+    fn sample_direction(&self, rng: &mut impl Rng) -> (Vec3, f64) {
+        let y = pick(&self.marginal_cdf, rng.gen());
+        let x = pick(&self.conditional_cdfs[y], rng.gen());
+
+        // Jitter inside the pixel
+        let u = (x as f64 + rng.gen::<f64>()) / self.width as f64;
+        let v = (y as f64 + rng.gen::<f64>()) / self.height as f64;
+
+        // Convert the probability of the pixel to a density over the sphere
+        (self.uv_to_direction(u, v), self.pixel_pdfs[y * self.width + x] / self.get_pixel_solid_angle(v))
+    }
+
+    // Light samples for a surface point with the given normal. Directions below the surface can't light it,
+    // so they're dropped but still count towards the total so the estimate stays correct.
+    pub fn get_samples(&self, normal: Vec3) -> Vec<LightSample> {
+        let mut rng = rand::thread_rng();
+        let mut samples = vec![];
+        for _ in 0..self.samples {
+            let (direction, pdf) = self.sample_direction(&mut rng);
+            if direction.dot(normal) <= 0.0 || pdf <= 0.0 {
+                continue;
+            }
+
+            // Shading multiplies by the cosine, so the light only needs dividing by the pdf.
+            // Dividing by pi makes a uniform environment light as much as an ambient light of the same value.
+            let color = self.get_radiance(direction) / (pdf * PI * self.samples as f64);
+            samples.push(LightSample { direction, distance: f64::INFINITY, color, pdf: pdf * self.samples as f64 });
+        }
+        samples
+    }
+}
+
+// Running total of the weights, scaled so the last entry is 1.
+fn cumulative(weights: &[f64]) -> Vec<f64> {
+    let total: f64 = weights.iter().sum();
+    let mut running = 0.0;
+    weights.iter().map(|w| {
+        running += if total > 0.0 { w / total } else { 1.0 / weights.len() as f64 };
+        running
+    }).collect()
+}
+
+// Index of the first entry in a cumulative distribution that is above the random value.
+fn pick(cdf: &[f64], random: f64) -> usize {
+    cdf.partition_point(|&c| c <= random).min(cdf.len() - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    // A dark image with one bright pixel at (x, y).
+    fn bright_pixel(width: usize, height: usize, x: usize, y: usize) -> Environment {
+        let mut pixels = vec![vec3![0.01, 0.01, 0.01]; width * height];
+        pixels[y * width + x] = vec3![1000.0, 1000.0, 1000.0];
+        Environment::new(pixels, width, height).unwrap()
+    }
+
+    #[test]
+    fn cdfs_end_at_one() {
+        let pixels = (0..32).map(|i| vec3![i as f64, 0.5, (i % 5) as f64]).collect();
+        let environment = Environment::new(pixels, 8, 4).unwrap();
+        assert_eq!(environment.marginal_cdf.len(), 4);
+        assert!((environment.marginal_cdf.last().unwrap() - 1.0).abs() < 1e-9);
+        for row in environment.conditional_cdfs.iter() {
+            assert_eq!(row.len(), 8);
+            assert!((row.last().unwrap() - 1.0).abs() < 1e-9);
+        }
+
+        let black = Environment::new(vec![vec3![0.0, 0.0, 0.0]; 6], 3, 2).unwrap();
+        assert!((black.marginal_cdf.last().unwrap() - 1.0).abs() < 1e-9);
+        assert!((black.pixel_pdfs.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn the_bright_pixel_is_picked() {
+        let environment = bright_pixel(16, 8, 11, 3);
+        let mut rng = StdRng::seed_from_u64(1);
+        let picked = (0..1000)
+            .filter(|_| {
+                let (direction, _) = environment.sample_direction(&mut rng);
+                let (u, v) = environment.direction_to_uv(direction);
+                ((u * 16.0) as usize, (v * 8.0) as usize) == (11, 3)
+            })
+            .count();
+        assert!(picked > 990, "bright pixel picked {} times out of 1000", picked);
+    }
+
+    #[test]
+    fn sampled_pdfs_cover_the_sphere() {
+        // Averaging 1/pdf over samples estimates the solid angle of the whole sphere.
+        let pixels = (0..64).map(|i| vec3![(i % 7) as f64 + 0.1, 1.0, 0.5]).collect();
+        let environment = Environment::new(pixels, 8, 8).unwrap();
+        let mut rng = StdRng::seed_from_u64(2);
+        let n = 200_000;
+        let total: f64 = (0..n).map(|_| 1.0 / environment.sample_direction(&mut rng).1).sum();
+        assert!((total / n as f64 - 4.0 * PI).abs() < 0.2, "{}", total / n as f64);
+    }
+
+    #[test]
+    fn get_pdf_matches_the_sampled_pdf() {
+        let pixels = (0..64).map(|i| vec3![(i % 7) as f64 + 0.1, 1.0, 0.5]).collect();
+        let mut environment = Environment::new(pixels, 8, 8).unwrap();
+        environment.set_rotation(30.0);
+        environment.set_samples(4);
+        let mut rng = StdRng::seed_from_u64(3);
+        for _ in 0..100 {
+            let (direction, pdf) = environment.sample_direction(&mut rng);
+            // get_pdf is for all the samples together
+            let looked_up = environment.get_pdf(direction) / 4.0;
+            assert!((looked_up - pdf).abs() < pdf * 1e-6, "{} is not {}", looked_up, pdf);
+        }
+    }
+
+    #[test]
+    fn uniform_environment_lights_like_an_ambient_light() {
+        let mut environment = Environment::new(vec![vec3![1.0, 1.0, 1.0]; 32], 8, 4).unwrap();
+        environment.set_samples(20_000);
+        let normal = vec3![0.0, 1.0, 0.0];
+        let total = environment.get_samples(normal).iter()
+            .fold(0.0, |total, sample| total + sample.color.x * sample.direction.dot(normal));
+        assert!((total - 1.0).abs() < 0.05, "{}", total);
+    }
+
+    #[test]
+    fn empty_images_are_rejected() {
+        assert!(matches!(
+            Environment::new(vec![], 0, 0),
+            Err(EnvironmentError::InvalidSize { width: 0, height: 0, pixels: 0 })
+        ));
+        assert!(Environment::new(vec![], 4, 0).is_err());
+        assert!(Environment::new(vec![vec3![1.0, 1.0, 1.0]; 3], 2, 2).is_err());
+        assert_eq!(
+            Environment::new(vec![], 0, 0).err().unwrap().to_string(),
+            "image is 0x0 but needs at least one pixel"
+        );
+    }
+}
@@ -1,40 +1,187 @@
+use std::f64::consts::PI;
 use crate::data_structures::{Vector, Vec3};
 use crate::{vec3};
+use crate::objects::{DiskLight, RectangleLight, SphereLight};
+use crate::utils::deg_to_rad;
+
+// A single path light takes to reach a surface point.
+#[derive(Debug, Copy, Clone)]
+pub struct LightSample {
+    pub direction: Vec3, // Unit direction from the surface point towards the light
+    pub distance: f64, // How far the light is in that direction
+    pub color: Vec3, // Light arriving along this path. All samples of a light add up to its full contribution.
+    // How likely the samples of the light were to go in this direction, per solid angle. Used by the path tracer
+    // to weigh light samples against rays that hit the light by chance. 0 for lights without an area.
+    pub pdf: f64,
+}
+
+// Where a ray hits a light that has an area.
+#[derive(Debug, Copy, Clone)]
+pub struct LightHit {
+    pub distance: f64,
+    // Light leaving the light towards the ray, in the same units as environment pixels. 1 is as bright as a white surface
+    // fully lit by a light of 1.
+    pub radiance: Vec3,
+    pub pdf: f64, // Same as LightSample::pdf for the direction of the ray
+}
 
 pub trait WorldLight {
     fn get_light_color(self, surface_point: Vec3) -> Vec3;
-    fn get_position(self) -> Vec3;
+    // If any light from this light reaches the surface point, ignoring objects in the way.
+    // Points that aren't lit also can't be in the shadow of this light.
+    fn illuminates(self, surface_point: Vec3) -> bool;
+    // Unit direction from the surface point towards the light, and how far away the light is in that direction.
+    // Shadow rays only need to look for objects up to that distance.
+    fn get_direction_from(self, surface_point: Vec3) -> (Vec3, f64);
+    // Points on the light that the surface point is lit by. Lights with an area return multiple samples
+    // so that lighting and shadows are averaged over the whole light.
+    fn get_samples(self, surface_point: Vec3) -> Vec<LightSample>;
     fn set_color(&mut self, color: Vec3);
-    fn set_position(&mut self, position: Vec3);
+    // Where a ray from the surface point going in a direction hits the light, if it does.
+    // Lights without an area can't be hit.
+    fn hit(self, _surface_point: Vec3, _direction: Vec3) -> Option<LightHit> where Self: Sized {
+        None
+    }
+    // Random point on the light for a photon to leave from. Lights infinitely far away have no such point.
+    fn get_photon_origin(self) -> Option<Vec3> where Self: Sized {
+        None
+    }
+    // Light leaving a point picked by get_photon_origin in a direction, per solid angle, divided by the chance of picking
+    // the point. Pi times bigger than light colors, like LightHit::radiance, so photons light surfaces as much as the light does.
+    fn get_photon_intensity(self, _origin: Vec3, _direction: Vec3) -> Vec3 where Self: Sized {
+        vec3![0.0, 0.0, 0.0]
+    }
+}
+
+// The only sample of a light that has no area.
+fn single_sample<L: WorldLight + Copy>(light: L, surface_point: Vec3) -> Vec<LightSample> {
+    let (direction, distance) = light.get_direction_from(surface_point);
+    vec![LightSample { direction, distance, color: light.get_light_color(surface_point), pdf: 0.0 }]
 }
 
 #[derive(Debug, Copy, Clone)]
 pub enum Light {
-    PointLight(PointLight)
+    PointLight(PointLight),
+    SpotLight(SpotLight),
+    DirectionalLight(DirectionalLight),
+    RectangleLight(RectangleLight),
+    DiskLight(DiskLight),
+    SphereLight(SphereLight)
+}
+
+impl Light {
+    // Where the light is, which is the center of lights with an area.
+    // Directional lights are infinitely far away, so they don't have a position.
+    pub fn get_position(&self) -> Option<Vec3> {
+        match self {
+            Light::PointLight(light) => Some(light.get_position()),
+            Light::SpotLight(light) => Some(light.get_position()),
+            Light::DirectionalLight(_) => None,
+            Light::RectangleLight(light) => Some(light.get_position()),
+            Light::DiskLight(light) => Some(light.get_position()),
+            Light::SphereLight(light) => Some(light.get_position())
+        }
+    }
+
+    // Moves the light. Does nothing for directional lights since they don't have a position.
+    pub fn set_position(&mut self, position: Vec3) {
+        match self {
+            Light::PointLight(light) => light.set_position(position),
+            Light::SpotLight(light) => light.set_position(position),
+            Light::DirectionalLight(_) => {},
+            Light::RectangleLight(light) => light.set_position(position),
+            Light::DiskLight(light) => light.set_position(position),
+            Light::SphereLight(light) => light.set_position(position)
+        }
+    }
 }
 
 impl WorldLight for Light {
     fn get_light_color(self, surface_point: Vec3) -> Vec3 {
         match self {
-            Light::PointLight(light) => light.get_light_color(surface_point)
+            Light::PointLight(light) => light.get_light_color(surface_point),
+            Light::SpotLight(light) => light.get_light_color(surface_point),
+            Light::DirectionalLight(light) => light.get_light_color(surface_point),
+            Light::RectangleLight(light) => light.get_light_color(surface_point),
+            Light::DiskLight(light) => light.get_light_color(surface_point),
+            Light::SphereLight(light) => light.get_light_color(surface_point)
+        }
+    }
+
+    fn illuminates(self, surface_point: Vec3) -> bool {
+        match self {
+            Light::PointLight(light) => light.illuminates(surface_point),
+            Light::SpotLight(light) => light.illuminates(surface_point),
+            Light::DirectionalLight(light) => light.illuminates(surface_point),
+            Light::RectangleLight(light) => light.illuminates(surface_point),
+            Light::DiskLight(light) => light.illuminates(surface_point),
+            Light::SphereLight(light) => light.illuminates(surface_point)
+        }
+    }
+
+    fn get_direction_from(self, surface_point: Vec3) -> (Vec3, f64) {
+        match self {
+            Light::PointLight(light) => light.get_direction_from(surface_point),
+            Light::SpotLight(light) => light.get_direction_from(surface_point),
+            Light::DirectionalLight(light) => light.get_direction_from(surface_point),
+            Light::RectangleLight(light) => light.get_direction_from(surface_point),
+            Light::DiskLight(light) => light.get_direction_from(surface_point),
+            Light::SphereLight(light) => light.get_direction_from(surface_point)
         }
     }
 
-    fn get_position(self) -> Vec3 {
+    fn get_samples(self, surface_point: Vec3) -> Vec<LightSample> {
         match self {
-            Light::PointLight(light) => light.get_position()
+            Light::PointLight(light) => light.get_samples(surface_point),
+            Light::SpotLight(light) => light.get_samples(surface_point),
+            Light::DirectionalLight(light) => light.get_samples(surface_point),
+            Light::RectangleLight(light) => light.get_samples(surface_point),
+            Light::DiskLight(light) => light.get_samples(surface_point),
+            Light::SphereLight(light) => light.get_samples(surface_point)
         }
     }
 
     fn set_color(&mut self, color: Vec3) {
         match self {
-            Light::PointLight(light) => light.set_color(color)
+            Light::PointLight(light) => light.set_color(color),
+            Light::SpotLight(light) => light.set_color(color),
+            Light::DirectionalLight(light) => light.set_color(color),
+            Light::RectangleLight(light) => light.set_color(color),
+            Light::DiskLight(light) => light.set_color(color),
+            Light::SphereLight(light) => light.set_color(color)
+        }
+    }
+
+    fn hit(self, surface_point: Vec3, direction: Vec3) -> Option<LightHit> {
+        match self {
+            Light::PointLight(light) => light.hit(surface_point, direction),
+            Light::SpotLight(light) => light.hit(surface_point, direction),
+            Light::DirectionalLight(light) => light.hit(surface_point, direction),
+            Light::RectangleLight(light) => light.hit(surface_point, direction),
+            Light::DiskLight(light) => light.hit(surface_point, direction),
+            Light::SphereLight(light) => light.hit(surface_point, direction)
+        }
+    }
+
+    fn get_photon_origin(self) -> Option<Vec3> {
+        match self {
+            Light::PointLight(light) => light.get_photon_origin(),
+            Light::SpotLight(light) => light.get_photon_origin(),
+            Light::DirectionalLight(light) => light.get_photon_origin(),
+            Light::RectangleLight(light) => light.get_photon_origin(),
+            Light::DiskLight(light) => light.get_photon_origin(),
+            Light::SphereLight(light) => light.get_photon_origin()
         }
     }
 
-    fn set_position(&mut self, position: Vec3) {
+    fn get_photon_intensity(self, origin: Vec3, direction: Vec3) -> Vec3 {
         match self {
-            Light::PointLight(light) => light.set_position(position)
+            Light::PointLight(light) => light.get_photon_intensity(origin, direction),
+            Light::SpotLight(light) => light.get_photon_intensity(origin, direction),
+            Light::DirectionalLight(light) => light.get_photon_intensity(origin, direction),
+            Light::RectangleLight(light) => light.get_photon_intensity(origin, direction),
+            Light::DiskLight(light) => light.get_photon_intensity(origin, direction),
+            Light::SphereLight(light) => light.get_photon_intensity(origin, direction)
         }
     }
 }
@@ -55,6 +202,14 @@ impl PointLight {
             position: vec3![0.0, 0.0, 0.0],
         }
     }
+
+    pub fn get_position(&self) -> Vec3 {
+        self.position
+    }
+
+    pub fn set_position(&mut self, position: Vec3) {
+        self.position = position;
+    }
 }
 
 impl WorldLight for PointLight {
@@ -65,20 +220,257 @@ impl WorldLight for PointLight {
         (self.color * self.intensity) / (distance * distance)
     }
 
-    fn get_position(self) -> Vec3 {
-        self.position
+    fn illuminates(self, _: Vec3) -> bool {
+        true
+    }
+
+    fn get_direction_from(self, surface_point: Vec3) -> (Vec3, f64) {
+        let to_light = self.position - surface_point;
+        (to_light.unit(), to_light.length())
+    }
+
+    fn get_samples(self, surface_point: Vec3) -> Vec<LightSample> {
+        single_sample(self, surface_point)
     }
 
     fn set_color(&mut self, color: Vec3) {
         self.color = color;
     }
 
-    fn set_position(&mut self, position: Vec3) {
+    fn get_photon_origin(self) -> Option<Vec3> {
+        Some(self.position)
+    }
+
+    // The light color one unit away.
+    fn get_photon_intensity(self, _: Vec3, _: Vec3) -> Vec3 {
+        self.color * (PI * self.intensity)
+    }
+}
+
+// A point light that only shines inside a cone around its direction.
+// Inside the inner angle the light is at full strength, then it smoothly fades out until the outer angle.
+// Angles are in degrees, measured from the direction to the edge of the cone.
+#[derive(Debug, Copy, Clone)]
+pub struct SpotLight {
+    pub color: Vec3,
+    pub position: Vec3,
+    pub intensity: f64,
+    direction: Vec3,
+    cos_inner: f64,
+    cos_outer: f64,
+}
+
+impl SpotLight {
+    // The inner angle is clamped so it's never wider than the outer angle.
+    pub fn new(color: Vec3, intensity: f64, direction: Vec3, inner_angle: f64, outer_angle: f64) -> Self {
+        let mut light = SpotLight {
+            color,
+            intensity,
+            position: vec3![0.0, 0.0, 0.0],
+            direction: vec3![0.0, -1.0, 0.0],
+            cos_inner: 1.0,
+            cos_outer: 1.0,
+        };
+        light.set_direction(direction);
+        light.set_cone(inner_angle, outer_angle);
+        light
+    }
+
+    pub fn get_position(&self) -> Vec3 {
+        self.position
+    }
+
+    pub fn set_position(&mut self, position: Vec3) {
         self.position = position;
     }
+
+    pub fn set_direction(&mut self, direction: Vec3) {
+        self.direction = direction.unit();
+    }
+
+    pub fn set_cone(&mut self, inner_angle: f64, outer_angle: f64) {
+        self.cos_outer = deg_to_rad(outer_angle).cos();
+        self.cos_inner = deg_to_rad(inner_angle.min(outer_angle)).cos();
+    }
+
+    pub fn get_direction(&self) -> Vec3 {
+        self.direction
+    }
+
+    // How much of the light reaches a point based on where it is in the cone. 1 inside the inner cone, 0 outside the outer cone.
+    fn cone_falloff(&self, surface_point: Vec3) -> f64 {
+        let cos_angle = (surface_point - self.position).unit().dot(self.direction);
+        if cos_angle <= self.cos_outer {
+            return 0.0;
+        }
+        if cos_angle >= self.cos_inner {
+            return 1.0;
+        }
+
+        // Smoothstep between the outer and inner edge of the cone
+        let t = (cos_angle - self.cos_outer) / (self.cos_inner - self.cos_outer);
+        t * t * (3.0 - 2.0 * t)
+    }
+}
+
+impl WorldLight for SpotLight {
+    // Same attenuation as a point light, scaled by how far into the cone the point is.
+    fn get_light_color(self, surface_point: Vec3) -> Vec3 {
+        let distance = (self.position - surface_point).length();
+        (self.color * self.intensity * self.cone_falloff(surface_point)) / (distance * distance)
+    }
+
+    fn illuminates(self, surface_point: Vec3) -> bool {
+        self.cone_falloff(surface_point) > 0.0
+    }
+
+    fn get_direction_from(self, surface_point: Vec3) -> (Vec3, f64) {
+        let to_light = self.position - surface_point;
+        (to_light.unit(), to_light.length())
+    }
+
+    fn get_samples(self, surface_point: Vec3) -> Vec<LightSample> {
+        single_sample(self, surface_point)
+    }
+
+    fn set_color(&mut self, color: Vec3) {
+        self.color = color;
+    }
+
+    fn get_photon_origin(self) -> Option<Vec3> {
+        Some(self.position)
+    }
+
+    fn get_photon_intensity(self, origin: Vec3, direction: Vec3) -> Vec3 {
+        self.color * (PI * self.intensity * self.cone_falloff(origin + direction))
+    }
+}
+
+// A light infinitely far away, like the sun. All of its rays are parallel and it doesn't get weaker with distance.
+#[derive(Debug, Copy, Clone)]
+pub struct DirectionalLight {
+    pub color: Vec3,
+    pub intensity: f64,
+    direction: Vec3, // Direction the light travels in
+}
+
+impl DirectionalLight {
+    pub fn new(color: Vec3, intensity: f64, direction: Vec3) -> Self {
+        DirectionalLight { color, intensity, direction: direction.unit() }
+    }
+
+    pub fn set_direction(&mut self, direction: Vec3) {
+        self.direction = direction.unit();
+    }
+
+    pub fn get_direction(&self) -> Vec3 {
+        self.direction
+    }
+}
+
+impl WorldLight for DirectionalLight {
+    fn get_light_color(self, _: Vec3) -> Vec3 {
+        self.color * self.intensity
+    }
+
+    fn illuminates(self, _: Vec3) -> bool {
+        true
+    }
+
+    // The light is always in the opposite direction it travels, and nothing is behind it.
+    fn get_direction_from(self, _: Vec3) -> (Vec3, f64) {
+        (self.direction * -1.0, f64::INFINITY)
+    }
+
+    fn get_samples(self, surface_point: Vec3) -> Vec<LightSample> {
+        single_sample(self, surface_point)
+    }
+
+    fn set_color(&mut self, color: Vec3) {
+        self.color = color;
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
 pub struct AmbientLight {
     pub intensity: Vec3
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Point one unit away from the light, the given angle in degrees away from straight down.
+    fn point_at_angle(light: &SpotLight, angle: f64) -> Vec3 {
+        let (sin, cos) = deg_to_rad(angle).sin_cos();
+        light.position + vec3![sin, -cos, 0.0]
+    }
+
+    fn spot() -> SpotLight {
+        let mut light = SpotLight::new(vec3![255.0, 255.0, 255.0], 2.0, vec3![0.0, -3.0, 0.0], 20.0, 40.0);
+        light.set_position(vec3![1.0, 5.0, -2.0]);
+        light
+    }
+
+    #[test]
+    fn spot_light_is_full_inside_the_inner_cone_and_dark_outside_the_outer_cone() {
+        let light = spot();
+        for angle in [0.0, 10.0, 19.9, 20.0] {
+            assert!((light.cone_falloff(point_at_angle(&light, angle)) - 1.0).abs() < 1e-9, "angle {}", angle);
+        }
+        for angle in [40.01, 45.0, 90.0, 180.0] {
+            assert_eq!(light.cone_falloff(point_at_angle(&light, angle)), 0.0, "angle {}", angle);
+            assert!(!light.illuminates(point_at_angle(&light, angle)));
+        }
+
+        // Full strength is the same as a point light
+        let point = PointLight { color: light.color, position: light.position, intensity: light.intensity };
+        let inside = point_at_angle(&light, 5.0);
+        assert_eq!(light.get_light_color(inside), point.get_light_color(inside));
+    }
+
+    #[test]
+    fn spot_light_falloff_is_a_smoothstep_between_the_cones() {
+        let light = spot();
+        let falloff = |angle: f64| light.cone_falloff(point_at_angle(&light, angle));
+
+        // Smoothstep of how far the cosine is from the outer to the inner edge
+        let (cos_inner, cos_outer) = (deg_to_rad(20.0).cos(), deg_to_rad(40.0).cos());
+        for angle in [22.0, 30.0, 38.0] {
+            let t = (deg_to_rad(angle).cos() - cos_outer) / (cos_inner - cos_outer);
+            assert!((falloff(angle) - t * t * (3.0 - 2.0 * t)).abs() < 1e-9);
+        }
+
+        // Fades out steadily, and flattens out towards both edges
+        let angles: Vec<f64> = (0..=40).map(|i| 20.0 + i as f64 * 0.5).collect();
+        assert!(angles.windows(2).all(|w| falloff(w[0]) > falloff(w[1])));
+        assert!(1.0 - falloff(20.1) < 1e-3);
+        assert!(falloff(39.9) < 1e-3);
+    }
+
+    #[test]
+    fn inner_angle_is_clamped_to_the_outer_angle() {
+        let mut light = spot();
+        light.set_cone(50.0, 30.0);
+        assert_eq!(light.cone_falloff(point_at_angle(&light, 29.9)), 1.0);
+        assert_eq!(light.cone_falloff(point_at_angle(&light, 30.1)), 0.0);
+    }
+
+    #[test]
+    fn directional_lights_have_no_position() {
+        let mut sun = Light::DirectionalLight(DirectionalLight::new(vec3![255.0, 255.0, 255.0], 1.0, vec3![0.0, -2.0, 0.0]));
+        sun.set_position(vec3![1.0, 2.0, 3.0]);
+        assert_eq!(sun.get_position(), None);
+
+        // Light comes from straight up no matter where the surface is, and nothing behind it can block it
+        for point in [vec3![0.0, 0.0, 0.0], vec3![100.0, -50.0, 3.0]] {
+            assert_eq!(sun.get_direction_from(point), (vec3![0.0, 1.0, 0.0], f64::INFINITY));
+            assert_eq!(sun.get_light_color(point), vec3![255.0, 255.0, 255.0]);
+        }
+
+        let mut point = Light::PointLight(PointLight::new(vec3![255.0, 255.0, 255.0], 1.0));
+        point.set_position(vec3![1.0, 2.0, 3.0]);
+        assert_eq!(point.get_position(), Some(vec3![1.0, 2.0, 3.0]));
+        assert_eq!(point.get_direction_from(vec3![1.0, 2.0, -1.0]), (vec3![0.0, 0.0, 1.0], 4.0));
+    }
+}
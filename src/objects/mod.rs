@@ -13,11 +13,31 @@ pub use plane::*;
 mod lights;
 pub use lights::*;
 
+mod area_lights;
+pub use area_lights::*;
+
+mod environment;
+pub use environment::*;
+
+mod sky;
+pub use sky::*;
+
 mod camera;
 pub use camera::*;
 
 mod scene;
 pub use scene::*;
 
+mod path_tracer;
+
+mod photon_map;
+pub use photon_map::*;
+
+mod ambient_occlusion;
+pub use ambient_occlusion::*;
+
+mod aov;
+pub use aov::*;
+
 mod bounding_volume;
 pub use bounding_volume::*;
\ No newline at end of file
@@ -0,0 +1,204 @@
+use std::f64::consts::PI;
+use rand::Rng;
+use crate::{Ray, Scene, Vec3, vec3, Vector, WorldLight};
+use crate::materials::{Bsdf, Mat};
+use crate::objects::LightHit;
+use crate::traits::HitData;
+
+// Monte Carlo path tracing. Instead of only lighting surfaces directly, light is followed as it bounces around
+// the scene, which gives soft indirect light, color bleeding and blurry reflections of other objects.
+//
+// At every bounce the lights are sampled directly (next event estimation), then the material picks where the path
+// goes next. Paths that hit a light or the environment by chance are weighed against the light samples with multiple
+// importance sampling, so the light isn't counted twice and each is used where it's less noisy. After a few bounces
+// Russian roulette randomly ends paths that carry little light, making up for it in the ones that go on.
+//
+// Ambient lights don't light anything, since bounced light takes their place.
+
+const ROULETTE_DEPTH: u32 = 3; // Bounces before paths can be ended early
+const MAX_SURVIVAL: f64 = 0.95; // Even bright paths can be ended, so paths inside mirrors don't go on forever
+const MAX_PASS_THROUGHS: u32 = 16; // Surfaces a path goes through inside of higher priority objects before giving up
+const SHADOW_EPSILON: f64 = 1e-4;
+
+impl Scene {
+    // Color seen along a camera ray, 0-255 like get_color_from_ray.
+    pub fn get_path_color(&self, camera_ray: Ray) -> Vec3 {
+        let mut rng = rand::thread_rng();
+        let mut ray = camera_ray;
+        let mut radiance = vec3![0.0, 0.0, 0.0];
+        let mut throughput = vec3![1.0, 1.0, 1.0]; // How much of the light found along the path makes it to the camera
+        let mut bsdf_pdf = 0.0; // Pdf of the direction the ray was sent in. 0 for camera rays and sharp bounces.
+        let mut bounce = 0;
+        let mut pass_throughs = 0;
+
+        loop {
+            let hit = self.get_hit(ray, self.get_render_distance());
+            let max_distance = if hit.did_hit { (hit.hit_point - ray.origin).length() } else { f64::INFINITY };
+
+            // Lights with an area are in front of whatever is behind them
+            if let Some(light_hit) = self.hit_lights(ray, max_distance) {
+                throughput *= self.get_medium_transmittance(ray, light_hit.distance);
+                radiance += throughput * light_hit.radiance * mis_weight(bsdf_pdf, light_hit.pdf);
+                break;
+            }
+            if !hit.did_hit {
+                radiance += throughput * match self.get_environment() {
+                    Some(environment) => environment.get_radiance(ray.direction) * mis_weight(bsdf_pdf, environment.get_pdf(ray.direction)),
+                    None => self.get_background_color() / 255.0
+                };
+                break;
+            }
+            throughput *= self.get_medium_transmittance(ray, max_distance);
+
+            // Glowing objects are lights too
+            let emission = hit.mat.get_emission(self, &hit);
+            if emission.x.max(emission.y).max(emission.z) > 0.0 {
+                let cos_light = (-1.0 * ray.direction.unit()).dot(hit.geometric_normal);
+                let light_pdf = self.get_mesh_light().get_pdf(emission, max_distance, cos_light);
+                radiance += throughput * emission * mis_weight(bsdf_pdf, light_pdf);
+            }
+
+            let mut hit = hit;
+            if let Some(normal_map) = hit.mat.get_normal_map() {
+                hit.normal = normal_map.apply(self, &hit);
+            }
+            let direction = ray.direction.unit();
+
+            // Inside of an object with a higher priority this surface isn't there, so just keep going
+            let (crossing, ratio) = self.get_crossing(ray, &hit);
+            if !crossing.is_interface {
+                pass_throughs += 1;
+                if pass_throughs > MAX_PASS_THROUGHS {
+                    break;
+                }
+                ray = Ray::new_in_media(hit.hit_point + direction * 1e-6, direction, crossing.after);
+                continue;
+            }
+
+            let bsdf = hit.mat.get_bsdf(self, &hit, ratio);
+            let view_dir = -1.0 * direction;
+            radiance += throughput * self.sample_lights(&hit, &bsdf, view_dir);
+
+            if bounce >= self.get_ray_depth() {
+                break;
+            }
+            let Some(sample) = bsdf.sample(view_dir, &mut rng) else {
+                break;
+            };
+            throughput *= sample.weight;
+
+            if bounce >= ROULETTE_DEPTH {
+                let survival = throughput.x.max(throughput.y).max(throughput.z).min(MAX_SURVIVAL);
+                if rng.gen::<f64>() >= survival {
+                    break;
+                }
+                throughput /= survival;
+            }
+
+            // Reflected rays stay on the side the ray came from and transmitted rays go to the other side
+            let offset = bsdf.get_geometric_normal() * 1e-6;
+            ray = if sample.transmitted {
+                Ray::new_in_media(hit.hit_point - offset, sample.direction, crossing.after)
+            } else {
+                Ray::new_in_media(hit.hit_point + offset, sample.direction, crossing.before)
+            };
+            bsdf_pdf = sample.pdf;
+            bounce += 1;
+        }
+        radiance * 255.0
+    }
+
+    // Light arriving at the hit directly from lights and the environment, scattered towards the viewer.
+    fn sample_lights(&self, hit: &HitData, bsdf: &Bsdf, view_dir: Vec3) -> Vec3 {
+        // Light samples only come from the side the normal faces
+        let mut facing = hit.clone();
+        facing.normal = bsdf.get_normal();
+        facing.geometric_normal = bsdf.get_geometric_normal();
+
+        let mut total = vec3![0.0, 0.0, 0.0];
+        for sample in self.get_light_samples(&facing) {
+            let scattered = bsdf.eval(view_dir, sample.direction);
+            if scattered.x.max(scattered.y).max(scattered.z) <= 0.0 || !self.is_visible(&facing, sample.direction, sample.distance) {
+                continue;
+            }
+
+            // Light sample colors are pi times smaller than the light arriving, see LightHit
+            let weight = if sample.pdf > 0.0 { mis_weight(sample.pdf, bsdf.pdf(view_dir, sample.direction)) } else { 1.0 };
+            total += scattered * sample.color * (PI * facing.normal.dot(sample.direction) * weight);
+        }
+        total
+    }
+
+    // If nothing blocks the way from the hit towards a light. Everything blocks light here, light that goes
+    // through transparent objects is found by the paths instead.
+    fn is_visible(&self, hit: &HitData, direction: Vec3, distance: f64) -> bool {
+        if !self.get_render_shadows() {
+            return true;
+        }
+        let shadow_ray = Ray::new(hit.hit_point + hit.geometric_normal * 1e-6, direction);
+        !self.get_hit(shadow_ray, distance - SHADOW_EPSILON).did_hit
+    }
+
+    // Closest light with an area the ray hits before going max distance.
+    fn hit_lights(&self, ray: Ray, max_distance: f64) -> Option<LightHit> {
+        let direction = ray.direction.unit();
+        self.get_lights().iter()
+            .filter_map(|light| light.hit(ray.origin, direction))
+            .filter(|light_hit| light_hit.distance < max_distance)
+            .min_by(|a, b| a.distance.total_cmp(&b.distance))
+    }
+
+    // Light left after travelling some distance through whatever the ray is in.
+    fn get_medium_transmittance(&self, ray: Ray, distance: f64) -> Vec3 {
+        match ray.media.current() {
+            Some(medium) => medium.get_transmittance(distance),
+            None => vec3![1.0, 1.0, 1.0]
+        }
+    }
+}
+
+// Balance heuristic. How much a sample picked with one pdf counts, when it could also have been picked with another.
+// Samples that only one of them can pick, like sharp reflections, count fully.
+fn mis_weight(pdf: f64, other_pdf: f64) -> f64 {
+    if pdf <= 0.0 || other_pdf <= 0.0 { 1.0 } else { pdf / (pdf + other_pdf) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::materials::{Flat, Material};
+    use crate::objects::{Environment, Integrator, Sphere};
+
+    #[test]
+    fn mis_weights_add_up_to_one() {
+        for (a, b) in [(1.0, 1.0), (0.25, 3.0), (100.0, 0.01), (1e-6, 5.0)] {
+            assert!((mis_weight(a, b) + mis_weight(b, a) - 1.0).abs() < 1e-12, "{} and {}", a, b);
+        }
+        // Sharp bounces can't be picked by the other strategy, so they count fully
+        assert_eq!(mis_weight(0.0, 2.0), 1.0);
+        assert_eq!(mis_weight(2.0, 0.0), 1.0);
+    }
+
+    #[test]
+    fn diffuse_sphere_in_a_uniform_environment_is_albedo_times_environment() {
+        // A convex object only ever sees the environment, so every path gets albedo * environment.
+        let mut scene = Scene::new((1, 1), 100.0, vec3![0.0, 0.0, 0.0], 90.0, 0, 8, 1.0, true);
+        scene.set_integrator(Integrator::Path { samples: 1 });
+        scene.set_environment(Some(Environment::new(vec![vec3![0.8, 0.8, 0.8]; 32], 8, 4).unwrap()));
+        scene.push_object(Box::new(Sphere::new(1.0, Material::Flat(Flat::new(vec3![127.5, 63.75, 255.0])))));
+
+        let paths = 4000;
+        let mut total = vec3![0.0, 0.0, 0.0];
+        for i in 0..paths {
+            // Spread the paths over the visible side of the sphere
+            let x = (i % 20) as f64 / 20.0 - 0.5;
+            let y = (i / 20 % 20) as f64 / 20.0 - 0.5;
+            total += scene.get_path_color(Ray::new(vec3![x, y, 5.0], vec3![0.0, 0.0, -1.0]));
+        }
+        let average = total / paths as f64;
+        let expected = vec3![127.5, 63.75, 255.0] * 0.8;
+        for i in 0..3 {
+            assert!((average[i] - expected[i]).abs() < expected[i] * 0.03, "{:?} is not {:?}", average, expected);
+        }
+    }
+}
@@ -0,0 +1,255 @@
+use std::f64::consts::PI;
+use rand::Rng;
+use crate::{Light, Ray, Scene, Vec3, vec3, Vector, WorldLight};
+use crate::data_structures::{KdPoint, KdTree};
+use crate::materials::Mat;
+use crate::traits::HitData;
+use crate::utils::orthonormal_basis;
+
+// Photon mapping for caustics, the bright patterns glass and mirrors focus light into. Light samples can't find these,
+// since the light has to bounce off or go through something on its way.
+//
+// Before rendering, photons are sent out from the lights towards objects that can reflect or refract sharply, each
+// carrying a share of the light. They follow sharp reflections and refractions, and wherever they land on a diffuse
+// surface after at least one of those they're stored in a kd-tree. While rendering, the photons around a hit point are
+// added up to find how much focused light arrived there.
+//
+// Photons are only aimed at the bounding spheres of the objects that focus light, so none are wasted on the rest of the
+// scene. Environments and ambient lights don't send out photons.
+
+const MAX_PASS_THROUGHS: u32 = 16; // Surfaces a photon goes through inside of higher priority objects before giving up
+const DIRECTIONAL_DISTANCE: f64 = 1e4; // How far back photons from directional lights start
+
+// How many photons are sent out in total, and how far around a point they're gathered from. More photons give smoother
+// caustics, and a smaller radius gives sharper ones but needs more photons.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Caustics {
+    pub photons: u32,
+    pub radius: f64
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Photon {
+    pub position: Vec3,
+    pub direction: Vec3, // Unit direction the photon was going in when it landed
+    pub power: Vec3 // Light the photon carries, in the units of LightHit::radiance times area and solid angle
+}
+
+impl KdPoint for Photon {
+    fn get_position(&self) -> Vec3 {
+        self.position
+    }
+}
+
+pub struct PhotonMap {
+    photons: KdTree<Photon>,
+    radius: f64
+}
+
+impl PhotonMap {
+    pub fn new(photons: Vec<Photon>, radius: f64) -> Self {
+        PhotonMap { photons: KdTree::new(photons), radius }
+    }
+
+    pub fn len(&self) -> usize {
+        self.photons.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.photons.is_empty()
+    }
+
+    // Focused light arriving at the hit, like a light sample color times the cosine of the angle it arrives at.
+    // Only photons that landed on the side of the surface the ray came from count.
+    pub fn get_light(&self, hit: &HitData) -> Vec3 {
+        let side = hit.ray.direction.dot(hit.geometric_normal);
+        let mut total = vec3![0.0, 0.0, 0.0];
+        self.photons.for_each_within(hit.hit_point, self.radius, &mut |photon, distance_squared| {
+            // Cone filter, photons closer to the point count more so the edges of caustics stay sharp
+            if photon.direction.dot(hit.geometric_normal) * side > 0.0 {
+                total += photon.power * (1.0 - distance_squared.sqrt() / self.radius);
+            }
+        });
+
+        // The filter averages out to a third over the disk. The last pi turns the light into light sample units.
+        total * 3.0 / (PI * self.radius * self.radius * PI)
+    }
+}
+
+// Bounding sphere of an object photons are aimed at.
+#[derive(Debug, Copy, Clone)]
+struct Target {
+    center: Vec3,
+    radius: f64
+}
+
+impl Target {
+    // Cone of directions from the origin that go towards the target, as its axis and the cosine of its half angle.
+    // From inside the target it's all around.
+    fn get_cone(&self, origin: Vec3) -> (Vec3, f64) {
+        let to_center = self.center - origin;
+        let distance = to_center.length();
+        if distance <= self.radius {
+            return (vec3![0.0, 1.0, 0.0], -1.0);
+        }
+        (to_center / distance, (1.0 - (self.radius / distance).powi(2)).sqrt())
+    }
+}
+
+impl Scene {
+    // Sends photons out from the lights and collects where they land after being focused.
+    // Every light sends as many photons towards every target.
+    pub fn trace_photons(&self, caustics: Caustics) -> PhotonMap {
+        let mut rng = rand::thread_rng();
+        let targets = self.get_caustic_targets();
+        let sources = self.get_lights().len() + if self.get_mesh_light().is_empty() { 0 } else { 1 };
+        if targets.is_empty() || sources == 0 {
+            return PhotonMap::new(vec![], caustics.radius);
+        }
+        let per_target = (caustics.photons as usize / (sources * targets.len())).max(1);
+
+        let mut photons = vec![];
+        for light in self.get_lights() {
+            for target in 0..targets.len() {
+                for _ in 0..per_target {
+                    let (origin, direction, power) = match light {
+                        // Parallel light, the photons start on a disk facing the light that covers the target
+                        Light::DirectionalLight(sun) => {
+                            let direction = sun.get_direction();
+                            let (point, density) = aim_parallel_photon(direction, target, &targets, per_target, &mut rng);
+                            (point - direction * DIRECTIONAL_DISTANCE, direction, light.get_light_color(point) * PI / density)
+                        },
+                        _ => {
+                            let Some(origin) = light.get_photon_origin() else {
+                                continue;
+                            };
+                            let (direction, density) = aim_photon(origin, target, &targets, per_target, &mut rng);
+                            (origin, direction, light.get_photon_intensity(origin, direction) / density)
+                        }
+                    };
+                    self.trace_photon(Ray::new(origin, direction), power, &mut photons, &mut rng);
+                }
+            }
+        }
+
+        // Glowing objects send their photons out together, like they're sampled together
+        for target in 0..targets.len() {
+            for _ in 0..per_target {
+                let Some((origin, normal, radiance)) = self.get_mesh_light().get_photon_origin() else {
+                    break;
+                };
+                let (direction, density) = aim_photon(origin, target, &targets, per_target, &mut rng);
+                let power = radiance * (direction.dot(normal).max(0.0) / density);
+                self.trace_photon(Ray::new(origin + normal * 1e-6, direction), power, &mut photons, &mut rng);
+            }
+        }
+        PhotonMap::new(photons, caustics.radius)
+    }
+
+    // Bounding spheres of the objects that can focus light. Objects without a size, like endless planes, are left out.
+    fn get_caustic_targets(&self) -> Vec<Target> {
+        self.get_objects().iter()
+            .filter(|object| object.get_material().casts_caustics())
+            .filter_map(|object| {
+                let volume = object.get_bounding_vol();
+                let radius = (volume.max - volume.min).length() / 2.0;
+                if radius.is_finite() { Some(Target { center: volume.get_position(), radius }) } else { None }
+            })
+            .collect()
+    }
+
+    // Follows a photon through sharp reflections and refractions, storing it wherever it lands on a diffuse surface
+    // after at least one of them.
+    fn trace_photon(&self, mut ray: Ray, mut power: Vec3, photons: &mut Vec<Photon>, rng: &mut impl Rng) {
+        if power.x.max(power.y).max(power.z) <= 0.0 {
+            return;
+        }
+        let mut focused = false;
+        let mut bounce = 0;
+        let mut pass_throughs = 0;
+
+        loop {
+            let mut hit = self.get_hit(ray, f64::INFINITY);
+            if !hit.did_hit {
+                return;
+            }
+            let direction = ray.direction.unit();
+            if let Some(medium) = ray.media.current() {
+                power *= medium.get_transmittance((hit.hit_point - ray.origin).length());
+            }
+            if let Some(normal_map) = hit.mat.get_normal_map() {
+                hit.normal = normal_map.apply(self, &hit);
+            }
+
+            // Inside of an object with a higher priority this surface isn't there, so just keep going
+            let (crossing, ratio) = self.get_crossing(ray, &hit);
+            if !crossing.is_interface {
+                pass_throughs += 1;
+                if pass_throughs > MAX_PASS_THROUGHS {
+                    return;
+                }
+                ray = Ray::new_in_media(hit.hit_point + direction * 1e-6, direction, crossing.after);
+                continue;
+            }
+
+            let bsdf = hit.mat.get_bsdf(self, &hit, ratio);
+            if focused && bsdf.is_diffuse() {
+                photons.push(Photon { position: hit.hit_point, direction, power });
+            }
+            if bounce >= self.get_ray_depth() {
+                return;
+            }
+
+            // Light scattered any other way isn't focused, and is left to the light samples
+            let Some(sample) = bsdf.sample(-1.0 * direction, rng) else {
+                return;
+            };
+            if sample.pdf > 0.0 {
+                return;
+            }
+            power *= sample.weight;
+
+            let offset = bsdf.get_geometric_normal() * 1e-6;
+            ray = if sample.transmitted {
+                Ray::new_in_media(hit.hit_point - offset, sample.direction, crossing.after)
+            } else {
+                Ray::new_in_media(hit.hit_point + offset, sample.direction, crossing.before)
+            };
+            focused = true;
+            bounce += 1;
+        }
+    }
+}
+
+// Random direction from the origin towards a target. Targets can be in front of each other, so directions can be picked
+// while aiming at any of them. Returns the direction and how many photons go in it per solid angle, counting all targets.
+fn aim_photon(origin: Vec3, target: usize, targets: &[Target], per_target: usize, rng: &mut impl Rng) -> (Vec3, f64) {
+    let (axis, cos_max) = targets[target].get_cone(origin);
+    let cos = 1.0 - rng.gen::<f64>() * (1.0 - cos_max);
+    let sin = (1.0 - cos * cos).max(0.0).sqrt();
+    let angle = 2.0 * PI * rng.gen::<f64>();
+    let (tangent, bitangent) = orthonormal_basis(axis);
+    let direction = axis * cos + tangent * (sin * angle.cos()) + bitangent * (sin * angle.sin());
+
+    let density = targets.iter().enumerate().map(|(i, other)| {
+        let (axis, cos_max) = other.get_cone(origin);
+        if i == target || direction.dot(axis) >= cos_max { per_target as f64 / (2.0 * PI * (1.0 - cos_max)) } else { 0.0 }
+    }).sum();
+    (direction, density)
+}
+
+// Random point on a disk facing the direction light travels in that covers a target. Returns the point and how many
+// photons go through it per area, counting the disks of all targets.
+fn aim_parallel_photon(direction: Vec3, target: usize, targets: &[Target], per_target: usize, rng: &mut impl Rng) -> (Vec3, f64) {
+    let (tangent, bitangent) = orthonormal_basis(direction);
+    let r = targets[target].radius * rng.gen::<f64>().sqrt();
+    let angle = 2.0 * PI * rng.gen::<f64>();
+    let point = targets[target].center + tangent * (r * angle.cos()) + bitangent * (r * angle.sin());
+
+    let density = targets.iter().enumerate().map(|(i, other)| {
+        let to_center = other.center - point;
+        let off_line = to_center - direction * to_center.dot(direction);
+        if i == target || off_line.length() <= other.radius { per_target as f64 / (PI * other.radius * other.radius) } else { 0.0 }
+    }).sum();
+    (point, density)
+}
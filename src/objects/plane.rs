@@ -1,6 +1,7 @@
 use crate::{Hittable, HittableList, Material, Ray, SceneObject, Vec3, vec3, Vector};
-use crate::objects::BoundingVolume;
+use crate::objects::{BoundingVolume, Emitter, EmitterShape};
 use crate::traits::HitData;
+use crate::utils::{deg_to_rad, rotate_m};
 const PARALLEL_TOLERANCE: f64 = 1e-8;
 
 #[derive(Debug, Copy, Clone)]
@@ -9,7 +10,9 @@ pub struct Plane {
     normal: Vec3,
     width: f64,
     height: f64,
-    material: Material
+    material: Material,
+    object_id: u32,
+    material_id: u32
 }
 
 impl Plane {
@@ -17,12 +20,37 @@ impl Plane {
     pub fn new(width: f64, height: f64, normal: Vec3, material: Material) -> Self {
         Plane {
             material,
+            object_id: 0,
+            material_id: 0,
             width,
             height,
             position: vec3![0.0, 0.0, 0.0],
             normal: normal.unit()
         }
     }
+
+    // Axes the width and height of the plane are measured along, so that the texture is upright when looking
+    // at the front of the plane. Planes facing up or down have the top of the texture towards -Z.
+    fn get_axes(&self) -> (Vec3, Vec3) {
+        let horizontal = vec3![0.0, 1.0, 0.0].cross(self.normal);
+        let width_axis = if horizontal.length() > 1e-9 { horizontal.unit() } else { vec3![1.0, 0.0, 0.0] };
+        (width_axis, self.normal.cross(width_axis))
+    }
+
+    // Texture coordinates go from 0 to 1 over the width and height, with the middle of the texture at the
+    // plane's position. The plane itself doesn't end, so the texture continues past its size.
+    fn get_uv(&self, point: Vec3) -> (f64, f64) {
+        let (width_axis, height_axis) = self.get_axes();
+        let offset = point - self.position;
+        (offset.dot(width_axis) / self.width + 0.5, offset.dot(height_axis) / self.height + 0.5)
+    }
+
+    // Point relative to the plane's position, along its width axis, normal and height axis.
+    fn get_local_point(&self, point: Vec3) -> Vec3 {
+        let (width_axis, height_axis) = self.get_axes();
+        let offset = point - self.position;
+        vec3![offset.dot(width_axis), offset.dot(self.normal), offset.dot(height_axis)]
+    }
 }
 
 impl Hittable for Plane {
@@ -34,7 +62,21 @@ impl Hittable for Plane {
             let t = self.normal.dot(self.position - ray.origin) / denom;
             if t > t_min && t < t_max{
                 let ray_intersect = ray.get_point_at(t);
-                return HitData::from(t, true, ray, ray_intersect, self.normal, self.material);
+
+                // Glowing planes end at their width and height, so they can be used as softboxes
+                if let Material::Emissive(_) = self.material {
+                    let (width_axis, height_axis) = self.get_axes();
+                    let offset = ray_intersect - self.position;
+                    if offset.dot(width_axis).abs() > self.width / 2.0 || offset.dot(height_axis).abs() > self.height / 2.0 {
+                        return HitData::new();
+                    }
+                }
+                let mut hit_data = HitData::from(
+                    t, true, ray, ray_intersect, self.normal, self.get_uv(ray_intersect), self.get_local_point(ray_intersect), self.material
+                );
+                (hit_data.tangent, hit_data.bitangent) = self.get_axes();
+                (hit_data.object_id, hit_data.material_id) = (self.object_id, self.material_id);
+                return hit_data;
             }
         }
         HitData::new()
@@ -73,25 +115,61 @@ impl SceneObject for Plane {
         self.position
     }
 
+    fn get_material(&self) -> Material {
+        self.material
+    }
+
     fn set_material(&mut self, material: Material) {
-        todo!()
+        self.material = material;
+    }
+
+    fn set_ids(&mut self, object_id: u32, material_id: u32) {
+        self.object_id = object_id;
+        self.material_id = material_id;
     }
 
     fn translate(&mut self, translation: Vec3) {
         self.position += translation
     }
 
+    // Scales width and height along the same axes used to build the bounding volume.
     fn scale(&mut self, scale: Vec3) {
-        todo!()
+        if self.normal.abs() == vec3![1.0, 0.0, 0.0] {
+            self.width *= scale.z;
+            self.height *= scale.y;
+        } else if self.normal.abs() == vec3![0.0, 0.0, 1.0] {
+            self.width *= scale.x;
+            self.height *= scale.y;
+        } else {
+            self.width *= scale.x;
+            self.height *= scale.z;
+        }
     }
 
+    // Rotating a plane only changes where its normal points.
     fn rotate(&mut self, rotation: Vec3) {
-        todo!()
+        let rotate_by = vec3![deg_to_rad(rotation.x), deg_to_rad(rotation.y), deg_to_rad(rotation.z)];
+        self.normal = (self.normal * &rotate_m(rotate_by).transpose_square()).unit();
     }
 
     fn decompose(&self) -> HittableList {
         let mut list = HittableList::new();
-        list.push(Box::new(self.clone()));
+        list.push(Box::new(*self));
         list
     }
+
+    fn get_emitters(&self) -> Vec<Emitter> {
+        let Material::Emissive(emissive) = self.material else {
+            return vec![];
+        };
+        let (width_axis, height_axis) = self.get_axes();
+        let (half_width, half_height) = (width_axis * (self.width / 2.0), height_axis * (self.height / 2.0));
+        let corners = [
+            self.position - half_width - half_height, self.position + half_width - half_height,
+            self.position + half_width + half_height, self.position - half_width + half_height
+        ];
+        [[corners[0], corners[1], corners[2]], [corners[0], corners[2], corners[3]]].into_iter()
+            .map(|triangle| Emitter { shape: EmitterShape::Triangle(triangle), radiance: emissive.get_radiance() })
+            .collect()
+    }
 }
\ No newline at end of file
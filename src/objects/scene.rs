@@ -1,26 +1,36 @@
 use std::collections::HashMap;
+use std::iter;
 use std::ops::DerefMut;
 use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU32, Ordering};
 use std::thread;
+use rand::Rng;
 use std::sync::mpsc;
-use crate::materials::Mat;
+use crate::materials::{Mat, Texture};
 use crate::{Camera, Hittable, HittableList, Ray, Vec3, vec3, Light, Material, Vector, WorldLight};
-use crate::data_structures::{BvhNode};
-use crate::objects::AmbientLight;
+use crate::data_structures::{BvhNode, Crossing};
+use crate::objects::{AmbientLight, AmbientOcclusion, Aov, Caustics, Emitter, Environment, LightSample, MeshLight, PhotonMap, RenderOutput};
 use crate::traits::HitData;
 
 const PARALLEL_TOLERANCE: f64 = 1e-8;
+const SHADOW_EPSILON: f64 = 1e-4;
+const MAX_SHADOW_HITS: u32 = 16; // Transparent objects a shadow ray passes through before the light counts as blocked
 
 
 // Define a super trait for objects in the world.
 // Objects in the world should be hittable by the ray as well as have these other common functions
 pub trait SceneObject: Hittable + CloneSceneObject {
     fn get_position(&self) -> Vec3;
+    fn get_material(&self) -> Material;
     fn set_material(&mut self, material: Material);
+    // Numbers the object and its material, to tell them apart in the object and material id AOVs.
+    fn set_ids(&mut self, object_id: u32, material_id: u32);
     fn translate(&mut self, translation: Vec3);
     fn scale(&mut self, scale: Vec3);
     fn rotate(&mut self, rotation: Vec3);
     fn decompose(&self) -> HittableList;
+    // Glowing parts of the object that light the scene, if it has an emissive material.
+    fn get_emitters(&self) -> Vec<Emitter>;
 }
 
 // A bunch of stuff ot make SceneObject copyable/cloneable
@@ -40,38 +50,75 @@ impl Clone for Box<dyn SceneObject> {
     }
 }
 
+// How much work adaptive supersampling did. Rays shot are new samples, cache calls are samples reused from neighboring pixels.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct SupersampleStats {
+    pub rays_shot: u64,
+    pub cache_calls: u64
+}
+
+// How the color seen along a camera ray is worked out.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Integrator {
+    // Direct lighting plus sharp reflections and refractions. Fast and free of noise.
+    Whitted,
+    // Follows light as it bounces around the scene, see path_tracer.rs. Samples paths per pixel, more gives less noise.
+    Path { samples: u32 },
+    // Only the ambient occlusion of the first surface seen, as a pass for compositing.
+    AmbientOcclusion
+}
+
 // Represents a scene to render.
 // Stores all the objects that exist in the scene and the camera by which to view the scene.
 pub struct Scene {
     pub main_camera: Camera,
     render_shadows: bool,
+    integrator: Integrator,
     acc_obj_num: u64,
     ray_depth: u32,
     bvh_root: BvhNode,
     render_distance: f64,
     refractive_index: f64,
     objects: HittableList,
+    materials: Vec<Material>, // Every different material of the objects, material ids count from 1 in this list
     background_color: Vec3,
+    environment: Option<Environment>, // Replaces the background color and lights the scene when set
     lights: Vec<Light>,
+    mesh_light: MeshLight, // Objects with an emissive material
     ambient_lights: Vec<AmbientLight>,
+    textures: Vec<Texture>, // Materials refer to textures by their index
     render_resolution: (u32, u32),
+    ambient_occlusion: Option<AmbientOcclusion>, // Darkens the ambient light of Phong and Hall materials when set
+    caustics: Option<Caustics>,
+    photon_map: Option<PhotonMap>, // Traced from the caustics settings when rendering starts
+    aovs: Vec<Aov>, // Rendered into buffers of their own next to the image
 }
 
 impl Scene {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(render_resolution: (u32, u32), render_distance: f64, background_color: Vec3, hfov: f64, acc_obj_num: u64, ray_depth: u32, refractive_index:f64, render_shadows: bool) -> Self {
         Scene {
             render_resolution,
             render_distance,
             background_color,
+            environment: None,
             acc_obj_num,
             ray_depth,
             refractive_index,
             render_shadows,
+            integrator: Integrator::Whitted,
             bvh_root: BvhNode::new(),
             main_camera: Camera::new(render_resolution, hfov),
             lights: vec![],
+            mesh_light: MeshLight::default(),
             ambient_lights: vec![],
-            objects: HittableList::new()
+            textures: vec![],
+            objects: HittableList::new(),
+            materials: vec![],
+            ambient_occlusion: None,
+            caustics: None,
+            photon_map: None,
+            aovs: vec![]
         }
     }
 
@@ -82,8 +129,21 @@ impl Scene {
         }
     }
 
-    // Add a single renderable object to the scene.
-    pub fn push_object(&mut self, obj: Box<dyn SceneObject>) {
+    // Add a single renderable object to the scene. Glowing objects also light the scene.
+    // Objects get the next object id, and share a material id with objects that have the same material.
+    pub fn push_object(&mut self, mut obj: Box<dyn SceneObject>) {
+        for emitter in obj.get_emitters() {
+            self.mesh_light.push(emitter);
+        }
+        let material = obj.get_material();
+        let material_index = match self.materials.iter().position(|other| *other == material) {
+            Some(index) => index,
+            None => {
+                self.materials.push(material);
+                self.materials.len() - 1
+            }
+        };
+        obj.set_ids(self.objects.len() as u32 + 1, material_index as u32 + 1);
         self.objects.push(obj);
     }
 
@@ -95,10 +155,41 @@ impl Scene {
         self.ambient_lights.push(light);
     }
 
+    // Adds a texture and returns the index materials can use to refer to it.
+    pub fn push_texture(&mut self, texture: Texture) -> usize {
+        self.textures.push(texture);
+        self.textures.len() - 1
+    }
+
+    pub fn get_texture(&self, index: usize) -> &Texture {
+        &self.textures[index]
+    }
+
+    pub fn set_environment(&mut self, environment: Option<Environment>) {
+        self.environment = environment;
+    }
+
+    pub fn get_environment(&self) -> Option<&Environment> {
+        self.environment.as_ref()
+    }
+
+    pub fn get_objects(&self) -> &HittableList {
+        &self.objects
+    }
+
     pub fn get_lights(&self) -> &Vec<Light> {
         &self.lights
     }
 
+    pub fn get_mesh_light(&self) -> &MeshLight {
+        &self.mesh_light
+    }
+
+    // How many light samples are shared between all glowing objects.
+    pub fn set_emission_samples(&mut self, samples: u32) {
+        self.mesh_light.set_samples(samples);
+    }
+
     pub fn get_ambient_lights(&self) -> &Vec<AmbientLight> {
         &self.ambient_lights
     }
@@ -107,78 +198,295 @@ impl Scene {
         self.ray_depth
     }
 
+    pub fn get_render_shadows(&self) -> bool {
+        self.render_shadows
+    }
+
+    pub fn get_render_distance(&self) -> f64 {
+        self.render_distance
+    }
+
+    pub fn get_background_color(&self) -> Vec3 {
+        self.background_color
+    }
+
+    pub fn get_integrator(&self) -> Integrator {
+        self.integrator
+    }
+
     pub fn get_refractive_index(&self) -> f64 {
         self.refractive_index
     }
 
+    pub fn get_render_resolution(&self) -> (u32, u32) {
+        self.render_resolution
+    }
+
+    // Changing the resolution also changes the aspect ratio of the camera.
+    pub fn set_render_resolution(&mut self, render_resolution: (u32, u32)) {
+        self.render_resolution = render_resolution;
+        self.main_camera.set_resolution(render_resolution);
+    }
+
+    // Replaces the main camera. The camera is fit to the render resolution of the scene.
+    pub fn set_camera(&mut self, mut camera: Camera) {
+        camera.set_resolution(self.render_resolution);
+        self.main_camera = camera;
+    }
+
+    pub fn set_ray_depth(&mut self, ray_depth: u32) {
+        self.ray_depth = ray_depth;
+    }
+
+    pub fn set_render_shadows(&mut self, render_shadows: bool) {
+        self.render_shadows = render_shadows;
+    }
+
+    pub fn set_integrator(&mut self, integrator: Integrator) {
+        self.integrator = integrator;
+    }
+
+    pub fn get_ambient_occlusion(&self) -> Option<AmbientOcclusion> {
+        self.ambient_occlusion
+    }
+
+    pub fn set_ambient_occlusion(&mut self, ambient_occlusion: Option<AmbientOcclusion>) {
+        self.ambient_occlusion = ambient_occlusion;
+    }
+
+    pub fn get_caustics(&self) -> Option<Caustics> {
+        self.caustics
+    }
+
+    // Caustics from a photon map, see photon_map.rs. None turns them off.
+    pub fn set_caustics(&mut self, caustics: Option<Caustics>) {
+        self.caustics = caustics;
+    }
+
+    pub fn get_aovs(&self) -> &Vec<Aov> {
+        &self.aovs
+    }
+
+    // AOVs rendered next to the image, see aov.rs. Each one is only in the render output once.
+    pub fn set_aovs(&mut self, aovs: Vec<Aov>) {
+        self.aovs = vec![];
+        for aov in aovs {
+            if !self.aovs.contains(&aov) {
+                self.aovs.push(aov);
+            }
+        }
+    }
+
+    // Light focused onto the hit by mirrors and clear objects, like a light sample color times the cosine of the angle
+    // it arrives at. Black until rendering starts, and when caustics are off.
+    pub fn get_caustic_light(&self, hit: &HitData) -> Vec3 {
+        match &self.photon_map {
+            Some(photon_map) => photon_map.get_light(hit),
+            None => vec3![0.0, 0.0, 0.0]
+        }
+    }
+
+    // Focus the camera on whatever object is visible through pixel (x, y), counting from the top left of the image.
+    // Returns the new focus distance, or None if there's nothing at that pixel and the focus was left alone.
+    pub fn focus_on_pixel(&mut self, x: u32, y: u32) -> Option<f64> {
+        // Sample the center of the pixel. Normalized y goes up from the bottom of the image.
+        let px = (x as f64 + 0.5) / self.render_resolution.0 as f64;
+        let py = (self.render_resolution.1 as f64 - y as f64 - 0.5) / self.render_resolution.1 as f64;
+        let ray = self.main_camera.get_pinhole_ray(px, py)?;
+
+        // The BVH might not be built yet, so check every object.
+        let hit = self.objects.hit(ray, 0.0, self.render_distance);
+        if !hit.did_hit {
+            return None;
+        }
+
+        // Focus distance is measured along the view direction, not along the ray.
+        let focus_distance = (hit.hit_point - self.main_camera.get_position()).dot(self.main_camera.get_view_dir());
+        self.main_camera.set_focus_distance(focus_distance);
+        Some(focus_distance)
+    }
+
     // Given normalized pixel location return the color to render.
     pub fn get_color_at_pixel(&self, x: f64, y: f64) -> Vec3 {
-        // Get the ray going from the camera origin to the chosen pixel location
-        self.get_color_from_ray(self.main_camera.get_ray(x, y), 0)
+        // Get the ray going from the camera origin to the chosen pixel location.
+        // A camera with an aperture gets a different ray each time, so average a few to smooth out the blur.
+        // Pixels the camera projection doesn't cover are black.
+        match self.integrator {
+            Integrator::Whitted | Integrator::AmbientOcclusion => {
+                let samples = self.main_camera.get_lens_samples();
+                let mut color = vec3![0.0, 0.0, 0.0];
+                for _ in 0..samples {
+                    if let Some(ray) = self.main_camera.get_ray(x, y) {
+                        color += if self.integrator == Integrator::Whitted { self.get_color_from_ray(ray, 0) } else { self.get_occlusion_color(ray) };
+                    }
+                }
+                color / samples as f64
+            },
+            // Every path goes through a random point inside the pixel, which also smooths out jagged edges.
+            // The lens is sampled along with it, so lens samples aren't needed on top.
+            Integrator::Path { samples } => {
+                let mut rng = rand::thread_rng();
+                let (width, height) = (self.render_resolution.0 as f64, self.render_resolution.1 as f64);
+                let mut color = vec3![0.0, 0.0, 0.0];
+                for _ in 0..samples {
+                    if let Some(ray) = self.main_camera.get_ray(x + rng.gen::<f64>() / width, y + rng.gen::<f64>() / height) {
+                        color += self.get_path_color(ray);
+                    }
+                }
+                color / samples.max(1) as f64
+            }
+        }
     }
 
-    // Given a ray extending into the scene, get the color of the object that the ray intersects.
-    pub fn get_color_from_ray(&self, ray: Ray, depth: u32) -> Vec3 {
-        // Get the closest scene object that is hit by the ray. Optionally use BVH for acceleration.
-        let mut hit = HitData::new();
+    // Closest object hit by the ray up to some distance. Uses the BVH when there is one.
+    pub fn get_hit(&self, ray: Ray, t_max: f64) -> HitData {
         if self.acc_obj_num > 0 {
-            hit = self.bvh_root.hit(ray, 0.0, self.render_distance);
+            self.bvh_root.hit(ray, 0.0, t_max)
         } else {
-            hit = self.objects.hit(ray, 0.0, self.render_distance);
+            self.objects.hit(ray, 0.0, t_max)
         }
+    }
 
-        // If it hit something, return the color of the object.
-        if hit.did_hit {
-            let base_surface_color = hit.mat.get_color(&self, ray, &hit, depth); // Base color of the surface with no shadows
-            let new_ray_origin = hit.hit_point + (hit.normal * 10e-6); // Origin for shadow rays
-            let mut final_color = base_surface_color; // Keep track of the final surface color
-
-            // Compute shadows.
-            if self.render_shadows {
-                for light in &self.lights {
-                    // Check if there are any objects between surface and all lights.
-                    // Raise ray origin a bit outside the object in case rounding error puts the hit_point inside the object
-
-                    let hit_to_light = light.get_position() - hit.hit_point;
-                    let shadow_ray = Ray::new(new_ray_origin, hit_to_light.unit());
-
-                    // Ensure shadow is only rendered if surface is facing the light source.
-                    // The color of the side of objects pointing away from the light source are computed
-                    // just fine with the lighting code. Otherwise these areas get unnatural looking
-                    // shadows from self-intersection
-                    let norm_dot_shadow_dir = hit.normal.dot(shadow_ray.direction);
-                    if norm_dot_shadow_dir <= PARALLEL_TOLERANCE || norm_dot_shadow_dir.abs() <= PARALLEL_TOLERANCE {
-                        continue;
-                    }
+    // How a ray goes through the surface it hit, and the refractive index on the side it comes from divided by the one on
+    // the other side. Surfaces of objects without an inside leave the ray in the media it was in.
+    pub fn get_crossing(&self, ray: Ray, hit: &HitData) -> (Crossing, f64) {
+        let Some(medium) = hit.mat.get_medium(self, hit) else {
+            return (Crossing { before: ray.media, after: ray.media, is_interface: true }, 1.0);
+        };
+        let crossing = ray.media.cross(medium, ray.direction.dot(hit.geometric_normal) < 0.0);
+        let ratio = crossing.before.get_refractive_index(self.refractive_index) / crossing.after.get_refractive_index(self.refractive_index);
+        (crossing, ratio)
+    }
 
-                    // Send the new ray in the direction of the light to find if there's anything in between,
-                    // and only go as far as the light is.
-                    let mut shadow_hit = HitData::new();
-                    if self.acc_obj_num > 0 {
-                        shadow_hit = self.bvh_root.hit(shadow_ray, 0.0, hit_to_light.length());
-                    } else {
-                        shadow_hit = self.objects.hit(shadow_ray, 0.0, hit_to_light.length());
-                    }
+    // All the light samples that can light the hit point, from the lights and the environment.
+    // Lights with an area are made up of multiple samples that each light the surface a little.
+    pub fn get_light_samples(&self, hit: &HitData) -> Vec<LightSample> {
+        let mut samples: Vec<LightSample> = self.lights.iter().flat_map(|light| light.get_samples(hit.hit_point)).collect();
+        samples.extend(self.mesh_light.get_samples(hit.hit_point));
+        if let Some(environment) = &self.environment {
+            samples.extend(environment.get_samples(hit.normal));
+        }
+        samples
+    }
 
-                    // For each light the produces a shadow (something in between surface and light),
-                    // subtract a portion of the original surface color to create the shadow
-                    if shadow_hit.did_hit && shadow_ray.direction.dot(shadow_hit.normal) <= PARALLEL_TOLERANCE {
-                        final_color -= 0.3 * base_surface_color;
-                    }
+    // How much of a light sample reaches the hit point, per color channel. 0 when the sample is in shadow.
+    // Transparent objects in the way let some light through, tinted by their transmission color.
+    pub fn get_light_transmission(&self, hit: &HitData, sample: &LightSample) -> Vec3 {
+        let mut transmission = vec3![1.0, 1.0, 1.0];
+        if !self.render_shadows {
+            return transmission;
+        }
+
+        // Ensure shadow is only rendered if surface is facing the light source.
+        // The color of the side of objects pointing away from the light source are computed
+        // just fine with the lighting code. Otherwise these areas get unnatural looking
+        // shadows from self-intersection
+        let norm_dot_shadow_dir = hit.normal.dot(sample.direction);
+        if norm_dot_shadow_dir <= PARALLEL_TOLERANCE {
+            return transmission;
+        }
+
+        // Raise ray origin a bit outside the object in case rounding error puts the hit_point inside the object
+        // Moved along the real surface normal, since a bent shading normal could point the ray back into the surface
+        let mut shadow_ray = Ray::new(hit.hit_point + (hit.geometric_normal * 10e-6), sample.direction);
+        // Stop just short of the light so surfaces the light sits on don't shadow it.
+        let mut distance_left = sample.distance - SHADOW_EPSILON;
+
+        // Keep following the shadow ray through transparent objects until it reaches the light or is blocked.
+        for _ in 0..MAX_SHADOW_HITS {
+            // Send the new ray in the direction of the light to find if there's anything in between,
+            // and only go as far as the light is. Directional lights are infinitely far away.
+            let shadow_hit = self.get_hit(shadow_ray, distance_left);
+            if !shadow_hit.did_hit {
+                return transmission;
+            }
+
+            // Only surfaces facing the ray block it, the ray leaving a transparent object doesn't count again.
+            // With caustics the light getting through is in the photon map, so transparent objects cast full shadows.
+            if shadow_ray.direction.dot(shadow_hit.geometric_normal) <= PARALLEL_TOLERANCE {
+                if self.photon_map.is_some() {
+                    return vec3![0.0, 0.0, 0.0];
+                }
+                transmission *= shadow_hit.mat.get_transmission(self, &shadow_hit);
+                if transmission.x.max(transmission.y).max(transmission.z) <= 0.0 {
+                    return transmission;
                 }
             }
-            final_color
+
+            // Continue just past the object that was hit
+            distance_left -= shadow_hit.t;
+            shadow_ray = Ray::new(shadow_hit.hit_point + sample.direction * 1e-6, sample.direction);
+        }
+
+        // Too many objects in the way, treat the light as blocked.
+        vec3![0.0, 0.0, 0.0]
+    }
+
+    // Given a ray extending into the scene, get the color of the object that the ray intersects.
+    pub fn get_color_from_ray(&self, ray: Ray, depth: u32) -> Vec3 {
+        // Get the closest scene object that is hit by the ray. Optionally use BVH for acceleration.
+        let mut hit = self.get_hit(ray, self.render_distance);
+
+        // If it hit something, return the color of the object. Shadows are part of the shading of each material.
+        if hit.did_hit {
+            if let Some(normal_map) = hit.mat.get_normal_map() {
+                hit.normal = normal_map.apply(self, &hit);
+            }
+            let color = hit.mat.get_color(self, ray, &hit, depth);
+
+            // Light coming from the hit is partly absorbed by whatever the ray went through to get there
+            match ray.media.current() {
+                Some(medium) => color * medium.get_transmittance((hit.hit_point - ray.origin).length()),
+                None => color
+            }
         } else {
-            // If nothing hit, then return the background color.
-            self.background_color
+            // If nothing hit, then return the environment or background color.
+            match &self.environment {
+                Some(environment) => environment.get_color(ray.direction),
+                None => self.background_color
+            }
         }
     }
 
-    // Renders a frame of the scene, rendering objects within camera view.
-    pub fn render_frame(&mut self) -> Vec<Vec3> {
+    // Builds what rendering needs once the scene is set up, the BVH and the photon map.
+    // The path tracer finds caustics by itself, so it doesn't need photons.
+    fn prepare_render(&mut self) {
         if self.acc_obj_num > 0 {
             self.bvh_root = BvhNode::from(&mut self.objects, 0, self.acc_obj_num);
         }
+        self.photon_map = match (self.caustics, self.integrator) {
+            (Some(caustics), Integrator::Whitted) => Some(self.trace_photons(caustics)),
+            _ => None
+        };
+    }
+
+    // Color of the pixel at normalized location followed by the values of the AOVs, one for each buffer of the output.
+    fn get_pixel_values(&self, x: f64, y: f64) -> Vec<Vec3> {
+        let mut values = vec![self.get_color_at_pixel(x, y)];
+        values.extend(self.get_aovs_at_pixel(x, y));
+        values
+    }
+
+    // Sorts the values of every pixel, as from get_pixel_values, into the named buffers of a render output.
+    fn to_render_output(&self, pixels: Vec<Vec<Vec3>>) -> RenderOutput {
+        let mut buffers = vec![Vec::with_capacity(pixels.len()); self.aovs.len() + 1];
+        for values in pixels {
+            for (buffer, value) in buffers.iter_mut().zip(values) {
+                buffer.push(value);
+            }
+        }
+
+        let mut output = RenderOutput::new(self.render_resolution);
+        let names = iter::once("beauty").chain(self.aovs.iter().map(|aov| aov.get_name()));
+        for (name, buffer) in names.zip(buffers) {
+            output.insert(name, buffer);
+        }
+        output
+    }
+
+    // Renders a frame of the scene, rendering objects within camera view.
+    pub fn render_frame(&mut self) -> RenderOutput {
+        self.prepare_render();
 
         let mut pixels = vec![];
         let x = self.render_resolution.0;
@@ -187,45 +495,52 @@ impl Scene {
             for j in 0..x {
                 let px = j as f64 / x as f64;
                 let py = i as f64 / y as f64;
-                pixels.push(self.get_color_at_pixel(px, py));
+                pixels.push(self.get_pixel_values(px, py));
             }
         }
-        pixels
+        self.to_render_output(pixels)
     }
 
-    // Same as render_frame but splits computing horizontal pixels into threads.
-    pub fn render_frame_threaded(mut self) -> Vec<Vec3> {
-        if self.acc_obj_num > 0 {
-            self.bvh_root = BvhNode::from(&mut self.objects, 0, self.acc_obj_num);
-        }
+    // Same as render_frame but splits computing horizontal pixels across a number of threads.
+    pub fn render_frame_threaded(mut self, threads: u32) -> RenderOutput {
+        self.prepare_render();
 
         let x = self.render_resolution.0;
         let y = self.render_resolution.1;
         let mut handles = vec![]; // Keep track of threads
         let self_ptr = Arc::new(self); // Turn self into a pointer
+        let next_row = Arc::new(AtomicU32::new(0)); // Next row of pixels that hasn't been picked up by a thread
         let (tx, rx) = mpsc::channel(); // Create channel for collecting pixels
 
-        // Create a thread for each row of pixels to compute.
-        for i in (0..y).rev() {
+        // Each thread keeps taking the next row of pixels to compute until there are none left.
+        for _ in 0..threads.max(1) {
             let self_clone = self_ptr.clone();
+            let next_row_clone = next_row.clone();
             let tx_clone = tx.clone();
 
-            // Create a thread at each y position to render horizontal pixels for that y
             handles.push(thread::spawn(move || {
-                let mut pix_row = vec![];
-                for j in 0..x {
-                    let px = j as f64 / x as f64;
-                    let py = i as f64 / y as f64;
-                    let color = self_clone.get_color_at_pixel(px, py);
-                    pix_row.push(color); // Get the  color of pixel
+                loop {
+                    let row = next_row_clone.fetch_add(1, Ordering::Relaxed);
+                    if row >= y {
+                        break;
+                    }
+
+                    // Rows are rendered from the top of the image down
+                    let i = y - row - 1;
+                    let mut pix_row = vec![];
+                    for j in 0..x {
+                        let px = j as f64 / x as f64;
+                        let py = i as f64 / y as f64;
+                        pix_row.push(self_clone.get_pixel_values(px, py)); // Get the color and AOVs of pixel
+                    }
+                    // Send the index where this row should start, and of course the row of pixels.
+                    tx_clone.send((x * row, pix_row)).unwrap();
                 }
-                // Send the index where this row should start, and of course the row of pixels.
-                tx_clone.send((x * (y - i - 1), pix_row)).unwrap();
             }));
         }
 
         // Collect all rows
-        let mut computed_pixels = vec![vec3![0.0, 0.0, 0.0]; (x * y) as usize];
+        let mut computed_pixels = vec![vec![]; (x * y) as usize];
         for _ in 0..y {
             let (index, row) = rx.recv().unwrap();
             computed_pixels.splice(index as usize..(index + x) as usize, row);
@@ -236,14 +551,14 @@ impl Scene {
             h.join().unwrap();
         }
 
-        computed_pixels
+        self_ptr.to_render_output(computed_pixels)
     }
 
-    // Does adaptive supersampling of image. Returns supersampled vec of pixels and vec of pixels representing heatmap of samples taken per pixel
-    pub fn render_supersample_frame_threaded(mut self, tolerance: f64) -> (Vec<Vec3>, Vec<Vec3>) {
-        if self.acc_obj_num > 0 {
-            self.bvh_root = BvhNode::from(&mut self.objects, 0, self.acc_obj_num);
-        }
+    // Does adaptive supersampling of image. Besides the supersampled image and AOVs, the output has a "samples" buffer
+    // with the number of samples taken per pixel, for a heatmap. AOVs aren't supersampled.
+    // Also returns how many rays were shot and cached samples used.
+    pub fn render_supersample_frame_threaded(mut self, tolerance: f64, threads: u32) -> (RenderOutput, SupersampleStats) {
+        self.prepare_render();
 
         // Cache x and y since now since self ownership is transferred to the arc
         let x = self.render_resolution.0;
@@ -256,41 +571,53 @@ impl Scene {
         let mut handles = vec![]; // Keep track of threads
         let self_ptr = Arc::new(self); // Turn self into a pointer
         let sample_map = Arc::new(Mutex::new(HashMap::new())); // Store sample taken
+        let next_row = Arc::new(AtomicU32::new(0)); // Next row of pixels that hasn't been picked up by a thread
         let (tx, rx) = mpsc::channel(); // Create channel for collecting pixels
 
-        // Create a thread for each row of pixels to compute.
-        for i in (0..y).rev() {
+        // Each thread keeps taking the next row of pixels to compute until there are none left.
+        for _ in 0..threads.max(1) {
             // Clone sender and pointers for each thread
             let tx_clone = tx.clone();
             let self_clone = self_ptr.clone();
             let map_clone = sample_map.clone();
+            let next_row_clone = next_row.clone();
             let ray_shot_clone = rays_shot.clone();
             let cache_calls_clone = cache_calls.clone();
 
-            // Create a thread at each y position to render row of pixels
             handles.push(thread::spawn(move || {
-                let mut pix_row = vec![]; // Row of pixels in final image
-                let mut heatmap_row = vec![]; // Row of pixels for the ray/sample heatmap
-                for j in 0..x {
-                    // Supersample recursively with depth of 2
-                    let (new_rays, new_cache_calls, color) = self_clone.supersample_recurse((j as f64, i as f64), 2.0, 0, 2, tolerance, (x, y), map_clone.clone());
-                    pix_row.push(color);
-
-                    // Record rays shot and cached samples taken
-                    *ray_shot_clone.lock().unwrap().deref_mut() += new_rays;
-                    *cache_calls_clone.lock().unwrap().deref_mut() += new_cache_calls;
-
-                    // Get pixel for heatmap
-                    let total_samples = (new_rays + new_cache_calls) as f64;
-                    heatmap_row.push(vec3![total_samples, total_samples, total_samples]);
+                loop {
+                    let row = next_row_clone.fetch_add(1, Ordering::Relaxed);
+                    if row >= y {
+                        break;
+                    }
+
+                    // Rows are rendered from the top of the image down
+                    let i = y - row - 1;
+                    let mut pix_row = vec![]; // Row of pixels in final image
+                    let mut heatmap_row = vec![]; // Row of pixels for the ray/sample heatmap
+                    for j in 0..x {
+                        // Supersample recursively with depth of 2
+                        let (new_rays, new_cache_calls, color) = self_clone.supersample_recurse((j as f64, i as f64), 2.0, 0, 2, tolerance, (x, y), map_clone.clone());
+                        let mut values = vec![color];
+                        values.extend(self_clone.get_aovs_at_pixel(j as f64 / x as f64, i as f64 / y as f64));
+                        pix_row.push(values);
+
+                        // Record rays shot and cached samples taken
+                        *ray_shot_clone.lock().unwrap().deref_mut() += new_rays;
+                        *cache_calls_clone.lock().unwrap().deref_mut() += new_cache_calls;
+
+                        // Get pixel for heatmap
+                        let total_samples = (new_rays + new_cache_calls) as f64;
+                        heatmap_row.push(vec3![total_samples, total_samples, total_samples]);
+                    }
+                    // Send the index where this row should start, and of course the row of pixels for the final image and heatmap.
+                    tx_clone.send((x * row, pix_row, heatmap_row)).unwrap();
                 }
-                // Send the index where this row should start, and of course the row of pixels for the final image and heatmap.
-                tx_clone.send((x * (y - i - 1), pix_row, heatmap_row)).unwrap();
             }));
         }
 
         // Collect all rows
-        let mut computed_pixels = vec![vec3![0.0, 0.0, 0.0]; ((x) * (y)) as usize];
+        let mut computed_pixels = vec![vec![]; ((x) * (y)) as usize];
         let mut heatmap_pixels = vec![vec3![0.0, 0.0, 0.0]; ((x) * (y)) as usize];
         for _ in 0..y {
             let (index, row, heatmap_row) = rx.recv().unwrap();
@@ -303,11 +630,17 @@ impl Scene {
             h.join().unwrap();
         }
 
-        println!("Shot a total of {} unique rays.\nCalled ray cache {} times.", rays_shot.lock().unwrap(), cache_calls.lock().unwrap());
-        (computed_pixels, heatmap_pixels)
+        let stats = SupersampleStats {
+            rays_shot: *rays_shot.lock().unwrap(),
+            cache_calls: *cache_calls.lock().unwrap()
+        };
+        let mut output = self_ptr.to_render_output(computed_pixels);
+        output.insert("samples", heatmap_pixels);
+        (output, stats)
     }
 
 
+    #[allow(clippy::too_many_arguments)]
     fn supersample_recurse(
         &self, top_left: (f64, f64), corner_distance: f64, depth: u32, max_depth: u32, tolerance: f64, resolution: (u32, u32), sample_map: Arc<Mutex<HashMap<String, Vec3>>>
     ) -> (u64, u64, Vec3) {
@@ -327,6 +660,8 @@ impl Scene {
 
                 // If the map already has a sample for this position, don't compute it again.
                 let key = format!("{}, {}", corner_px, corner_py);
+                // The entry API clippy suggests would keep the map locked while tracing and block the other rows
+                #[allow(clippy::map_entry)]
                 if !sample_map.lock().unwrap().contains_key(&key) {
                     let color = self.get_color_at_pixel(corner_px, corner_py);
                     rays_shot += 1;
@@ -0,0 +1,214 @@
+use std::f64::consts::PI;
+use crate::data_structures::{Vector, Vec3};
+use crate::{vec3};
+use crate::objects::{DirectionalLight, Environment, EnvironmentError};
+use crate::utils::deg_to_rad;
+
+// Sky luminance from the model is in kcd/m^2, which is scaled down so a clear midday zenith is around 0.5.
+const LUMINANCE_SCALE: f64 = 0.05;
+
+// Clear daylight sky using the Preetham model ("A Practical Analytic Model for Daylight", Preetham et al. 1999).
+// The sun is placed with its elevation above the horizon and its azimuth, where 0 is towards -Z and 90 is towards +X.
+// Turbidity is how hazy the air is: 2 is very clear, 3 a normal clear day and 10 hazy.
+// Below the horizon is a flat ground with the given albedo color (0-255), lit by the sky and the sun.
+pub struct Sky {
+    sun_elevation: f64, // In radians
+    sun_azimuth: f64, // In radians
+    turbidity: f64,
+    ground_albedo: Vec3,
+    intensity: f64,
+}
+
+// Coefficients A to E of the Perez sky distribution function.
+type Perez = [f64; 5];
+
+impl Sky {
+    // Angles are in degrees. The sun is kept above the horizon since the model only covers daylight.
+    pub fn new(sun_elevation: f64, sun_azimuth: f64, turbidity: f64, ground_albedo: Vec3) -> Self {
+        Sky {
+            sun_elevation: deg_to_rad(sun_elevation.clamp(0.0, 90.0)),
+            sun_azimuth: deg_to_rad(sun_azimuth),
+            turbidity: turbidity.max(1.0),
+            ground_albedo,
+            intensity: 1.0,
+        }
+    }
+
+    pub fn set_intensity(&mut self, intensity: f64) {
+        self.intensity = intensity;
+    }
+
+    // Unit direction pointing towards the sun.
+    pub fn get_sun_direction(&self) -> Vec3 {
+        vec3![
+            self.sun_elevation.cos() * self.sun_azimuth.sin(),
+            self.sun_elevation.sin(),
+            -self.sun_elevation.cos() * self.sun_azimuth.cos()
+        ]
+    }
+
+    // Directional light for the sun, tinted by how much of its light makes it through the atmosphere.
+    pub fn get_sun(&self, intensity: f64) -> DirectionalLight {
+        DirectionalLight::new(self.get_sun_transmittance() * 255.0, intensity / 255.0, self.get_sun_direction() * -1.0)
+    }
+
+    // Linear light from the sky in a direction above the horizon.
+    pub fn get_sky_radiance(&self, direction: Vec3) -> Vec3 {
+        let d = direction.unit();
+        let theta_sun = PI / 2.0 - self.sun_elevation;
+
+        // Angle from the zenith, kept just above the horizon where the model breaks down
+        let cos_theta = d.y.max(0.01);
+        let cos_gamma = d.dot(self.get_sun_direction()).clamp(-1.0, 1.0);
+        let gamma = cos_gamma.acos();
+
+        let t = self.turbidity;
+        let (zenith_y, zenith_x, zenith_yy) = self.zenith_values(theta_sun);
+        let luminance = zenith_y * perez_ratio(self.luminance_coefficients(), cos_theta, gamma, theta_sun);
+        let x = zenith_x * perez_ratio([
+            -0.0193 * t - 0.2592, -0.0665 * t + 0.0008, -0.0004 * t + 0.2125, -0.0641 * t - 0.8989, -0.0033 * t + 0.0452
+        ], cos_theta, gamma, theta_sun);
+        let y = zenith_yy * perez_ratio([
+            -0.0167 * t - 0.2608, -0.0950 * t + 0.0092, -0.0079 * t + 0.2102, -0.0441 * t - 1.6537, -0.0109 * t + 0.0529
+        ], cos_theta, gamma, theta_sun);
+
+        xyy_to_rgb(x, y, luminance * LUMINANCE_SCALE) * self.intensity
+    }
+
+    fn luminance_coefficients(&self) -> Perez {
+        let t = self.turbidity;
+        [0.1787 * t - 1.4630, -0.3554 * t + 0.4275, -0.0227 * t + 5.3251, 0.1206 * t - 2.5771, -0.0670 * t + 0.3703]
+    }
+
+    // Luminance and chromaticity at the zenith for the sun at theta_sun from the zenith.
+    fn zenith_values(&self, theta_sun: f64) -> (f64, f64, f64) {
+        let t = self.turbidity;
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_sun);
+        let luminance = ((4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192).max(0.0);
+
+        let th = theta_sun;
+        let (th2, th3) = (th * th, th * th * th);
+        let x = t * t * (0.00166 * th3 - 0.00375 * th2 + 0.00209 * th)
+            + t * (-0.02903 * th3 + 0.06377 * th2 - 0.03202 * th + 0.00394)
+            + (0.11693 * th3 - 0.21196 * th2 + 0.06052 * th + 0.25886);
+        let y = t * t * (0.00275 * th3 - 0.00610 * th2 + 0.00317 * th)
+            + t * (-0.04214 * th3 + 0.08970 * th2 - 0.04153 * th + 0.00516)
+            + (0.15346 * th3 - 0.26756 * th2 + 0.06670 * th + 0.26688);
+        (luminance, x, y)
+    }
+
+    // Fraction of sunlight making it through the atmosphere for red, green and blue,
+    // from Rayleigh scattering by air and Mie scattering by haze (appendix of the Preetham paper).
+    fn get_sun_transmittance(&self) -> Vec3 {
+        let theta_sun = PI / 2.0 - self.sun_elevation;
+        let theta_degrees = theta_sun.to_degrees();
+        let air_mass = 1.0 / (theta_sun.cos() + 0.15 * (93.885 - theta_degrees).max(0.01).powf(-1.253));
+        let beta = 0.04608 * self.turbidity - 0.04586; // Angstrom turbidity
+
+        // Wavelengths in micrometers
+        let transmittance = |wavelength: f64| {
+            let rayleigh = (-air_mass * 0.008735 * wavelength.powf(-4.08)).exp();
+            let haze = (-air_mass * beta * wavelength.powf(-1.3)).exp();
+            rayleigh * haze
+        };
+        vec3![transmittance(0.680), transmittance(0.550), transmittance(0.440)]
+    }
+
+    // Bakes the sky into an environment map, so it's used as the background and to light the scene.
+    // The ground reflects the light from the sky and sun that lands on it. Fails if the size is 0.
+    pub fn to_environment(&self, width: usize, height: usize, sun_intensity: f64) -> Result<Environment, EnvironmentError> {
+        let mut pixels = vec![vec3![0.0, 0.0, 0.0]; width * height];
+        let direction = |x: usize, y: usize| {
+            let phi = ((x as f64 + 0.5) / width as f64 - 0.5) * 2.0 * PI;
+            let theta = (y as f64 + 0.5) / height as f64 * PI;
+            vec3![theta.sin() * phi.sin(), theta.cos(), -theta.sin() * phi.cos()]
+        };
+
+        // Fill in the sky and add up the light it puts on the ground
+        let mut ground_irradiance = vec3![0.0, 0.0, 0.0];
+        for y in 0..height {
+            let theta = (y as f64 + 0.5) / height as f64 * PI;
+            if theta >= PI / 2.0 {
+                continue;
+            }
+            let solid_angle = (2.0 * PI / width as f64) * (PI / height as f64) * theta.sin();
+            for x in 0..width {
+                let radiance = self.get_sky_radiance(direction(x, y));
+                ground_irradiance += radiance * (theta.cos() * solid_angle);
+                pixels[y * width + x] = radiance;
+            }
+        }
+
+        // Sunlight is in the same units the environment lights in, where 1 lights like an ambient light of 1.
+        // The sky was added up as real irradiance, so it's divided by pi to match.
+        let sun = self.get_sun_transmittance() * (sun_intensity * self.sun_elevation.sin());
+        let ground = self.ground_albedo / 255.0 * (ground_irradiance / PI + sun);
+        for y in 0..height {
+            let theta = (y as f64 + 0.5) / height as f64 * PI;
+            if theta >= PI / 2.0 {
+                for x in 0..width {
+                    pixels[y * width + x] = ground;
+                }
+            }
+        }
+        Environment::new(pixels, width, height)
+    }
+}
+
+// How bright a direction is compared to the zenith, according to the Perez distribution.
+fn perez_ratio(c: Perez, cos_theta: f64, gamma: f64, theta_sun: f64) -> f64 {
+    let perez = |cos_theta: f64, gamma: f64| {
+        (1.0 + c[0] * (c[1] / cos_theta).exp()) * (1.0 + c[2] * (c[3] * gamma).exp() + c[4] * gamma.cos().powi(2))
+    };
+    perez(cos_theta, gamma) / perez(1.0, theta_sun)
+}
+
+// CIE xyY color to linear sRGB.
+fn xyy_to_rgb(x: f64, y: f64, luminance: f64) -> Vec3 {
+    if y <= 0.0 {
+        return vec3![0.0, 0.0, 0.0];
+    }
+    let cx = x / y * luminance;
+    let cz = (1.0 - x - y) / y * luminance;
+    vec3![
+        (3.2406 * cx - 1.5372 * luminance - 0.4986 * cz).max(0.0),
+        (-0.9689 * cx + 1.8758 * luminance + 0.0415 * cz).max(0.0),
+        (0.0557 * cx - 0.2040 * luminance + 1.0570 * cz).max(0.0)
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sky_and_sun_agree_on_the_sun_direction() {
+        let (width, height) = (128, 64);
+        for (elevation, azimuth) in [(30.0, 0.0), (45.0, 90.0), (60.0, 200.0), (20.0, -45.0)] {
+            let sky = Sky::new(elevation, azimuth, 3.0, vec3![50.0, 50.0, 50.0]);
+            let towards_sun = sky.get_sun_direction();
+
+            // The sun light shines away from the sun
+            let sun = sky.get_sun(1.0);
+            assert!(sun.get_direction().dot(towards_sun) < -1.0 + 1e-9);
+
+            // The brightest part of the baked sky is around the sun
+            let environment = sky.to_environment(width, height, 1.0).unwrap();
+            let mut brightest = (0.0, vec3![0.0, 0.0, 0.0]);
+            for y in 0..height {
+                for x in 0..width {
+                    let phi = ((x as f64 + 0.5) / width as f64 - 0.5) * 2.0 * PI;
+                    let theta = (y as f64 + 0.5) / height as f64 * PI;
+                    let direction = vec3![theta.sin() * phi.sin(), theta.cos(), -theta.sin() * phi.cos()];
+                    let color = environment.get_color(direction);
+                    let luminance = color.x + color.y + color.z;
+                    if luminance > brightest.0 {
+                        brightest = (luminance, direction);
+                    }
+                }
+            }
+            let angle = brightest.1.dot(towards_sun).clamp(-1.0, 1.0).acos();
+            assert!(angle < 0.1, "brightest pixel is {} radians from the sun at {}, {}", angle, elevation, azimuth);
+        }
+    }
+}
@@ -1,26 +1,49 @@
+use std::f64::consts::PI;
 use crate::data_structures::{Ray, Vec3};
 use crate::{HittableList, SceneObject, vec3, Vector};
 use crate::materials::Material;
-use crate::objects::BoundingVolume;
+use crate::objects::{BoundingVolume, Emitter, EmitterShape};
 use crate::traits::{HitData, Hittable};
+use crate::utils::{deg_to_rad, rotate_m};
 
 #[derive(Debug, Copy, Clone)]
 pub struct Sphere {
     position: Vec3,
-    scale: Vec3,
     radius: f64,
-    material: Material
+    original_radius: f64, // Radius before scaling, which texture coordinates in object space are measured against
+    axes: [Vec3; 3], // Where the sphere's own X, Y and Z axes point after rotating, used for texture coordinates
+    material: Material,
+    object_id: u32,
+    material_id: u32
 }
 
 impl Sphere {
     pub fn new(radius: f64, material: Material) -> Self {
         Sphere {
             radius,
+            original_radius: radius,
             material,
+            object_id: 0,
+            material_id: 0,
             position: vec3![0.0, 0.0, 0.0],
-            scale: vec3![1.0, 1.0, 1.0]
+            axes: [vec3![1.0, 0.0, 0.0], vec3![0.0, 1.0, 0.0], vec3![0.0, 0.0, 1.0]]
         }
     }
+
+    // Point relative to the center of the sphere, turned with the sphere and scaled back to its original size.
+    fn get_local_point(&self, point: Vec3) -> Vec3 {
+        let offset = point - self.position;
+        vec3![offset.dot(self.axes[0]), offset.dot(self.axes[1]), offset.dot(self.axes[2])] * (self.original_radius / self.radius)
+    }
+
+    // Texture coordinates from longitude and latitude. The middle of the texture faces the sphere's +Z axis
+    // and the top of the texture is at its +Y pole.
+    fn get_uv(&self, normal: Vec3) -> (f64, f64) {
+        let x = normal.dot(self.axes[0]);
+        let y = normal.dot(self.axes[1]).clamp(-1.0, 1.0);
+        let z = normal.dot(self.axes[2]);
+        (0.5 + x.atan2(z) / (2.0 * PI), 0.5 + y.asin() / PI)
+    }
 }
 
 impl SceneObject for Sphere {
@@ -28,10 +51,19 @@ impl SceneObject for Sphere {
         self.position
     }
 
+    fn get_material(&self) -> Material {
+        self.material
+    }
+
     fn set_material(&mut self, material: Material) {
         self.material = material;
     }
 
+    fn set_ids(&mut self, object_id: u32, material_id: u32) {
+        self.object_id = object_id;
+        self.material_id = material_id;
+    }
+
     fn translate(&mut self, translation: Vec3) {
         self.position += translation
     }
@@ -43,14 +75,29 @@ impl SceneObject for Sphere {
         }
     }
 
-    // Doesn't really make sense to rotate sphere. Unless there's a texture on it.
-    fn rotate(&mut self, _: Vec3) { }
+    // Rotating a sphere only turns its texture.
+    fn rotate(&mut self, rotation: Vec3) {
+        let rotate_by = rotate_m(vec3![deg_to_rad(rotation.x), deg_to_rad(rotation.y), deg_to_rad(rotation.z)]).transpose_square();
+        for axis in self.axes.iter_mut() {
+            *axis = (*axis * &rotate_by).unit();
+        }
+    }
 
     fn decompose(&self) -> HittableList {
         let mut list = HittableList::new();
-        list.push(Box::new(self.clone()));
+        list.push(Box::new(*self));
         list
     }
+
+    fn get_emitters(&self) -> Vec<Emitter> {
+        match self.material {
+            Material::Emissive(emissive) => vec![Emitter {
+                shape: EmitterShape::Sphere { center: self.position, radius: self.radius },
+                radiance: emissive.get_radiance()
+            }],
+            _ => vec![]
+        }
+    }
 }
 
 impl Hittable for Sphere {
@@ -76,17 +123,29 @@ impl Hittable for Sphere {
             if t0_hit || t1_hit {
                 let t = if t0_hit { t0 } else { t1 };
                 let hit = ray.get_point_at(t);
-                return HitData::from(
+                let normal = (hit - self.position).unit();
+                let mut hit_data = HitData::from(
                     t,
                     true,
                     ray,
                     hit,
-                    (hit - self.position).unit(),
+                    normal,
+                    self.get_uv(normal),
+                    self.get_local_point(hit),
                     self.material
                 );
+                (hit_data.object_id, hit_data.material_id) = (self.object_id, self.material_id);
+
+                // Tangent goes around the sphere's Y axis, the way u grows. It isn't defined at the poles.
+                let tangent = self.axes[0] * normal.dot(self.axes[2]) - self.axes[2] * normal.dot(self.axes[0]);
+                if tangent.length() > 1e-9 {
+                    hit_data.tangent = tangent.unit();
+                    hit_data.bitangent = normal.cross(hit_data.tangent);
+                }
+                return hit_data;
             }
         }
-        return HitData::new();
+        HitData::new()
     }
 
     fn get_bounding_vol(&self) -> BoundingVolume {
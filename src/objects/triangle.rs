@@ -1,22 +1,42 @@
 use crate::data_structures::Matrix4;
 use crate::{Hittable, HittableList, Material, Ray, SceneObject, Vec3, vec3, Vector};
-use crate::objects::BoundingVolume;
+use crate::objects::{BoundingVolume, Emitter, EmitterShape};
 use crate::traits::HitData;
+use crate::utils::orthonormal_basis;
 
 const PARALLEL_TOLERANCE: f64 = 1e-8;
 
 // Struct representing a single triangle to be used in a triangle mesh.
 pub struct Triangle {
     pub vertex_normals: Matrix4,
+    pub vertex_uvs: Matrix4, // Texture coordinates of each vertex, if the mesh has any
+    pub vertex_tangents: Matrix4, // Directions u grows in at each vertex, if the mesh has texture coordinates
+    pub tangent_handedness: f64, // 1 if v grows towards normal x tangent, -1 if the texture is mirrored
     surface_normal: Vec3,
     material: Material,
+    object_id: u32,
+    material_id: u32,
     vertices: Matrix4,
+    object_vertices: Matrix4, // Vertices as they were before any transformations
     smooth: bool
 }
 
 impl Triangle {
     pub fn new(surface_normal: Vec3, vertex_normals: Matrix4, vertices: Matrix4, material: Material, smooth: bool) -> Self {
-        Triangle { surface_normal, vertex_normals, vertices, material, smooth }
+        Triangle {
+            surface_normal, vertex_normals, material, smooth,
+            object_id: 0,
+            material_id: 0,
+            object_vertices: vertices.clone(),
+            vertices,
+            vertex_uvs: Matrix4::new(),
+            vertex_tangents: Matrix4::new(),
+            tangent_handedness: 1.0
+        }
+    }
+
+    pub fn get_vertices(&self) -> [Vec3; 3] {
+        [self.vertices[0].to_vec3(), self.vertices[1].to_vec3(), self.vertices[2].to_vec3()]
     }
 
     pub fn transform(&mut self, transformation: &Matrix4, transform_normals: bool) {
@@ -25,6 +45,9 @@ impl Triangle {
             let for_normals = transformation.inverse().transpose_square();
             self.vertex_normals = &self.vertex_normals * &for_normals;
             self.surface_normal *= &for_normals;
+
+            // Tangents lie along the surface so they transform like the vertices, but without moving
+            self.vertex_tangents = &self.vertex_tangents * transformation;
         }
     }
 }
@@ -41,10 +64,19 @@ impl SceneObject for Triangle {
         ]
     }
 
+    fn get_material(&self) -> Material {
+        self.material
+    }
+
     fn set_material(&mut self, material: Material) {
         self.material = material;
     }
 
+    fn set_ids(&mut self, object_id: u32, material_id: u32) {
+        self.object_id = object_id;
+        self.material_id = material_id;
+    }
+
     fn translate(&mut self, translation: Vec3) {
         let to_vec4 = translation.to_vec4(0.0);
         self.vertices[0] += to_vec4;
@@ -67,15 +99,28 @@ impl SceneObject for Triangle {
         list.push(Box::new(self.clone()));
         list
     }
+
+    fn get_emitters(&self) -> Vec<Emitter> {
+        match self.material {
+            Material::Emissive(emissive) => vec![Emitter { shape: EmitterShape::Triangle(self.get_vertices()), radiance: emissive.get_radiance() }],
+            _ => vec![]
+        }
+    }
 }
 
 impl Clone for Triangle {
     fn clone(&self) -> Self {
         Triangle {
             vertex_normals: self.vertex_normals.clone(),
+            vertex_uvs: self.vertex_uvs.clone(),
+            vertex_tangents: self.vertex_tangents.clone(),
+            tangent_handedness: self.tangent_handedness,
             surface_normal: self.surface_normal,
             material: self.material,
+            object_id: self.object_id,
+            material_id: self.material_id,
             vertices: self.vertices.clone(),
+            object_vertices: self.object_vertices.clone(),
             smooth: self.smooth
         }
     }
@@ -127,27 +172,47 @@ impl Hittable for Triangle {
         let det_g = edge1.dot(col3_x_col2_g);
         let g = det_g / det_a;
 
-        // Check if beta and gamma are inside tri
-        if b > 0.0 && g > 0.0 && b + g <= 1.0 {
+        // Check if beta and gamma are inside tri. Edges count as inside so rays can't slip between neighboring triangles.
+        if b >= 0.0 && g >= 0.0 && b + g <= 1.0 {
             // Only set t for the found hit to the computed if the hit point is indeed in the triangle
             found_hit.t = t;
             found_hit.hit_point = ray.get_point_at(t);
             found_hit.did_hit = true;
             found_hit.ray = ray;
             found_hit.mat = self.material;
+            (found_hit.object_id, found_hit.material_id) = (self.object_id, self.material_id);
+
+            // Get last barycentric coordinate
+            let a = 1.0 - b - g;
+
+            found_hit.local_point = (self.object_vertices[0] * a + self.object_vertices[1] * b + self.object_vertices[2] * g).to_vec3();
+
+            // Interpolate texture coordinates the same way as normals. Meshes without them get (0, 0) everywhere.
+            if !self.vertex_uvs.is_empty() {
+                let uv = self.vertex_uvs[0] * a + self.vertex_uvs[1] * b + self.vertex_uvs[2] * g;
+                found_hit.uv = (uv.x, uv.y);
+            }
 
             // Choose which normal to use: triangle surface normal or interpolated normal at the hit point
             if self.smooth {
-                // Get last barycentric coordinate
-                let a = 1.0 - b - g;
-
                 // Interpolate hit point normal with vertex normals and barycentric coordinates
                 found_hit.normal = (self.vertex_normals[2] * g + self.vertex_normals[0] * a + self.vertex_normals[1] * b).to_vec3();
             } else {
                 found_hit.normal = self.surface_normal;
             }
+            found_hit.geometric_normal = self.surface_normal;
+
+            // Keep the tangent along the surface after the normal was interpolated
+            let normal = found_hit.normal;
+            if self.vertex_tangents.is_empty() {
+                (found_hit.tangent, found_hit.bitangent) = orthonormal_basis(normal);
+            } else {
+                let tangent = (self.vertex_tangents[0] * a + self.vertex_tangents[1] * b + self.vertex_tangents[2] * g).to_vec3();
+                found_hit.tangent = (tangent - normal * normal.dot(tangent)).unit();
+                found_hit.bitangent = normal.cross(found_hit.tangent) * self.tangent_handedness;
+            }
         }
-        return found_hit;
+        found_hit
     }
 
     fn get_bounding_vol(&self) -> BoundingVolume {
@@ -1,11 +1,69 @@
-use crate::data_structures::{Matrix4, Ray, Vec3, Vec4, Vector};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use crate::data_structures::{Matrix4, Ray, Vec3, Vector};
 use crate::{HittableList, matrix4, SceneObject, vec3};
 use crate::traits::Hittable;
 use crate::materials::{Material, Flat};
-use crate::objects::{BoundingVolume, Triangle};
+use crate::objects::{BoundingVolume, Emitter, EmitterShape, Triangle};
 use crate::traits::HitData;
 use crate::utils::{deg_to_rad, rotate_m, translate_m};
 
+// Indices into the lists of a MeshData for each corner of a triangle.
+// Normals and uvs are only present if they were authored in the mesh file.
+#[derive(Debug, Copy, Clone)]
+pub struct MeshFace {
+    pub vertices: [usize; 3],
+    pub normals: Option<[usize; 3]>,
+    pub uvs: Option<[usize; 3]>
+}
+
+// Raw data used to build a TriangleMesh. Faces use 0-based indices into the other lists.
+#[derive(Debug, Clone, Default)]
+pub struct MeshData {
+    pub vertices: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+    pub uvs: Vec<Vec3>,
+    pub faces: Vec<MeshFace>
+}
+
+// A face of a MeshData refers to a vertex, normal or uv that doesn't exist.
+#[derive(Debug, Copy, Clone)]
+pub struct InvalidFaceError {
+    pub face: usize, // Index of the face in MeshData::faces
+    pub attribute: &'static str, // "vertex", "normal" or "uv"
+    pub index: usize, // The 0-based index that is out of range
+    pub count: usize // How many of the attribute there are
+}
+
+impl Display for InvalidFaceError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "face {} uses {} {} but there are only {}", self.face, self.attribute, self.index, self.count)
+    }
+}
+
+impl Error for InvalidFaceError {}
+
+impl MeshData {
+    // Make sure every face only refers to vertices, normals and uvs that exist.
+    pub fn validate(&self) -> Result<(), InvalidFaceError> {
+        for (face_i, face) in self.faces.iter().enumerate() {
+            let attributes = [
+                ("vertex", Some(face.vertices), self.vertices.len()),
+                ("normal", face.normals, self.normals.len()),
+                ("uv", face.uvs, self.uvs.len())
+            ];
+            for (attribute, indices, count) in attributes {
+                for index in indices.iter().flatten() {
+                    if *index >= count {
+                        return Err(InvalidFaceError { face: face_i, attribute, index: *index, count });
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
 pub struct TriangleMesh {
     pub smooth: bool,
     triangles: Vec<Triangle>,
@@ -16,25 +74,44 @@ pub struct TriangleMesh {
 }
 
 impl TriangleMesh {
-    pub fn new(vertices: Matrix4, faces: Vec<Vec3>, smooth: bool) -> Self {
+    // Create a mesh from vertices and faces made of 1-based vertex indices.
+    pub fn new(vertices: Matrix4, faces: Vec<Vec3>, smooth: bool) -> Result<Self, InvalidFaceError> {
+        // Indices below 1 wrap around so they are caught as out of range.
+        let to_index = |i: f64| (i as usize).wrapping_sub(1);
+        let data = MeshData {
+            vertices: vertices.iter().map(|v| v.to_vec3()).collect(),
+            faces: faces.iter().map(|f| MeshFace {
+                vertices: [to_index(f.x), to_index(f.y), to_index(f.z)],
+                normals: None,
+                uvs: None
+            }).collect(),
+            ..MeshData::default()
+        };
+        TriangleMesh::from_data(&data, smooth)
+    }
+
+    // Create a mesh from mesh data. Faces with authored normals always use them for smooth shading.
+    // Other faces use vertex normals averaged from the surrounding faces if smooth is set.
+    pub fn from_data(data: &MeshData, smooth: bool) -> Result<Self, InvalidFaceError> {
+        data.validate()?;
         let material = Material::Flat(Flat::new(vec3![0.5, 0.5, 0.5]));
+        let vertices = &data.vertices;
         let mut triangles = vec![];
         let mut vertex_normals = vec![vec3![0.0, 0.0, 0.0]; vertices.len()];  // Keep track of vert norms
-        let mut vertex_indices = vec![]; // Keep track of vertex indices for each triangle to later assign the correct vertex normals
         let mut vn_div_by = vec![0.0; vertices.len()];  // Keep track of number of surface norms making a vert norm to average it later
+        let mut vertex_tangents = vec![vec3![0.0, 0.0, 0.0]; vertices.len()];  // Summed up like the vertex normals
+        let mut handedness = vec![1.0; data.faces.len()];
 
         // Each face contains the indices of the associated vertices.
-        for face in faces.iter() {
+        for face in data.faces.iter() {
             // Get the indices of the vertices in the face
-            let v1_i = face.x as usize - 1;
-            let v2_i = face.y as usize - 1;
-            let v3_i = face.z as usize - 1;
+            let [v1_i, v2_i, v3_i] = face.vertices;
 
             // Construct a matrix out of the vertices for easier transformation
             let tri_verts = matrix4![
-                vertices[v1_i].to_vec(),
-                vertices[v2_i].to_vec(),
-                vertices[v3_i].to_vec()
+                vertices[v1_i].to_vec4(1.0).to_vec(),
+                vertices[v2_i].to_vec4(1.0).to_vec(),
+                vertices[v3_i].to_vec4(1.0).to_vec()
             ];
 
             // Get the surface normal of the triangle.
@@ -49,14 +126,32 @@ impl TriangleMesh {
             vn_div_by[v2_i] += 1.0;
             vn_div_by[v3_i] += 1.0;
 
-            vertex_indices.push(vec![v1_i, v2_i, v3_i]);
-            triangles.push(Triangle::new(
+            let mut tri = Triangle::new(
                 surface_normal.unit(),
                 Matrix4::new(),
                 tri_verts,
                 material,
-                smooth
-            ));
+                smooth || face.normals.is_some()
+            );
+            if let Some(uv_i) = face.uvs {
+                tri.vertex_uvs = Matrix4::from(uv_i.iter().map(|i| data.uvs[*i].to_vec4(0.0)).collect());
+
+                // The tangent is the direction along the triangle that u grows in, found from how the
+                // texture coordinates change along its edges.
+                let (uv1, uv2, uv3) = (data.uvs[uv_i[0]], data.uvs[uv_i[1]], data.uvs[uv_i[2]]);
+                let (du1, dv1, du2, dv2) = (uv2.x - uv1.x, uv2.y - uv1.y, uv3.x - uv1.x, uv3.y - uv1.y);
+                let det = du1 * dv2 - du2 * dv1;
+                if det.abs() > 1e-12 {
+                    let edge1 = vertices[v2_i] - vertices[v1_i];
+                    let edge2 = vertices[v3_i] - vertices[v1_i];
+                    let tangent = (edge1 * dv2 - edge2 * dv1) / det;
+                    vertex_tangents[v1_i] += tangent;
+                    vertex_tangents[v2_i] += tangent;
+                    vertex_tangents[v3_i] += tangent;
+                    handedness[triangles.len()] = det.signum();
+                }
+            }
+            triangles.push(tri);
         }
 
         // Make each vertex normal truly normal
@@ -64,23 +159,28 @@ impl TriangleMesh {
             *vn = (*vn / vn_div_by[i]).unit();
         }
 
-        // Assign vertex normals to triangles
-        for (i, tri) in triangles.iter_mut().enumerate() {
-            tri.vertex_normals = Matrix4::from(vec![
-                vertex_normals[vertex_indices[i][0]].to_vec4(1.0),
-                vertex_normals[vertex_indices[i][1]].to_vec4(1.0),
-                vertex_normals[vertex_indices[i][2]].to_vec4(1.0)
-            ]);
+        // Assign vertex normals to triangles. Prefer the normals from the mesh data if there are any.
+        // Triangles with texture coordinates also get tangents, unless they all added up to nothing.
+        for (i, (face, tri)) in data.faces.iter().zip(triangles.iter_mut()).enumerate() {
+            tri.vertex_normals = match face.normals {
+                Some(n_i) => Matrix4::from(n_i.iter().map(|i| data.normals[*i].unit().to_vec4(1.0)).collect()),
+                None => Matrix4::from(face.vertices.iter().map(|i| vertex_normals[*i].to_vec4(1.0)).collect())
+            };
+            let has_tangents = face.vertices.iter().all(|i| vertex_tangents[*i].length() > 1e-12);
+            if face.uvs.is_some() && has_tangents {
+                tri.vertex_tangents = Matrix4::from(face.vertices.iter().map(|i| vertex_tangents[*i].unit().to_vec4(0.0)).collect());
+                tri.tangent_handedness = handedness[i];
+            }
         }
 
-        TriangleMesh {
+        Ok(TriangleMesh {
             triangles,
             smooth,
             material,
             scale: vec3![1.0, 1.0, 1.0],
             position: vec3![0.0, 0.0, 0.0],
             rotation: vec3![0.0, 0.0, 0.0]
-        }
+        })
     }
 
     fn transform(&mut self, transformation_m: &Matrix4, transform_normals: bool) {
@@ -95,6 +195,10 @@ impl SceneObject for TriangleMesh {
         self.position
     }
 
+    fn get_material(&self) -> Material {
+        self.material
+    }
+
     fn set_material(&mut self, material: Material) {
         self.material = material;
         for tri in self.triangles.iter_mut() {
@@ -102,6 +206,13 @@ impl SceneObject for TriangleMesh {
         }
     }
 
+    // The triangles are the whole mesh, they're hit instead of it once the BVH is built
+    fn set_ids(&mut self, object_id: u32, material_id: u32) {
+        for tri in self.triangles.iter_mut() {
+            tri.set_ids(object_id, material_id);
+        }
+    }
+
     fn translate(&mut self, translation: Vec3) {
         self.position += translation;
         for tri in self.triangles.iter_mut() {
@@ -139,6 +250,16 @@ impl SceneObject for TriangleMesh {
         self.transform(&rotation_m, true);
     }
 
+    // Every triangle glows on its own, so lights of any shape can be modeled.
+    fn get_emitters(&self) -> Vec<Emitter> {
+        match self.material {
+            Material::Emissive(emissive) => self.triangles.iter()
+                .map(|tri| Emitter { shape: EmitterShape::Triangle(tri.get_vertices()), radiance: emissive.get_radiance() })
+                .collect(),
+            _ => vec![]
+        }
+    }
+
     // Return a hittable list containing all of the triangles of this mesh.
     fn decompose(&self) -> HittableList {
         let mut list = HittableList::new();
@@ -188,4 +309,76 @@ impl Hittable for TriangleMesh {
         }
         BoundingVolume::new(vol_min, vol_max)
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn face(vertices: [usize; 3]) -> MeshFace {
+        MeshFace { vertices, normals: None, uvs: None }
+    }
+
+    fn triangle_data() -> MeshData {
+        MeshData {
+            vertices: vec![vec3![0.0, 0.0, 0.0], vec3![1.0, 0.0, 0.0], vec3![0.0, 1.0, 0.0]],
+            normals: vec![vec3![0.0, 0.0, 1.0]],
+            uvs: vec![],
+            faces: vec![face([0, 1, 2])]
+        }
+    }
+
+    #[test]
+    fn validate_accepts_faces_in_range() {
+        assert!(triangle_data().validate().is_ok());
+        assert!(MeshData::default().validate().is_ok());
+    }
+
+    #[test]
+    fn validate_finds_out_of_range_indices() {
+        let mut data = triangle_data();
+        data.faces.push(face([0, 1, 2]));
+        data.faces.push(face([2, 3, 0]));
+        let error = data.validate().unwrap_err();
+        assert_eq!((error.face, error.attribute, error.index, error.count), (2, "vertex", 3, 3));
+
+        let mut data = triangle_data();
+        data.faces[0].normals = Some([0, 0, 1]);
+        let error = data.validate().unwrap_err();
+        assert_eq!((error.face, error.attribute, error.index, error.count), (0, "normal", 1, 1));
+
+        let mut data = triangle_data();
+        data.faces[0].uvs = Some([0, 0, 0]);
+        let error = data.validate().unwrap_err();
+        assert_eq!((error.attribute, error.index, error.count), ("uv", 0, 0));
+    }
+
+    #[test]
+    fn bad_faces_are_errors_not_panics() {
+        let mut data = triangle_data();
+        data.faces[0] = face([0, 1, usize::MAX]);
+        assert!(TriangleMesh::from_data(&data, true).is_err());
+
+        // Indices given to new start at 1, so 0 is out of range
+        let vertices = Matrix4::from(data.vertices.iter().map(|v| v.to_vec4(1.0)).collect());
+        assert!(TriangleMesh::new(vertices.clone(), vec![vec3![1.0, 2.0, 3.0]], false).is_ok());
+        assert!(TriangleMesh::new(vertices.clone(), vec![vec3![0.0, 1.0, 2.0]], false).is_err());
+        assert!(TriangleMesh::new(vertices, vec![vec3![1.0, 2.0, 4.0]], false).is_err());
+    }
+
+    #[test]
+    fn rays_along_shared_edges_hit_the_mesh() {
+        // A square split along its diagonal
+        let data = MeshData {
+            vertices: vec![vec3![0.0, 0.0, 0.0], vec3![1.0, 0.0, 0.0], vec3![1.0, 1.0, 0.0], vec3![0.0, 1.0, 0.0]],
+            normals: vec![],
+            uvs: vec![],
+            faces: vec![face([0, 1, 2]), face([0, 2, 3])]
+        };
+        let mesh = TriangleMesh::from_data(&data, false).unwrap();
+        for (x, y) in [(0.25, 0.25), (0.5, 0.5), (0.75, 0.75), (1.0, 1.0), (0.0, 0.0), (0.5, 0.0), (0.0, 0.5)] {
+            let hit = mesh.hit(Ray::new(vec3![x, y, 1.0], vec3![0.0, 0.0, -1.0]), 0.0, f64::INFINITY);
+            assert!(hit.did_hit, "ray at ({}, {}) slipped through the mesh", x, y);
+            assert!((hit.t - 1.0).abs() < 1e-9);
+        }
+    }
+}
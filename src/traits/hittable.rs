@@ -4,6 +4,7 @@ use std::slice::Iter;
 use crate::materials::{Flat, Material};
 use crate::objects::{BoundingVolume, SceneObject};
 use crate::{Ray, Vec3, vec3};
+use crate::utils::orthonormal_basis;
 
 
 // Trait used for things that can be hit by a ray
@@ -16,13 +17,27 @@ pub trait Hittable: Sync + Send {
 }
 
 // Represents data about a raycast hit.
+#[derive(Clone)]
 pub struct HitData {
     pub t: f64,  // The location along the ray where the object intersects
     pub did_hit: bool,  // If there was a hit
     pub ray: Ray,  // Ray that made the hit
     pub hit_point: Vec3,  // The point in space where the ray intersects with the object
-    pub normal: Vec3,  // Normal of the point at which the ray hits the object
-    pub mat: Material // The material of the object hit so we can render it appropriately.
+    pub normal: Vec3,  // Normal used for shading, which smooth shading and normal maps bend
+    pub geometric_normal: Vec3,  // The real normal of the surface, used to move rays off of it
+    pub tangent: Vec3,  // Direction along the surface that the u texture coordinate grows in
+    pub bitangent: Vec3,  // Direction along the surface that the v texture coordinate grows in
+    pub uv: (f64, f64),  // Texture coordinates of the hit point
+    pub local_point: Vec3,  // The hit point relative to the object, before it was moved, rotated or scaled
+    pub mat: Material, // The material of the object hit so we can render it appropriately.
+    pub object_id: u32,  // Which object was hit, numbered from 1 in the order they were added to the scene
+    pub material_id: u32  // Which of the scene's different materials was hit, numbered from 1
+}
+
+impl Default for HitData {
+    fn default() -> Self {
+        HitData::new()
+    }
 }
 
 impl HitData {
@@ -33,14 +48,24 @@ impl HitData {
             hit_point: vec3![0.0, 0.0, 0.0],
             ray: Ray::new(vec3![0.0, 0.0, 0.0], vec3![0.0, 0.0, 0.0]),
             normal: vec3![0.0, 0.0, 0.0],
+            geometric_normal: vec3![0.0, 0.0, 0.0],
+            tangent: vec3![0.0, 0.0, 0.0],
+            bitangent: vec3![0.0, 0.0, 0.0],
+            uv: (0.0, 0.0),
+            local_point: vec3![0.0, 0.0, 0.0],
             mat: Material::Flat(Flat::new(vec3![0.5, 0.5, 0.5])),
+            object_id: 0,
+            material_id: 0,
             did_hit: false
         }
     }
 
-    // Crete new hit data with known data.
-    pub fn from(t: f64, did_hit: bool, ray: Ray, hit_point: Vec3, normal: Vec3, mat: Material) -> Self {
-        HitData { t, did_hit, ray, hit_point, normal, mat }
+    // Crete new hit data with known data. The normal is also the geometric normal,
+    // and the tangents are any directions along the surface until they're set. The ids are 0 until they're set.
+    #[allow(clippy::too_many_arguments)]
+    pub fn from(t: f64, did_hit: bool, ray: Ray, hit_point: Vec3, normal: Vec3, uv: (f64, f64), local_point: Vec3, mat: Material) -> Self {
+        let (tangent, bitangent) = orthonormal_basis(normal);
+        HitData { t, did_hit, ray, hit_point, normal, geometric_normal: normal, tangent, bitangent, uv, local_point, mat, object_id: 0, material_id: 0 }
     }
 }
 
@@ -97,6 +122,12 @@ impl ops::Index<usize> for HittableList {
     }
 }
 
+impl Default for HittableList {
+    fn default() -> Self {
+        HittableList::new()
+    }
+}
+
 impl HittableList {
     pub fn new() -> Self {
         HittableList { data: vec![] }
@@ -106,6 +137,10 @@ impl HittableList {
         self.data.len()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
     pub fn push(&mut self, hittable: Box<dyn SceneObject>) {
         self.data.push(hittable);
     }
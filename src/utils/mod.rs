@@ -1,39 +1,9 @@
 use std::f64::consts::PI;
-use std::fs;
-use image::{ImageBuffer, Rgb, RgbImage};
-use crate::{matrix4, vec3, vec4};
-use crate::data_structures::{Matrix4, Vec4, Vec3};
-use crate::objects::TriangleMesh;
+use image::{ImageBuffer, ImageFormat, Rgb, Rgb32FImage, RgbImage};
+use crate::{matrix4, vec3};
+use crate::data_structures::{Matrix4, Vec3, Vector};
 
-pub fn load_smf_mesh(filename: &str, smooth: bool) -> TriangleMesh {
-    let text = fs::read_to_string(filename).expect("Error loading mesh.");
-    let split = text.split('\n');
-
-    let mut vertices = Matrix4::new();
-    let mut faces: Vec<Vec3> = vec![];
-    for s in split {
-        let line_vec: Vec<String> = s.split(' ').map(str::to_string).collect();
-        if line_vec.len() > 3 && line_vec[0].chars().nth(0) != Some('#') {
-            if line_vec[0] == "v" {
-                vertices.push(vec4![
-                line_vec[1].parse().unwrap(),
-                line_vec[2].parse().unwrap(),
-                line_vec[3].parse().unwrap(),
-                1.0
-            ]);
-            } else if line_vec[0] == "f" {
-                faces.push(vec3![
-                line_vec[1].parse().unwrap(),
-                line_vec[2].parse().unwrap(),
-                line_vec[3].parse().unwrap()
-            ])
-            }
-        }
-    }
-    TriangleMesh::new(vertices, faces, smooth)
-}
-
-pub fn save_png(pixels: &Vec<Vec3>, resolution: (u32, u32), filename: &str) {
+pub fn save_png(pixels: &[Vec3], resolution: (u32, u32), filename: &str) {
     let mut buffer: RgbImage = ImageBuffer::new(resolution.0, resolution.1);
     for (x, y, buf_pix) in buffer.enumerate_pixels_mut() {
         let computed_pix = pixels[(resolution.0 * y + x) as usize];
@@ -42,11 +12,29 @@ pub fn save_png(pixels: &Vec<Vec3>, resolution: (u32, u32), filename: &str) {
     buffer.save(filename).unwrap();
 }
 
+// Saves pixels as OpenEXR without clamping them or rounding them to whole numbers, for AOVs that aren't colors.
+pub fn save_exr(pixels: &[Vec3], resolution: (u32, u32), filename: &str) {
+    let mut buffer: Rgb32FImage = ImageBuffer::new(resolution.0, resolution.1);
+    for (x, y, buf_pix) in buffer.enumerate_pixels_mut() {
+        let computed_pix = pixels[(resolution.0 * y + x) as usize];
+        *buf_pix = Rgb([computed_pix.x as f32, computed_pix.y as f32, computed_pix.z as f32]);
+    }
+    buffer.save_with_format(filename, ImageFormat::OpenExr).unwrap();
+}
+
 pub fn deg_to_rad(d: f64) -> f64 {
     (d * PI) / 180.0
 }
 
-pub fn supersample(pixels: &Vec<Vec3>, resolution: (u32, u32), samples: u32) -> Vec<Vec3> {
+// Two unit vectors perpendicular to the given unit vector and each other.
+pub fn orthonormal_basis(n: Vec3) -> (Vec3, Vec3) {
+    // Cross with whichever axis is furthest from n so the result isn't close to 0
+    let axis = if n.x.abs() < 0.9 { vec3![1.0, 0.0, 0.0] } else { vec3![0.0, 1.0, 0.0] };
+    let tangent = n.cross(axis).unit();
+    (tangent, n.cross(tangent))
+}
+
+pub fn supersample(pixels: &[Vec3], resolution: (u32, u32), samples: u32) -> Vec<Vec3> {
     // Return empty pixels if samples isn't a square number or if resolution can fit
     let square = (samples as f64).sqrt();
     if square % 1.0 != 0.0 || resolution.0 as f64 % square != 0.0 || resolution.1 as f64 % square != 0.0 {
@@ -73,7 +61,7 @@ pub fn supersample(pixels: &Vec<Vec3>, resolution: (u32, u32), samples: u32) ->
             new_image.push(sampled_pixel / samples as f64);
         }
     }
-    return new_image;
+    new_image
 }
 
 // Get translation matrix, translating to a point
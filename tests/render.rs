@@ -0,0 +1,166 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use rust_raytracer::{load_scene, Light, Material, Ray, Scene, SceneObject, Vec3, vec3, Vector};
+use rust_raytracer::materials::{Flat, Hall};
+use rust_raytracer::objects::{MeshData, MeshFace, Plane, PointLight, RectangleLight, TriangleMesh};
+
+// The meshes scene1 uses aren't in the repository, so the scene is copied next to small cubes standing in for them.
+fn scene1_with_stand_in_meshes(name: &str) -> PathBuf {
+    let root = std::env::temp_dir().join(format!("rust-raytracer-{}-{}", name, std::process::id()));
+    fs::create_dir_all(root.join("scenes")).unwrap();
+    fs::create_dir_all(root.join("models")).unwrap();
+
+    let cube = "v -1 -1 -1\nv 1 -1 -1\nv 1 1 -1\nv -1 1 -1\nv -1 -1 1\nv 1 -1 1\nv 1 1 1\nv -1 1 1\n\
+                f 1 3 2\nf 1 4 3\nf 5 6 7\nf 5 7 8\nf 1 2 6\nf 1 6 5\nf 4 8 7\nf 4 7 3\nf 1 5 8\nf 1 8 4\nf 2 3 7\nf 2 7 6\n";
+    for model in ["box.smf", "teapot.smf", "frog.smf", "bound-bunny_1k.smf"] {
+        fs::write(root.join("models").join(model), cube).unwrap();
+    }
+    let scene_path = root.join("scenes").join("scene1.toml");
+    fs::copy(Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes/scene1.toml"), &scene_path).unwrap();
+    scene_path
+}
+
+fn pixel_at(pixels: &[Vec3], resolution: (u32, u32), x: u32, y: u32) -> Vec3 {
+    pixels[(resolution.0 * y + x) as usize]
+}
+
+fn assert_close(actual: Vec3, expected: [f64; 3]) {
+    for (a, e) in [actual.x, actual.y, actual.z].into_iter().zip(expected) {
+        assert!((a - e).abs() < 0.5, "expected {:?}, got {:?}", expected, actual);
+    }
+}
+
+#[test]
+fn renders_scene1_at_a_small_resolution() {
+    let mut scene = load_scene(scene1_with_stand_in_meshes("scene1")).unwrap();
+    scene.set_render_resolution((64, 64));
+    let output = scene.render_frame();
+
+    assert_eq!(output.get_resolution(), (64, 64));
+    let pixels = output.get_beauty();
+    assert_eq!(pixels.len(), 64 * 64);
+
+    // The top left corner looks over everything at the background
+    assert_close(pixel_at(pixels, (64, 64), 0, 0), [255.0, 229.0, 204.0]);
+    // The green Hall sphere in front, lit by both point lights
+    assert_close(pixel_at(pixels, (64, 64), 40, 47), [96.40, 148.46, 110.97]);
+}
+
+#[test]
+fn render_functions_agree_on_the_image() {
+    let scene_path = scene1_with_stand_in_meshes("threaded");
+    let mut scene = load_scene(&scene_path).unwrap();
+    scene.set_render_resolution((32, 24));
+    let single = scene.render_frame();
+
+    let mut scene = load_scene(&scene_path).unwrap();
+    scene.set_render_resolution((32, 24));
+    let threaded = scene.render_frame_threaded(4);
+
+    assert_eq!(threaded.get_beauty().len(), 32 * 24);
+    for (a, b) in single.get_beauty().iter().zip(threaded.get_beauty()) {
+        assert_close(*a, [b.x, b.y, b.z]);
+    }
+}
+
+#[test]
+fn adaptive_supersampling_reports_its_samples() {
+    let mut scene = load_scene(scene1_with_stand_in_meshes("adaptive")).unwrap();
+    scene.set_render_resolution((32, 24));
+    let (output, stats) = scene.render_supersample_frame_threaded(0.05, 4);
+
+    assert_eq!(output.get_beauty().len(), 32 * 24);
+    let heatmap = output.get("samples").unwrap();
+    // Every pixel takes at least its four corners, either shot or from the cache
+    let samples: f64 = heatmap.iter().map(|p| p.x).sum();
+    assert!(heatmap.iter().all(|p| p.x >= 4.0));
+    assert_eq!(samples as u64, stats.rays_shot + stats.cache_calls);
+    assert!(stats.rays_shot > 0 && stats.cache_calls > 0);
+}
+
+// A black floor lit by a light 4 units above it, with a board halfway between them that covers the given range of x.
+// The floor is black so only the light reaching it gives it color.
+fn floor_under_light(light: Light, board: Option<(f64, f64, Material)>) -> Scene {
+    let mut scene = Scene::new((16, 16), 100.0, vec3![0.0, 0.0, 0.0], 60.0, 0, 1, 1.0, true);
+    scene.push_light(light);
+
+    let black = Material::Flat(Flat::new(vec3![0.0, 0.0, 0.0]));
+    let mut objects: Vec<Box<dyn SceneObject>> = vec![Box::new(Plane::new(20.0, 20.0, vec3![0.0, 1.0, 0.0], black))];
+    if let Some((from, to, material)) = board {
+        // Facing down towards the floor. Planes are infinite, so the board is a mesh.
+        let data = MeshData {
+            vertices: vec![vec3![from, 2.0, -5.0], vec3![to, 2.0, -5.0], vec3![to, 2.0, 5.0], vec3![from, 2.0, 5.0]],
+            normals: vec![],
+            uvs: vec![],
+            faces: vec![
+                MeshFace { vertices: [0, 1, 2], normals: None, uvs: None },
+                MeshFace { vertices: [0, 2, 3], normals: None, uvs: None }
+            ]
+        };
+        let mut board = TriangleMesh::from_data(&data, false).unwrap();
+        board.set_material(material);
+        objects.push(Box::new(board));
+    }
+    scene.add_objects(objects);
+    scene
+}
+
+fn point_light() -> Light {
+    let mut light = PointLight::new(vec3![255.0, 255.0, 255.0], 16.0);
+    light.set_position(vec3![0.0, 4.0, 0.0]);
+    Light::PointLight(light)
+}
+
+fn square_light() -> Light {
+    let mut light = RectangleLight::new(vec3![255.0, 255.0, 255.0], 16.0, vec3![0.0, -1.0, 0.0], 2.0, 2.0, 16);
+    light.set_position(vec3![0.0, 4.0, 0.0]);
+    Light::RectangleLight(light)
+}
+
+fn opaque() -> Material {
+    Material::Flat(Flat::new(vec3![200.0, 200.0, 200.0]))
+}
+
+fn floor_color(scene: &Scene) -> Vec3 {
+    scene.get_color_from_ray(Ray::new(vec3![0.0, 1.0, 0.0], vec3![0.0, -1.0, 0.0]), 0)
+}
+
+#[test]
+fn occluded_lights_add_nothing() {
+    // 16 intensity from 4 units away
+    assert_eq!(floor_color(&floor_under_light(point_light(), None)), vec3![255.0, 255.0, 255.0]);
+    assert_eq!(floor_color(&floor_under_light(point_light(), Some((-5.0, 5.0, opaque())))), vec3![0.0, 0.0, 0.0]);
+    assert_eq!(floor_color(&floor_under_light(square_light(), Some((-5.0, 5.0, opaque())))), vec3![0.0, 0.0, 0.0]);
+
+    // Unless shadows are turned off
+    let mut scene = floor_under_light(point_light(), Some((-5.0, 5.0, opaque())));
+    scene.set_render_shadows(false);
+    assert_eq!(floor_color(&scene), vec3![255.0, 255.0, 255.0]);
+}
+
+#[test]
+fn transparent_occluders_tint_the_light() {
+    let glass = Hall::new(
+        vec3![0.0, 0.0, 0.0], vec3![0.0, 0.0, 0.0], vec3![255.0, 127.5, 51.0],
+        0.0, 0.0, 0.0, 0.5, 1.0, 1.0, 0.0
+    );
+    let tinted = floor_color(&floor_under_light(point_light(), Some((-5.0, 5.0, Material::Hall(glass)))));
+    // t_color / 255 * transmissive_factor of the light gets through
+    let expected = vec3![255.0 * 0.5, 255.0 * 0.25, 255.0 * 0.1];
+    assert!((tinted - expected).length() < 1e-9, "expected {:?}, got {:?}", expected, tinted);
+}
+
+#[test]
+fn half_occluded_area_light_casts_a_partial_shadow() {
+    let lit = floor_color(&floor_under_light(square_light(), None)).x;
+    let half = floor_color(&floor_under_light(square_light(), Some((-5.0, 0.0, opaque())))).x;
+    assert!(0.0 < half && half < lit, "{} should be between 0 and {}", half, lit);
+
+    // Samples are spread over a grid, so half of them are blocked and the rest light the floor about as much
+    assert!((half / lit - 0.5).abs() < 0.1, "{} should be about half of {}", half, lit);
+}
+
+#[test]
+fn missing_scene_file_is_an_error() {
+    assert!(load_scene(Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes/missing.toml")).is_err());
+}